@@ -0,0 +1,170 @@
+//! Times the stats over a large history: `cargo bench --bench stats`.
+//!
+//! Seeds `BENCH_GAMES` games (10 000 by default) into a temporary SQLite database, or into the
+//! Postgres database of `DATABASE_URL`, which must hold no games yet. On Postgres the SQL
+//! aggregation of `stats::jsonb` is timed against the scan of the payloads; the reads of the
+//! aggregate tables are timed on both.
+
+use bullseye_tracker_backend::config::{DatabaseConfig, DisplayNamePolicy};
+use bullseye_tracker_backend::model::parse_payload;
+use bullseye_tracker_backend::stats::{
+    aggregates, jsonb, players::player_stats, scan_overall_stats, scan_team_leaderboard,
+    teams::team_stats, StatsQuery,
+};
+use bullseye_tracker_backend::storage::{backend::storage, connect_database, store_game};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+const FIXTURE_GAME_ID: &str = "b67d1e69-e2f6-4dce-ad87-b852e9106cbd";
+const GUEST: &str = "692e0515735fa457d1fef2c5";
+const HOST: &str = "5d5afe4db4ec171bd432ac55";
+const COUNTRIES: [&str; 8] = ["fr", "de", "es", "it", "br", "jp", "us", "za"];
+
+fn env_number(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Median duration of `runs` calls of `f`.
+async fn time<F, Fut>(runs: usize, mut f: F) -> Duration
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut durations = Vec::new();
+    for _ in 0..runs {
+        let start = Instant::now();
+        f().await;
+        durations.push(start.elapsed());
+    }
+    durations.sort();
+    durations[durations.len() / 2]
+}
+
+#[tokio::main]
+async fn main() {
+    sqlx::any::install_default_drivers();
+    let games = env_number("BENCH_GAMES", 10_000);
+    let runs = env_number("BENCH_RUNS", 5);
+
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let url = match std::env::var("DATABASE_URL") {
+        Ok(url) if url.starts_with("postgres") => url,
+        _ => format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("bench.db").display()
+        ),
+    };
+    let config = DatabaseConfig {
+        url,
+        ..DatabaseConfig::default()
+    };
+    let pool = connect_database(&config).await.expect("bench database");
+
+    let existing: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM games")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(existing, 0, "the bench database must hold no games");
+
+    let fixture = std::fs::read_to_string(
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../extension/data_debug/ws_data_round_ended_last.json"),
+    )
+    .expect("recorded payload")
+    .lines()
+    .filter(|line| !line.trim_start().starts_with("//"))
+    .collect::<Vec<_>>()
+    .join("\n");
+
+    // 40 guests playing with the host, over a spread of countries
+    let seeding = Instant::now();
+    for i in 0..games {
+        let payload = fixture
+            .replace(FIXTURE_GAME_ID, &format!("bench-{}", i))
+            .replace(GUEST, &format!("{:024x}", i % 40))
+            .replace(
+                "\"countryCode\": \"fr\"",
+                &format!("\"countryCode\": \"{}\"", COUNTRIES[i % COUNTRIES.len()]),
+            );
+        store_game(
+            &pool,
+            &parse_payload(&payload).unwrap(),
+            DisplayNamePolicy::default(),
+        )
+        .await
+        .unwrap();
+    }
+    // As autovacuum would, so Postgres merges the pending GIN entries and has statistics
+    sqlx::query(storage(&pool).vacuum_statement())
+        .execute(&pool)
+        .await
+        .unwrap();
+    println!(
+        "{}: seeded {} games in {:.1?}",
+        storage(&pool).name(),
+        games,
+        seeding.elapsed()
+    );
+
+    let params = StatsQuery {
+        exclude_abandons: Some(true),
+        map: None,
+        score_type: None,
+        season: None,
+        include_excluded: None,
+    };
+
+    let scan = time(runs, || async {
+        scan_overall_stats(&pool, &params, None).await;
+    })
+    .await;
+    println!("overall stats, scan:       {:>10.1?}", scan);
+    if storage(&pool).supports_jsonb() {
+        let sql = time(runs, || async {
+            jsonb::overall_stats(&pool, &params, None).await.unwrap();
+        })
+        .await;
+        println!("overall stats, jsonb:      {:>10.1?}", sql);
+    }
+    let read = time(runs, || async {
+        aggregates::overall_stats(&pool, &params).await.unwrap();
+    })
+    .await;
+    println!("overall stats, aggregates: {:>10.1?}", read);
+
+    let scan = time(runs, || async {
+        scan_team_leaderboard(&pool, &params, None).await;
+    })
+    .await;
+    println!("team leaderboard, scan:    {:>10.1?}", scan);
+    if storage(&pool).supports_jsonb() {
+        let sql = time(runs, || async {
+            jsonb::team_leaderboard(&pool, &params, None).await.unwrap();
+        })
+        .await;
+        println!("team leaderboard, jsonb:   {:>10.1?}", sql);
+    }
+    let read = time(runs, || async {
+        aggregates::team_leaderboard(&pool, &params).await.unwrap();
+    })
+    .await;
+    println!("team leaderboard, aggregates: {:>7.1?}", read);
+
+    // Filtered through the players index on Postgres, a full scan on SQLite
+    let guest = format!("{:024x}", 1);
+    let player = time(runs, || async {
+        player_stats(&pool, guest.clone(), &params).await.unwrap();
+    })
+    .await;
+    println!("player stats:              {:>10.1?}", player);
+    let team = time(runs, || async {
+        team_stats(&pool, format!("{},{}", HOST, guest), &params)
+            .await
+            .unwrap();
+    })
+    .await;
+    println!("team stats:                {:>10.1?}", team);
+}
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS players (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    last_seen TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS seasons (
+    id BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    starts_at TEXT NOT NULL,
+    ends_at TEXT NOT NULL,
+    closed_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS season_standings (
+    season_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    standing_rank BIGINT NOT NULL,
+    standing_key TEXT NOT NULL,
+    name TEXT NOT NULL,
+    members TEXT NOT NULL,
+    games_played BIGINT NOT NULL,
+    total_score BIGINT NOT NULL,
+    average_score DOUBLE PRECISION NOT NULL,
+    total_duration BIGINT NOT NULL,
+    PRIMARY KEY (season_id, kind, standing_key),
+    FOREIGN KEY (season_id) REFERENCES seasons(id)
+);
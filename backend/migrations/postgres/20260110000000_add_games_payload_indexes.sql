@@ -0,0 +1,27 @@
+-- Containment lookups on the players and the round countries of the JSONB payloads
+CREATE INDEX IF NOT EXISTS idx_games_players
+    ON games USING GIN ((data->'bullseye'->'state'->'players') jsonb_path_ops);
+CREATE INDEX IF NOT EXISTS idx_games_rounds
+    ON games USING GIN ((data->'bullseye'->'state'->'rounds') jsonb_path_ops);
+
+-- Real date of a game, as in the Rust stats: start of round 1 (or of the first round) when
+-- known, otherwise the stored played_at. Unreadable dates give NULL instead of an error.
+CREATE OR REPLACE FUNCTION bullseye_played_at(state JSONB, stored TIMESTAMPTZ)
+RETURNS TIMESTAMPTZ AS $$
+DECLARE
+    rounds JSONB := CASE WHEN jsonb_typeof(state->'rounds') = 'array' THEN state->'rounds' ELSE '[]' END;
+    first_round JSONB;
+BEGIN
+    SELECT r INTO first_round
+    FROM jsonb_array_elements(rounds) WITH ORDINALITY AS e(r, position)
+    ORDER BY (r->'roundNumber' = '1') DESC, position
+    LIMIT 1;
+
+    IF first_round->>'startTime' IS NULL THEN
+        RETURN stored;
+    END IF;
+    RETURN CAST(first_round->>'startTime' AS TIMESTAMPTZ);
+EXCEPTION WHEN OTHERS THEN
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql STABLE;
@@ -0,0 +1,45 @@
+-- Running totals of the games outside the trash, split by whether the game was finished and
+-- whether it is excluded from stats so both filters can still be applied.
+-- Games without a map name (or without a readable payload) are counted under ''.
+CREATE TABLE IF NOT EXISTS stats_maps (
+    map_name TEXT NOT NULL,
+    finished BIGINT NOT NULL,
+    excluded BIGINT NOT NULL,
+    games BIGINT NOT NULL,
+    scored_games BIGINT NOT NULL,
+    score_sum BIGINT NOT NULL,
+    duration_sum BIGINT NOT NULL,
+    PRIMARY KEY (map_name, finished, excluded)
+);
+
+CREATE TABLE IF NOT EXISTS stats_countries (
+    map_name TEXT NOT NULL,
+    country_code TEXT NOT NULL,
+    finished BIGINT NOT NULL,
+    excluded BIGINT NOT NULL,
+    rounds BIGINT NOT NULL,
+    points BIGINT NOT NULL,
+    PRIMARY KEY (map_name, country_code, finished, excluded)
+);
+
+-- Teams and players are keyed by primary player ids, so alias changes rebuild the tables
+CREATE TABLE IF NOT EXISTS stats_teams (
+    team_key TEXT NOT NULL,
+    finished BIGINT NOT NULL,
+    excluded BIGINT NOT NULL,
+    games BIGINT NOT NULL,
+    score_sum BIGINT NOT NULL,
+    duration_sum BIGINT NOT NULL,
+    PRIMARY KEY (team_key, finished, excluded)
+);
+
+CREATE TABLE IF NOT EXISTS stats_players (
+    player_id TEXT NOT NULL,
+    finished BIGINT NOT NULL,
+    excluded BIGINT NOT NULL,
+    games BIGINT NOT NULL,
+    personal_score_sum BIGINT NOT NULL,
+    game_score_sum BIGINT NOT NULL,
+    duration_sum BIGINT NOT NULL,
+    PRIMARY KEY (player_id, finished, excluded)
+);
@@ -0,0 +1,14 @@
+-- Every nick a player id was seen with. `first_seen` and `last_seen` are the RFC 3339 UTC dates
+-- of the games, so they compare as text; `source_game_id` is the game of the first sighting.
+CREATE TABLE IF NOT EXISTS player_names (
+    player_id TEXT NOT NULL REFERENCES players(id),
+    nick TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    games BIGINT NOT NULL,
+    source_game_id BIGINT,
+    PRIMARY KEY (player_id, nick)
+);
+
+-- Name chosen by an admin, used by the `pinned` display name policy
+ALTER TABLE players ADD COLUMN pinned_name TEXT;
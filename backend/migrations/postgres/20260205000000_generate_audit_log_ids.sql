@@ -0,0 +1,5 @@
+-- Ids were taken as MAX(id) + 1, which two concurrent writers can both read: let the
+-- database hand them out instead.
+ALTER TABLE audit_log ALTER COLUMN id ADD GENERATED BY DEFAULT AS IDENTITY;
+
+SELECT setval(pg_get_serial_sequence('audit_log', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM audit_log;
@@ -0,0 +1,12 @@
+-- A game sent twice at once could be stored twice: the later copies go to the trash, so
+-- that a game_id is only stored once outside of it from now on
+UPDATE games SET deleted_at = to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"')
+WHERE deleted_at IS NULL
+  AND game_id IS NOT NULL
+  AND id > (SELECT MIN(kept.id) FROM games kept
+            WHERE kept.game_id = games.game_id AND kept.deleted_at IS NULL);
+
+-- Emptied aggregates are rebuilt on startup, without the trashed copies
+DELETE FROM stats_maps;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_games_live_game_id ON games (game_id) WHERE deleted_at IS NULL;
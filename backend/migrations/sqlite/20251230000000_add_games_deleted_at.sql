@@ -0,0 +1,3 @@
+ALTER TABLE games ADD COLUMN deleted_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_games_deleted_at ON games (deleted_at);
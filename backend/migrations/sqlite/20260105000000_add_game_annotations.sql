@@ -0,0 +1,11 @@
+ALTER TABLE games ADD COLUMN excluded INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE games ADD COLUMN excluded_reason TEXT;
+ALTER TABLE games ADD COLUMN notes TEXT;
+
+CREATE TABLE IF NOT EXISTS game_tags (
+    game_id INTEGER NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (game_id, tag)
+);
+
+CREATE INDEX IF NOT EXISTS idx_game_tags_tag ON game_tags (tag);
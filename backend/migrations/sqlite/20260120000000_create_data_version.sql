@@ -0,0 +1,10 @@
+-- Version of the data behind the read endpoints, bumped by every write to it; the ETag and
+-- Last-Modified of their responses derive from it. A single row, shared by every replica.
+CREATE TABLE IF NOT EXISTS data_version (
+    id INTEGER PRIMARY KEY,
+    version BIGINT NOT NULL,
+    -- Unix time of the last bump
+    modified_at BIGINT NOT NULL
+);
+
+INSERT INTO data_version (id, version, modified_at) VALUES (1, 1, CAST(strftime('%s', 'now') AS INTEGER));
@@ -0,0 +1,9 @@
+-- Suggested alias pairs an admin rejected, so they are not suggested again. `player_a` is the
+-- smaller of the two player ids.
+CREATE TABLE IF NOT EXISTS alias_rejections (
+    player_a TEXT NOT NULL,
+    player_b TEXT NOT NULL,
+    rejected_at TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    PRIMARY KEY (player_a, player_b)
+);
@@ -0,0 +1,2 @@
+-- The games.id repair used to drop the indexes of `games` with the old table
+CREATE INDEX IF NOT EXISTS idx_games_deleted_at ON games (deleted_at);
@@ -0,0 +1,22 @@
+-- Ids were taken as MAX(id) + 1, which two concurrent writers can both read: let the
+-- database hand them out instead. SQLite cannot change a primary key in place.
+CREATE TABLE audit_log_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    before_value TEXT,
+    after_value TEXT,
+    undone_at TEXT
+);
+
+INSERT INTO audit_log_new (id, created_at, actor, action, target_type, target_id, before_value, after_value, undone_at)
+SELECT id, created_at, actor, action, target_type, target_id, before_value, after_value, undone_at
+FROM audit_log;
+
+DROP TABLE audit_log;
+ALTER TABLE audit_log_new RENAME TO audit_log;
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_target ON audit_log (target_type, target_id);
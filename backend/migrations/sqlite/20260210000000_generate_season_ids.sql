@@ -0,0 +1,38 @@
+-- Ids were taken as MAX(id) + 1, which two concurrent writers can both read: let the
+-- database hand them out instead. SQLite cannot change a primary key in place, and the
+-- standings are set aside meanwhile so that dropping the seasons breaks no reference.
+CREATE TABLE seasons_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    starts_at TEXT NOT NULL,
+    ends_at TEXT NOT NULL,
+    closed_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+INSERT INTO seasons_new (id, name, starts_at, ends_at, closed_at, created_at)
+SELECT id, name, starts_at, ends_at, closed_at, created_at FROM seasons;
+
+CREATE TEMPORARY TABLE season_standings_saved AS SELECT * FROM season_standings;
+
+DROP TABLE season_standings;
+DROP TABLE seasons;
+ALTER TABLE seasons_new RENAME TO seasons;
+
+CREATE TABLE season_standings (
+    season_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    standing_rank BIGINT NOT NULL,
+    standing_key TEXT NOT NULL,
+    name TEXT NOT NULL,
+    members TEXT NOT NULL,
+    games_played BIGINT NOT NULL,
+    total_score BIGINT NOT NULL,
+    average_score DOUBLE PRECISION NOT NULL,
+    total_duration BIGINT NOT NULL,
+    PRIMARY KEY (season_id, kind, standing_key),
+    FOREIGN KEY (season_id) REFERENCES seasons(id)
+);
+
+INSERT INTO season_standings SELECT * FROM season_standings_saved;
+DROP TABLE season_standings_saved;
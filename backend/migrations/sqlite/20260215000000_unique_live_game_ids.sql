@@ -0,0 +1,13 @@
+-- A game sent twice at once could be stored twice: the later copies go to the trash, so
+-- that a game_id is only stored once outside of it from now on. Databases created before
+-- the games.id repair still have NULL ids here, which is why the copies are told apart by rowid
+UPDATE games SET deleted_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+WHERE deleted_at IS NULL
+  AND game_id IS NOT NULL
+  AND rowid > (SELECT MIN(kept.rowid) FROM games kept
+               WHERE kept.game_id = games.game_id AND kept.deleted_at IS NULL);
+
+-- Emptied aggregates are rebuilt on startup, without the trashed copies
+DELETE FROM stats_maps;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_games_live_game_id ON games (game_id) WHERE deleted_at IS NULL;
@@ -0,0 +1,9 @@
+-- Two concurrent closes of a season could both store standings: keep one row per rank
+DELETE FROM season_standings
+WHERE EXISTS (SELECT 1 FROM season_standings kept
+              WHERE kept.season_id = season_standings.season_id
+                AND kept.kind = season_standings.kind
+                AND kept.standing_rank = season_standings.standing_rank
+                AND kept.standing_key < season_standings.standing_key);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_season_standings_rank ON season_standings (season_id, kind, standing_rank);
@@ -0,0 +1,195 @@
+//! HTTP caching of the read endpoints.
+//!
+//! Their responses only change when the data does, so they carry an ETag and a Last-Modified
+//! taken from the data version. Requests whose `If-None-Match` still matches get a 304, and
+//! recent responses are kept in memory until the next write, except the streamed lists.
+
+use crate::{
+    config::CacheConfig,
+    storage::version::{data_version, DataVersion},
+};
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use sqlx::AnyPool;
+use std::{collections::HashMap, sync::Arc};
+use tracing::error;
+
+/// Responses of one data version, keyed by path and sorted query parameters.
+pub struct ResponseCache {
+    pub pool: AnyPool,
+    pub max_entries: usize,
+    pub entries: std::sync::Mutex<CachedVersion>,
+}
+
+#[derive(Default)]
+pub struct CachedVersion {
+    pub version: i64,
+    pub responses: HashMap<String, CachedResponse>,
+}
+
+#[derive(Clone)]
+pub struct CachedResponse {
+    pub content_type: Option<HeaderValue>,
+    pub body: Bytes,
+}
+
+impl ResponseCache {
+    pub fn new(pool: AnyPool, config: &CacheConfig) -> Self {
+        ResponseCache {
+            pool,
+            max_entries: config.max_entries,
+            entries: std::sync::Mutex::new(CachedVersion::default()),
+        }
+    }
+
+    fn get(&self, version: i64, key: &str) -> Option<CachedResponse> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.version != version {
+            return None;
+        }
+        entries.responses.get(key).cloned()
+    }
+
+    fn insert(&self, version: i64, key: String, response: CachedResponse) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.version != version {
+            // A response computed before a write may finish after one: never go back
+            if entries.version > version {
+                return;
+            }
+            entries.version = version;
+            entries.responses.clear();
+        }
+        if entries.responses.len() < self.max_entries {
+            entries.responses.insert(key, response);
+        }
+    }
+}
+
+/// Path and query of the request, the query parameters sorted.
+fn cache_key(request: &Request) -> String {
+    let uri = request.uri();
+    let mut params: Vec<&str> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .collect();
+    params.sort_unstable();
+    format!("{}?{}", uri.path(), params.join("&"))
+}
+
+fn etag(version: &DataVersion) -> String {
+    // Weak: the same data may be sent compressed or not
+    format!("W/\"{}\"", version.version)
+}
+
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Whether the client already holds this version, from `If-None-Match`. `If-Modified-Since` is
+/// left alone: it counts whole seconds, and a write in the same second would go unnoticed.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| {
+            // Weak comparison, as for GET
+            tags.split(',').map(str::trim).any(|tag| {
+                tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/")
+            })
+        })
+}
+
+fn not_modified(version: &DataVersion, etag: &str) -> Response {
+    metrics::counter!("bullseye_response_cache_total", "outcome" => "not_modified").increment(1);
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    add_validators(response.headers_mut(), version, etag);
+    response
+}
+
+fn add_validators(headers: &mut HeaderMap, version: &DataVersion, etag: &str) {
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&http_date(version.modified_at)) {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+    // Stored by browsers, but checked with the server before each use
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+}
+
+/// Answers GET requests of the read endpoints from the data version: the cached response when
+/// there is one, the handler otherwise, and 304 instead of either when the client is up to
+/// date. Only successful responses are ever answered with a 304.
+pub async fn http_cache(
+    State(cache): State<Arc<ResponseCache>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let version = match data_version(&cache.pool).await {
+        Ok(version) => version,
+        Err(e) => {
+            error!("Failed to read the data version: {}", e);
+            return next.run(request).await;
+        }
+    };
+    let etag = etag(&version);
+    let up_to_date = etag_matches(request.headers(), &etag);
+
+    let key = cache_key(&request);
+    if let Some(cached) = cache.get(version.version, &key) {
+        if up_to_date {
+            return not_modified(&version, &etag);
+        }
+        metrics::counter!("bullseye_response_cache_total", "outcome" => "hit").increment(1);
+        let mut response = Response::new(Body::from(cached.body));
+        if let Some(content_type) = cached.content_type {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, content_type);
+        }
+        add_validators(response.headers_mut(), &version, &etag);
+        return response;
+    }
+
+    // The handler says whether the resource exists: an unknown season stays a 404
+    let response = next.run(request).await;
+    if up_to_date && response.status().is_success() {
+        return not_modified(&version, &etag);
+    }
+    metrics::counter!("bullseye_response_cache_total", "outcome" => "miss").increment(1);
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    add_validators(&mut parts.headers, &version, &etag);
+    // Streamed lists are sent as they are produced, never buffered here
+    if cache.max_entries == 0 || body.size_hint().exact().is_none() {
+        return Response::from_parts(parts, body);
+    }
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to read the response body: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    cache.insert(
+        version.version,
+        key,
+        CachedResponse {
+            content_type: parts.headers.get(header::CONTENT_TYPE).cloned(),
+            body: body.clone(),
+        },
+    );
+    Response::from_parts(parts, Body::from(body))
+}
@@ -0,0 +1,170 @@
+//! One-off admin commands run from the command line.
+
+use crate::{
+    config::Config,
+    export::{run_export, ExportFormat, ExportKind},
+    import::{import_games, import_items},
+    stats::{seasons::fetch_season, StatsQuery},
+    storage::{
+        backend::storage,
+        backup::{audit_restore, create_backup, restore_backup, BackupArchive},
+    },
+};
+use sqlx::AnyPool;
+use tracing::error;
+
+/// `vacuum`: compacts the database file (SQLite) or the tables (Postgres).
+pub async fn run_vacuum(pool: &AnyPool) -> i32 {
+    let statement = storage(pool).vacuum_statement();
+
+    match sqlx::query(statement).execute(pool).await {
+        Ok(_) => {
+            println!("{} done", statement);
+            0
+        }
+        Err(e) => {
+            error!("{} failed: {}", statement, e);
+            1
+        }
+    }
+}
+
+/// `export`: the same streams as `/api/export/*`, written to a file or stdout.
+pub async fn run_export_command(
+    pool: AnyPool,
+    kind: ExportKind,
+    format: ExportFormat,
+    filters: StatsQuery,
+    output: Option<&str>,
+) -> i32 {
+    use std::io::Write;
+
+    let window = match filters.season {
+        Some(id) => match fetch_season(&pool, id).await {
+            Ok(Some(season)) => season.window(),
+            Ok(None) => {
+                error!("Season {} not found", id);
+                return 1;
+            }
+            Err(_) => return 1,
+        },
+        None => None,
+    };
+
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => match std::fs::File::create(path) {
+            Ok(file) => Box::new(std::io::BufWriter::new(file)),
+            Err(e) => {
+                error!("Failed to create {}: {}", path, e);
+                return 1;
+            }
+        },
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(16);
+    let export = tokio::spawn(run_export(pool, kind, format, filters, window, tx));
+
+    while let Some(chunk) = rx.recv().await {
+        if let Err(e) = writer.write_all(&chunk) {
+            error!("Failed to write export: {}", e);
+            return 1;
+        }
+    }
+    if let Err(e) = writer.flush() {
+        error!("Failed to write export: {}", e);
+        return 1;
+    }
+
+    match export.await {
+        Ok(Ok(())) => 0,
+        Ok(Err(e)) => {
+            error!("Export failed: {}", e);
+            1
+        }
+        Err(e) => {
+            error!("Export failed: {}", e);
+            1
+        }
+    }
+}
+
+/// CLI `import <file>`: prints the report and returns the process exit code.
+pub async fn run_import_file(pool: &AnyPool, config: &Config, path: &str) -> i32 {
+    let content = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Failed to read {}: {}", path, e);
+            return 1;
+        }
+    };
+    let Some(items) = serde_json::from_str(&content).ok().and_then(import_items) else {
+        error!("{} is not a JSON array of games", path);
+        return 1;
+    };
+
+    let report = import_games(pool, config, items).await;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).unwrap_or_default()
+    );
+    println!(
+        "Imported {}, skipped {}, failed {}",
+        report.imported, report.skipped, report.failed
+    );
+
+    if report.failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// CLI `backup <file>` and `restore <file>`: returns the process exit code.
+pub async fn run_backup_command(pool: &AnyPool, command: &str, path: &str) -> i32 {
+    if command == "backup" {
+        let archive = match create_backup(pool).await {
+            Ok(a) => a,
+            Err(e) => {
+                error!("Backup failed: {}", e);
+                return 1;
+            }
+        };
+        let written = serde_json::to_vec(&archive)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| std::fs::write(path, bytes).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!("Failed to write {}: {}", path, e);
+            return 1;
+        }
+        for (table, rows) in &archive.tables {
+            println!("{}: {} rows", table, rows.len());
+        }
+        println!("Backup written to {}", path);
+        return 0;
+    }
+
+    let archive: BackupArchive = match std::fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()))
+    {
+        Ok(a) => a,
+        Err(e) => {
+            error!("Failed to read backup {}: {}", path, e);
+            return 1;
+        }
+    };
+    match restore_backup(pool, &archive).await {
+        Ok(report) => {
+            audit_restore(pool, "cli", &archive, &report).await;
+            for (table, rows) in &report.tables {
+                println!("{}: {} rows restored", table, rows);
+            }
+            0
+        }
+        Err(e) => {
+            error!("Restore failed: {}", e);
+            1
+        }
+    }
+}
@@ -0,0 +1,508 @@
+//! Service configuration: `bullseye.toml` overlaid with `BULLSEYE_*` environment variables.
+
+use axum::http::{header, Method};
+use serde::Deserialize;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// Settings read from the TOML config file, then overridden by environment variables.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub cors: CorsConfig,
+    pub auth: AuthConfig,
+    pub log: LogConfig,
+    pub limits: LimitsConfig,
+    pub validation: ValidationConfig,
+    pub trash: TrashConfig,
+    pub cache: CacheConfig,
+    pub players: PlayersConfig,
+    pub features: FeatureConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub port: u16,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: "0.0.0.0".to_string(),
+            port: 3000,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    /// How long a request waits for a free connection
+    pub acquire_timeout_secs: u64,
+    /// Idle connections are closed after this delay; 0 keeps them open
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: "sqlite://bullseye.db?mode=rwc".to_string(),
+            max_connections: 5,
+            acquire_timeout_secs: 30,
+            idle_timeout_secs: 600,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API; `["*"]` allows any origin
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Shared Bearer token accepted on submissions, in addition to the stored API tokens
+    pub api_key: Option<String>,
+    /// Reject submissions even when no key or token is configured
+    pub require_token: bool,
+    /// Secret the proxy sends in `X-Bullseye-Proxy-Secret` along with the admin user in
+    /// `X-Webauth-User`; without it the user header is ignored
+    pub proxy_secret: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// `tracing` filter directive, e.g. `info` or `info,sqlx=warn`
+    pub level: String,
+    /// Replace player identifiers by a short hash in the logs
+    pub redact: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::Text,
+            level: "info".to_string(),
+            redact: true,
+        }
+    }
+}
+
+/// Protection of the submission routes against a misbehaving client.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Largest accepted `/api/submit-game` body, in bytes
+    pub max_body_bytes: usize,
+    pub max_rounds: usize,
+    pub max_players: usize,
+    pub max_guesses_per_player: usize,
+    /// Steady submission rate per client IP; 0 disables the limit
+    pub submissions_per_minute_per_ip: u32,
+    /// Steady submission rate per API token; 0 disables the limit
+    pub submissions_per_minute_per_token: u32,
+    /// Submissions allowed back to back before the rate applies
+    pub burst: u32,
+    /// Take the client IP from `X-Forwarded-For` (only behind a trusted proxy)
+    pub trust_forwarded_for: bool,
+    /// Trusted proxies appending to `X-Forwarded-For`: the client IP is the entry this many
+    /// places from the right, since the ones before it are sent by the client
+    pub forwarded_hops: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_body_bytes: 2 * 1024 * 1024,
+            max_rounds: 50,
+            max_players: 50,
+            max_guesses_per_player: 500,
+            submissions_per_minute_per_ip: 30,
+            submissions_per_minute_per_token: 60,
+            burst: 10,
+            trust_forwarded_for: false,
+            forwarded_hops: 1,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationConfig {
+    /// Log validation problems instead of rejecting the payload (legacy extension builds)
+    pub lenient: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrashConfig {
+    /// Days a deleted game stays restorable before it is purged (0 keeps it forever)
+    pub retention_days: u32,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig { retention_days: 30 }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Responses of the read endpoints kept in memory until the next write (0 disables it)
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig { max_entries: 512 }
+    }
+}
+
+/// Which of the nicks of a player is shown as its name.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayNamePolicy {
+    /// The nick of the most recent game
+    #[default]
+    Latest,
+    /// The nick seen in the most games, the most recent one on a tie
+    MostFrequent,
+    /// The name pinned by an admin, else the latest nick
+    Pinned,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayersConfig {
+    pub display_name: DisplayNamePolicy,
+}
+
+/// Optional parts of the API that can be switched off.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureConfig {
+    pub import: bool,
+    pub export: bool,
+    pub backup: bool,
+    pub swagger_ui: bool,
+    pub metrics: bool,
+}
+
+impl Default for FeatureConfig {
+    fn default() -> Self {
+        FeatureConfig {
+            import: true,
+            export: true,
+            backup: true,
+            swagger_ui: true,
+            metrics: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String, std::io::Error),
+    Parse(String, toml::de::Error),
+    Env(&'static str, String),
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "cannot read {}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "invalid {}: {}", path, e),
+            ConfigError::Env(var, message) => write!(f, "invalid {}: {}", var, message),
+            ConfigError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Default config file, only read when present.
+pub const DEFAULT_CONFIG_FILE: &str = "bullseye.toml";
+
+impl Config {
+    /// Reads the config file (the explicit one must exist), applies the environment overrides
+    /// and validates the result.
+    pub fn load(path: Option<&str>) -> Result<Config, ConfigError> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => (DEFAULT_CONFIG_FILE, false),
+        };
+
+        let mut config = match std::fs::read_to_string(path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_string(), e))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => Config::default(),
+            Err(e) => return Err(ConfigError::Read(path.to_string(), e)),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(url) = env_var("DATABASE_URL") {
+            self.database.url = url;
+        }
+        if let Some(key) = env_var("API_KEY") {
+            self.auth.api_key = Some(key);
+        }
+        if let Some(secret) = env_var("BULLSEYE_PROXY_SECRET") {
+            self.auth.proxy_secret = Some(secret);
+        }
+        if let Some(bind) = env_var("BULLSEYE_BIND") {
+            self.server.bind = bind;
+        }
+        if let Some(port) = env_parse("BULLSEYE_PORT")? {
+            self.server.port = port;
+        }
+        if let Some(max) = env_parse("BULLSEYE_DB_MAX_CONNECTIONS")? {
+            self.database.max_connections = max;
+        }
+        if let Some(secs) = env_parse("BULLSEYE_DB_ACQUIRE_TIMEOUT_SECS")? {
+            self.database.acquire_timeout_secs = secs;
+        }
+        if let Some(secs) = env_parse("BULLSEYE_DB_IDLE_TIMEOUT_SECS")? {
+            self.database.idle_timeout_secs = secs;
+        }
+        if let Some(origins) = env_var("BULLSEYE_CORS_ORIGINS") {
+            self.cors.allowed_origins = origins
+                .split(',')
+                .map(|o| o.trim().to_string())
+                .filter(|o| !o.is_empty())
+                .collect();
+        }
+        if let Some(require) = env_parse("BULLSEYE_REQUIRE_TOKEN")? {
+            self.auth.require_token = require;
+        }
+        if let Some(format) = env_var("BULLSEYE_LOG_FORMAT") {
+            self.log.format = match format.to_ascii_lowercase().as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => {
+                    return Err(ConfigError::Env(
+                        "BULLSEYE_LOG_FORMAT",
+                        "expected `text` or `json`".to_string(),
+                    ))
+                }
+            };
+        }
+        if let Some(level) = env_var("BULLSEYE_LOG_LEVEL") {
+            self.log.level = level;
+        }
+        if let Some(redact) = env_parse("BULLSEYE_LOG_REDACT")? {
+            self.log.redact = redact;
+        }
+        if let Some(bytes) = env_parse("BULLSEYE_MAX_BODY_BYTES")? {
+            self.limits.max_body_bytes = bytes;
+        }
+        for (var, limit) in [
+            ("BULLSEYE_MAX_ROUNDS", &mut self.limits.max_rounds),
+            ("BULLSEYE_MAX_PLAYERS", &mut self.limits.max_players),
+            (
+                "BULLSEYE_MAX_GUESSES_PER_PLAYER",
+                &mut self.limits.max_guesses_per_player,
+            ),
+        ] {
+            if let Some(value) = env_parse(var)? {
+                *limit = value;
+            }
+        }
+        if let Some(rate) = env_parse("BULLSEYE_RATE_PER_MINUTE_PER_IP")? {
+            self.limits.submissions_per_minute_per_ip = rate;
+        }
+        if let Some(rate) = env_parse("BULLSEYE_RATE_PER_MINUTE_PER_TOKEN")? {
+            self.limits.submissions_per_minute_per_token = rate;
+        }
+        if let Some(burst) = env_parse("BULLSEYE_RATE_BURST")? {
+            self.limits.burst = burst;
+        }
+        if let Some(trust) = env_parse("BULLSEYE_TRUST_FORWARDED_FOR")? {
+            self.limits.trust_forwarded_for = trust;
+        }
+        if let Some(hops) = env_parse("BULLSEYE_FORWARDED_HOPS")? {
+            self.limits.forwarded_hops = hops;
+        }
+        if let Some(lenient) = env_parse("BULLSEYE_VALIDATION_LENIENT")? {
+            self.validation.lenient = lenient;
+        }
+        if let Some(days) = env_parse("BULLSEYE_TRASH_RETENTION_DAYS")? {
+            self.trash.retention_days = days;
+        }
+        if let Some(entries) = env_parse("BULLSEYE_CACHE_MAX_ENTRIES")? {
+            self.cache.max_entries = entries;
+        }
+        if let Some(policy) = env_var("BULLSEYE_PLAYER_DISPLAY_NAME") {
+            self.players.display_name = match policy.to_ascii_lowercase().as_str() {
+                "latest" => DisplayNamePolicy::Latest,
+                "most_frequent" => DisplayNamePolicy::MostFrequent,
+                "pinned" => DisplayNamePolicy::Pinned,
+                _ => {
+                    return Err(ConfigError::Env(
+                        "BULLSEYE_PLAYER_DISPLAY_NAME",
+                        "expected `latest`, `most_frequent` or `pinned`".to_string(),
+                    ))
+                }
+            };
+        }
+        for (var, toggle) in [
+            ("BULLSEYE_FEATURE_IMPORT", &mut self.features.import),
+            ("BULLSEYE_FEATURE_EXPORT", &mut self.features.export),
+            ("BULLSEYE_FEATURE_BACKUP", &mut self.features.backup),
+            ("BULLSEYE_FEATURE_SWAGGER_UI", &mut self.features.swagger_ui),
+            ("BULLSEYE_FEATURE_METRICS", &mut self.features.metrics),
+        ] {
+            if let Some(enabled) = env_parse(var)? {
+                *toggle = enabled;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        if self.server.bind.parse::<std::net::IpAddr>().is_err() {
+            return invalid(format!(
+                "server.bind must be an IP address, got `{}`",
+                self.server.bind
+            ));
+        }
+
+        let url = &self.database.url;
+        if !["sqlite:", "postgres:", "postgresql:"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme))
+        {
+            return invalid(format!(
+                "database.url must start with sqlite:// or postgres://, got `{}`",
+                url
+            ));
+        }
+        if self.database.max_connections == 0 {
+            return invalid("database.max_connections must be at least 1".to_string());
+        }
+        if self.database.acquire_timeout_secs == 0 {
+            return invalid("database.acquire_timeout_secs must be at least 1".to_string());
+        }
+        if self.limits.forwarded_hops == 0 {
+            return invalid("limits.forwarded_hops must be at least 1".to_string());
+        }
+
+        let origins = &self.cors.allowed_origins;
+        if origins.is_empty() {
+            return invalid(
+                "cors.allowed_origins must not be empty (use \"*\" to allow any origin)"
+                    .to_string(),
+            );
+        }
+        if origins.iter().any(|o| o == "*") {
+            if origins.len() > 1 {
+                return invalid(
+                    "cors.allowed_origins cannot mix \"*\" with explicit origins".to_string(),
+                );
+            }
+        } else if let Some(bad) = origins.iter().find(|o| {
+            !(o.starts_with("http://") || o.starts_with("https://"))
+                || o.ends_with('/')
+                || header::HeaderValue::from_str(o).is_err()
+        }) {
+            return invalid(format!(
+                "cors.allowed_origins entries must look like https://host[:port], got `{}`",
+                bad
+            ));
+        }
+
+        if self
+            .auth
+            .api_key
+            .as_deref()
+            .is_some_and(|k| k.trim().is_empty())
+        {
+            return invalid("auth.api_key must not be empty".to_string());
+        }
+
+        if self.limits.max_body_bytes < 1024 {
+            return invalid("limits.max_body_bytes must be at least 1024".to_string());
+        }
+        if self.limits.max_rounds == 0
+            || self.limits.max_players == 0
+            || self.limits.max_guesses_per_player == 0
+        {
+            return invalid(
+                "limits.max_rounds, max_players and max_guesses_per_player must be at least 1"
+                    .to_string(),
+            );
+        }
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.level) {
+            return invalid(format!("log.level `{}` is invalid: {}", self.log.level, e));
+        }
+
+        Ok(())
+    }
+
+    pub fn cors_layer(&self) -> CorsLayer {
+        let origins = &self.cors.allowed_origins;
+        let allow_origin = if origins.iter().any(|o| o == "*") {
+            AllowOrigin::any()
+        } else {
+            // Already validated
+            AllowOrigin::list(origins.iter().filter_map(|o| o.parse().ok()))
+        };
+
+        CorsLayer::new()
+            .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+            .allow_origin(allow_origin)
+            .allow_headers(Any)
+    }
+}
+
+/// Environment variable, ignoring empty values.
+pub fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+pub fn env_parse<T>(name: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    env_var(name)
+        .map(|v| {
+            v.parse()
+                .map_err(|e: T::Err| ConfigError::Env(name, e.to_string()))
+        })
+        .transpose()
+}
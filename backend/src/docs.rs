@@ -0,0 +1,104 @@
+//! OpenAPI description of the HTTP API.
+
+use crate::{
+    export::ExportFormat,
+    import::{ImportItemResult, ImportReport, ImportStatus},
+    model::{
+        BoundingBox, BullseyeData, BullseyePayload, BullseyeState, GameOptions, Guess, LatLng,
+        MovementOptions, Panorama, Player, Round, Score,
+    },
+    routes::{
+        admin::{__path_restore_handler, PinNameRequest, PlayerLinkRequest},
+        probes::{DatabaseCheck, Liveness, MigrationCheck, Readiness},
+        seasons::SeasonCreateRequest,
+    },
+    stats::{
+        aliases::AliasSuggestion,
+        games::GameSummary,
+        players::{PlayerStatsDetailed, ScorePoint, TeamStatSimple},
+        seasons::{PlayerStanding, Season, SeasonStandings},
+        teams::TeamStatsDetailed,
+        CountryStat, GameStats, TeamStats,
+    },
+    storage::{
+        aggregates::RecomputeReport,
+        annotations::{GameAnnotations, GameAnnotationsUpdate},
+        audit::AuditEntry,
+        backup::RestoreReport,
+        names::{NameSighting, PlayerNames},
+        trash::TrashedGame,
+    },
+    validation::{ValidationErrors, ValidationProblem},
+};
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    OpenApi,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::routes::probes::health_check,
+        crate::routes::probes::healthz,
+        crate::routes::probes::readyz,
+        crate::routes::probes::metrics_handler,
+        crate::routes::games::submit_game,
+        crate::routes::games::get_games,
+        crate::routes::stats::get_stats,
+        crate::routes::stats::get_team_leaderboard,
+        crate::routes::stats::get_player_stats,
+        crate::routes::players::get_player_names,
+        crate::routes::stats::get_team_stats,
+        crate::routes::seasons::get_seasons,
+        crate::routes::seasons::get_season_standings,
+        crate::routes::seasons::create_season,
+        crate::routes::seasons::close_season,
+        crate::routes::export::export_games,
+        crate::routes::export::export_rounds,
+        crate::routes::export::export_geojson,
+        crate::routes::admin::import_handler,
+        crate::routes::games::update_game_annotations,
+        crate::routes::admin::get_trash,
+        crate::routes::admin::restore_game,
+        crate::routes::admin::purge_trashed_game,
+        crate::routes::admin::get_audit_log,
+        crate::routes::admin::undo_audit,
+        crate::routes::admin::backup_handler,
+        crate::routes::admin::recompute_stats,
+        crate::routes::admin::get_alias_suggestions,
+        crate::routes::admin::accept_alias,
+        crate::routes::admin::reject_alias,
+        crate::routes::admin::pin_name,
+        restore_handler
+    ),
+    components(
+        schemas(
+            Liveness, Readiness, DatabaseCheck, MigrationCheck,
+            BullseyePayload, BullseyeData, BullseyeState, GameOptions, MovementOptions,            Round, Panorama, Player, Guess, Score, BoundingBox, LatLng,
+            GameSummary, GameStats, CountryStat, TeamStats, PlayerStatsDetailed, TeamStatSimple, ScorePoint, TeamStatsDetailed,
+            Season, SeasonCreateRequest, SeasonStandings, PlayerStanding, ExportFormat,
+            ImportReport, ImportItemResult, ImportStatus, RestoreReport,
+            ValidationErrors, ValidationProblem, AuditEntry, TrashedGame, GameAnnotations, GameAnnotationsUpdate,
+            RecomputeReport, PlayerNames, NameSighting, PinNameRequest, AliasSuggestion,
+            PlayerLinkRequest
+        )
+    ),
+    tags(
+        (name = "bullseye-tracker", description = "Bullseye Game Tracker API")
+    ),
+    modifiers(&SecurityAddon)
+)]
+pub struct ApiDoc;
+
+pub struct SecurityAddon;
+
+impl utoipa::Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            )
+        }
+    }
+}
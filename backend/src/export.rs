@@ -0,0 +1,262 @@
+//! CSV and JSON export of the stored games and rounds.
+
+use crate::storage::backend::storage;
+use crate::{
+    model::{game_mode, game_played_at, parse_payload},
+    stats::{game_matches, SeasonWindow, StatsQuery},
+};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::{AnyPool, Row};
+use utoipa::ToSchema;
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum ExportKind {
+    Games,
+    Rounds,
+}
+
+/// One line of `/api/export/games`.
+#[derive(Serialize)]
+pub struct GameExportRow {
+    pub id: i64,
+    pub game_id: Option<String>,
+    pub played_at: String,
+    pub map_name: Option<String>,
+    pub game_mode: String,
+    pub is_finished: bool,
+    pub score: Option<i64>,
+    pub max_score: i64,
+    pub round_count: i64,
+    pub round_time: Option<i64>,
+    pub total_duration: Option<i64>,
+    pub player_ids: String,
+    pub player_names: String,
+    pub country_codes: String,
+}
+
+/// One line of `/api/export/rounds`: a guess of a player, or the bare round if nobody guessed.
+#[derive(Serialize)]
+pub struct RoundExportRow {
+    pub id: i64,
+    pub game_id: Option<String>,
+    pub played_at: String,
+    pub map_name: Option<String>,
+    pub round_number: Option<i32>,
+    pub country_code: Option<String>,
+    pub pano_id: Option<String>,
+    pub pano_lat: Option<f64>,
+    pub pano_lng: Option<f64>,
+    pub round_points: Option<i32>,
+    pub round_distance: Option<f64>,
+    pub player_id: Option<String>,
+    pub primary_player_id: Option<String>,
+    pub player_name: Option<String>,
+    pub guess_lat: Option<f64>,
+    pub guess_lng: Option<f64>,
+    pub guess_size: Option<i32>,
+    pub guess_is_draft: Option<bool>,
+    pub guess_points: Option<i32>,
+    pub guess_distance: Option<f64>,
+    pub guess_within_radius: Option<bool>,
+}
+
+/// Encodes records one game at a time so exports never hold the whole history in memory.
+pub struct ExportEncoder {
+    pub format: ExportFormat,
+    pub wrote_header: bool,
+}
+
+impl ExportEncoder {
+    pub fn new(format: ExportFormat) -> Self {
+        ExportEncoder {
+            format,
+            wrote_header: false,
+        }
+    }
+
+    pub fn encode<T: Serialize>(&mut self, records: &[T]) -> Result<Vec<u8>, String> {
+        match self.format {
+            ExportFormat::Csv => {
+                // The header row comes from the first record, only once per export
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(!self.wrote_header)
+                    .from_writer(Vec::new());
+                for record in records {
+                    writer.serialize(record).map_err(|e| e.to_string())?;
+                    self.wrote_header = true;
+                }
+                writer.into_inner().map_err(|e| e.to_string())
+            }
+            ExportFormat::Ndjson => {
+                let mut out = Vec::new();
+                for record in records {
+                    serde_json::to_writer(&mut out, record).map_err(|e| e.to_string())?;
+                    out.push(b'\n');
+                }
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// Streams the filtered games from the database and sends them encoded, chunk by chunk.
+pub async fn run_export(
+    pool: AnyPool,
+    kind: ExportKind,
+    format: ExportFormat,
+    filters: StatsQuery,
+    window: Option<SeasonWindow>,
+    tx: tokio::sync::mpsc::Sender<Vec<u8>>,
+) -> Result<(), String> {
+    let alias_map: std::collections::HashMap<String, String> =
+        sqlx::query("SELECT alias_id, primary_id FROM player_aliases")
+            .fetch_all(&pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|row| (row.get("alias_id"), row.get("primary_id")))
+            .collect();
+
+    let mut encoder = ExportEncoder::new(format);
+    let sql = format!(
+        "SELECT id, game_id, map_name, score, round_time, total_duration, {} FROM games WHERE {} ORDER BY played_at",
+        storage(&pool).payload_columns(),
+        filters.counted_games()
+    );
+    let mut rows = sqlx::query(&sql).fetch(&pool);
+
+    while let Some(row) = rows.try_next().await.map_err(|e| e.to_string())? {
+        let data_str: Option<String> = row.get("data");
+        let Some(payload) = data_str.as_deref().and_then(|d| parse_payload(d).ok()) else {
+            continue;
+        };
+        let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) else {
+            continue;
+        };
+
+        let played_at = game_played_at(state, row.try_get("played_at").unwrap_or_default());
+        if !game_matches(&filters, window.as_ref(), state, &played_at) {
+            continue;
+        }
+
+        let id: i64 = row.try_get("id").unwrap_or(0);
+        let game_id: Option<String> = row.try_get("game_id").unwrap_or_default();
+        let map_name: Option<String> = row.try_get("map_name").unwrap_or_default();
+        let players = state.players.as_deref().unwrap_or_default();
+        let rounds = state.rounds.as_deref().unwrap_or_default();
+
+        let chunk = match kind {
+            ExportKind::Games => encoder.encode(&[GameExportRow {
+                id,
+                game_id,
+                played_at,
+                map_name,
+                game_mode: game_mode(state),
+                is_finished: state
+                    .status
+                    .as_deref()
+                    .map(|s| s.eq_ignore_ascii_case("finished"))
+                    .unwrap_or(false),
+                score: row.try_get("score").unwrap_or_default(),
+                max_score: rounds.len() as i64 * 5000,
+                round_count: rounds.len() as i64,
+                round_time: row.try_get("round_time").unwrap_or_default(),
+                total_duration: row.try_get("total_duration").unwrap_or_default(),
+                player_ids: players
+                    .iter()
+                    .filter_map(|p| p.player_id.clone())
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                player_names: players
+                    .iter()
+                    .filter_map(|p| p.nick.clone())
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                country_codes: rounds
+                    .iter()
+                    .filter_map(|r| r.panorama.as_ref().and_then(|p| p.country_code.clone()))
+                    .collect::<Vec<_>>()
+                    .join(";"),
+            }]),
+            ExportKind::Rounds => {
+                let mut records = Vec::new();
+                for round in rounds {
+                    let base = || RoundExportRow {
+                        id,
+                        game_id: game_id.clone(),
+                        played_at: played_at.clone(),
+                        map_name: map_name.clone(),
+                        round_number: round.round_number,
+                        country_code: round.panorama.as_ref().and_then(|p| p.country_code.clone()),
+                        pano_id: round.panorama.as_ref().and_then(|p| p.pano_id.clone()),
+                        pano_lat: round.panorama.as_ref().and_then(|p| p.lat),
+                        pano_lng: round.panorama.as_ref().and_then(|p| p.lng),
+                        round_points: round.score.as_ref().and_then(|s| s.points),
+                        round_distance: round.score.as_ref().and_then(|s| s.distance),
+                        player_id: None,
+                        primary_player_id: None,
+                        player_name: None,
+                        guess_lat: None,
+                        guess_lng: None,
+                        guess_size: None,
+                        guess_is_draft: None,
+                        guess_points: None,
+                        guess_distance: None,
+                        guess_within_radius: None,
+                    };
+
+                    let mut has_guess = false;
+                    for player in players {
+                        let guesses = player.guesses.as_deref().unwrap_or_default();
+                        for guess in guesses
+                            .iter()
+                            .filter(|g| g.round_number == round.round_number)
+                        {
+                            has_guess = true;
+                            records.push(RoundExportRow {
+                                player_id: player.player_id.clone(),
+                                primary_player_id: player
+                                    .player_id
+                                    .as_ref()
+                                    .map(|pid| alias_map.get(pid).unwrap_or(pid).clone()),
+                                player_name: player.nick.clone(),
+                                guess_lat: guess.lat,
+                                guess_lng: guess.lng,
+                                guess_size: guess.size,
+                                guess_is_draft: guess.is_draft,
+                                guess_points: guess.score.as_ref().and_then(|s| s.points),
+                                guess_distance: guess.score.as_ref().and_then(|s| s.distance),
+                                guess_within_radius: guess
+                                    .score
+                                    .as_ref()
+                                    .and_then(|s| s.is_answer_within_radius),
+                                ..base()
+                            });
+                        }
+                    }
+
+                    if !has_guess {
+                        records.push(base());
+                    }
+                }
+                encoder.encode(&records)
+            }
+        }?;
+
+        // The receiver is gone when the client disconnects: stop reading the database
+        if !chunk.is_empty() && tx.send(chunk).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
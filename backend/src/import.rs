@@ -0,0 +1,170 @@
+//! Bulk import of recorded payloads.
+
+use crate::{
+    config::{Config, ValidationConfig},
+    model::{payload_game_id, BullseyePayload},
+    storage::store_game,
+    validation::{check_payload_limits, validate_payload},
+};
+use serde::Serialize;
+use sqlx::AnyPool;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Imported,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportItemResult {
+    pub index: usize,
+    pub game_id: Option<String>,
+    pub status: ImportStatus,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub items: Vec<ImportItemResult>,
+}
+
+/// Turns a game saved by the extension in `chrome.storage.local` into a submission payload.
+///
+/// Stored games are the bullseye state itself (plus `date`, `gaveUp`, ...), wrapped the same
+/// way `content.js` does when syncing. Entries that already are payloads are kept as is.
+pub fn stored_game_to_payload(item: serde_json::Value) -> Result<BullseyePayload, String> {
+    if !item.is_object() {
+        return Err("entry is not a JSON object".to_string());
+    }
+
+    let value = if item.get("bullseye").is_some() {
+        item
+    } else {
+        serde_json::json!({
+            "gameId": item.get("id").or_else(|| item.get("gameId")).cloned(),
+            "totalDuration": item.get("totalDuration").cloned(),
+            "bullseye": { "state": item },
+        })
+    };
+
+    serde_json::from_value(value).map_err(|e| format!("invalid game payload: {}", e))
+}
+
+pub fn check_importable(payload: &BullseyePayload) -> Result<(), String> {
+    if payload_game_id(payload).is_none() {
+        return Err("missing gameId".to_string());
+    }
+    let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) else {
+        return Err("missing bullseye state".to_string());
+    };
+    if state.rounds.as_ref().map(|r| r.is_empty()).unwrap_or(true) {
+        return Err("game has no rounds".to_string());
+    }
+    if state.players.as_ref().map(|p| p.is_empty()).unwrap_or(true) {
+        return Err("game has no players".to_string());
+    }
+    Ok(())
+}
+
+/// Validation for imported items: the problems are joined into the failure reason.
+pub fn check_valid(payload: &BullseyePayload, validation: &ValidationConfig) -> Result<(), String> {
+    let problems = validate_payload(payload);
+    if problems.is_empty() || validation.lenient {
+        return Ok(());
+    }
+    Err(problems
+        .iter()
+        .map(|p| format!("{}: {}", p.path, p.message))
+        .collect::<Vec<_>>()
+        .join("; "))
+}
+
+/// Imports stored games one by one, skipping the ones whose `game_id` is already stored outside
+/// the trash.
+pub async fn import_games(
+    pool: &AnyPool,
+    config: &Config,
+    items: Vec<serde_json::Value>,
+) -> ImportReport {
+    let mut known_ids: std::collections::HashSet<String> = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT game_id FROM games WHERE deleted_at IS NULL",
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let mut items_report = Vec::new();
+
+    for (index, item) in items.into_iter().enumerate() {
+        let result = match stored_game_to_payload(item) {
+            Err(reason) => ImportItemResult {
+                index,
+                game_id: None,
+                status: ImportStatus::Failed,
+                reason: Some(reason),
+            },
+            Ok(payload) => {
+                let game_id = payload_game_id(&payload).map(str::to_string);
+                let checked = check_importable(&payload)
+                    .and_then(|()| check_payload_limits(&payload, &config.limits))
+                    .and_then(|()| check_valid(&payload, &config.validation));
+                let (status, reason) = if let Err(reason) = checked {
+                    (ImportStatus::Failed, Some(reason))
+                } else if game_id.as_ref().is_some_and(|id| known_ids.contains(id)) {
+                    (
+                        ImportStatus::Skipped,
+                        Some("game already exists".to_string()),
+                    )
+                } else {
+                    match store_game(pool, &payload, config.players.display_name).await {
+                        Ok(true) => {
+                            known_ids.extend(game_id.clone());
+                            (ImportStatus::Imported, None)
+                        }
+                        Ok(false) => (
+                            ImportStatus::Skipped,
+                            Some("game already exists".to_string()),
+                        ),
+                        Err(e) => (ImportStatus::Failed, Some(e.to_string())),
+                    }
+                };
+                ImportItemResult {
+                    index,
+                    game_id,
+                    status,
+                    reason,
+                }
+            }
+        };
+        items_report.push(result);
+    }
+
+    let count = |status| items_report.iter().filter(|i| i.status == status).count();
+    ImportReport {
+        imported: count(ImportStatus::Imported),
+        skipped: count(ImportStatus::Skipped),
+        failed: count(ImportStatus::Failed),
+        items: items_report,
+    }
+}
+
+/// Accepts the `games` array itself or a whole `chrome.storage.local` dump containing it.
+pub fn import_items(body: serde_json::Value) -> Option<Vec<serde_json::Value>> {
+    match body {
+        serde_json::Value::Array(items) => Some(items),
+        serde_json::Value::Object(mut map) => match map.remove("games") {
+            Some(serde_json::Value::Array(items)) => Some(items),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,18 @@
+//! Geoguessr Bullseye game tracker: payload model, storage, statistics and the HTTP API.
+//!
+//! The `bullseye-tracker-backend` binary is a thin command line around this library.
+
+pub mod cache;
+pub mod commands;
+pub mod config;
+pub mod docs;
+pub mod export;
+pub mod import;
+pub mod limits;
+pub mod logging;
+pub mod metrics;
+pub mod model;
+pub mod routes;
+pub mod stats;
+pub mod storage;
+pub mod validation;
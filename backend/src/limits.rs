@@ -0,0 +1,153 @@
+//! Per-client rate limiting of the API.
+
+use crate::{config::LimitsConfig, routes::bearer_token, storage::hash_token};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Token bucket per key (client IP or API token), refilled continuously.
+pub struct RateLimiter {
+    pub per_minute: u32,
+    pub burst: u32,
+    pub buckets: std::sync::Mutex<std::collections::HashMap<String, Bucket>>,
+}
+
+pub struct Bucket {
+    pub tokens: f64,
+    pub updated: std::time::Instant,
+}
+
+/// Buckets are pruned once the map grows past this many keys.
+pub const RATE_LIMIT_MAX_KEYS: usize = 10_000;
+
+impl RateLimiter {
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        RateLimiter {
+            per_minute,
+            burst: burst.max(1),
+            buckets: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
+
+    /// Takes one token for `key`; on refusal returns how long to wait for the next one.
+    pub fn check(&self, key: &str) -> Result<(), std::time::Duration> {
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+
+        let rate = self.per_minute as f64 / 60.0;
+        let capacity = self.burst as f64;
+        let now = std::time::Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= RATE_LIMIT_MAX_KEYS {
+            // Drop the buckets that have refilled completely: they hold no state worth keeping
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < capacity
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens =
+            (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(std::time::Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / rate,
+            ))
+        }
+    }
+}
+
+/// Rate limits shared by the submission routes.
+pub struct SubmissionLimiter {
+    pub per_ip: RateLimiter,
+    pub per_token: RateLimiter,
+    pub trust_forwarded_for: bool,
+    pub forwarded_hops: usize,
+}
+
+impl SubmissionLimiter {
+    pub fn new(limits: &LimitsConfig) -> Self {
+        SubmissionLimiter {
+            per_ip: RateLimiter::new(limits.submissions_per_minute_per_ip, limits.burst),
+            per_token: RateLimiter::new(limits.submissions_per_minute_per_token, limits.burst),
+            trust_forwarded_for: limits.trust_forwarded_for,
+            forwarded_hops: limits.forwarded_hops.max(1),
+        }
+    }
+
+    /// IP of the client: the `X-Forwarded-For` entry added by the outermost trusted proxy,
+    /// as the client can put anything in the entries before it, else the peer address.
+    pub fn client_ip(&self, request: &axum::extract::Request) -> Option<String> {
+        if self.trust_forwarded_for {
+            let forwarded = request
+                .headers()
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .map(str::trim)
+                .collect::<Vec<_>>();
+            // A shorter list went through fewer proxies; its leftmost entry is still theirs
+            let index = forwarded.len().saturating_sub(self.forwarded_hops);
+            let forwarded = forwarded
+                .get(index)
+                .map(|ip| ip.to_string())
+                .filter(|ip| !ip.is_empty());
+            if forwarded.is_some() {
+                return forwarded;
+            }
+        }
+        request
+            .extensions()
+            .get::<ConnectInfo<std::net::SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string())
+    }
+}
+
+/// Rejects submissions over the per-IP or per-token rate with 429 and `Retry-After`.
+pub async fn rate_limit(
+    State(limiter): State<Arc<SubmissionLimiter>>,
+    request: axum::extract::Request,
+    next: Next,
+) -> Response {
+    let token = bearer_token(request.headers()).map(hash_token);
+
+    let mut refused = None;
+    if let Some(ip) = limiter.client_ip(&request) {
+        if let Err(wait) = limiter.per_ip.check(&ip) {
+            refused = Some(("ip", wait));
+        }
+    }
+    if let (None, Some(token)) = (refused, token) {
+        if let Err(wait) = limiter.per_token.check(&token) {
+            refused = Some(("token", wait));
+        }
+    }
+
+    match refused {
+        None => next.run(request).await,
+        Some((scope, wait)) => {
+            warn!("Rate limit exceeded ({})", scope);
+            metrics::counter!("bullseye_rate_limited_total", "scope" => scope).increment(1);
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, wait.as_secs().max(1).to_string())],
+            )
+                .into_response()
+        }
+    }
+}
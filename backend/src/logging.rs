@@ -0,0 +1,45 @@
+//! Tracing setup and redaction of identifying values in the logs.
+
+use crate::{
+    config::{LogConfig, LogFormat},
+    storage::hash_token,
+};
+
+pub static REDACT_LOGS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(true);
+
+/// Player identifier as it should appear in the logs: a short stable hash when redaction is on.
+pub fn redact(player_id: &str) -> String {
+    if REDACT_LOGS.load(std::sync::atomic::Ordering::Relaxed) {
+        format!("#{}", &hash_token(player_id)[..8])
+    } else {
+        player_id.to_string()
+    }
+}
+
+/// Database URL without its password.
+pub fn redact_url(url: &str) -> String {
+    match (url.find("://"), url.rfind('@')) {
+        (Some(scheme_end), Some(at)) if at > scheme_end => {
+            let credentials = &url[scheme_end + 3..at];
+            match credentials.split_once(':') {
+                Some((user, _)) => format!("{}{}:***{}", &url[..scheme_end + 3], user, &url[at..]),
+                None => url.to_string(),
+            }
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// Installs the global tracing subscriber; the filter was validated with the config. Logs go
+/// to stderr so that command output such as `export` or `create-token` stays clean on stdout.
+pub fn init(config: &LogConfig) {
+    let filter = tracing_subscriber::EnvFilter::new(&config.level);
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match config.format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).init(),
+    }
+    REDACT_LOGS.store(config.redact, std::sync::atomic::Ordering::Relaxed);
+}
@@ -0,0 +1,99 @@
+//! Prometheus metrics of the HTTP server and the stored data.
+
+use crate::{config::Config, storage::backend::storage};
+use axum::{extract::MatchedPath, middleware::Next, response::Response};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::AnyPool;
+
+/// Prometheus recorder, installed on first use so the router can be built more than once.
+pub fn metrics_handle() -> &'static PrometheusHandle {
+    static HANDLE: std::sync::OnceLock<PrometheusHandle> = std::sync::OnceLock::new();
+    HANDLE.get_or_init(|| {
+        PrometheusBuilder::new()
+            .set_buckets_for_metric(
+                Matcher::Full("http_request_duration_seconds".to_string()),
+                &[
+                    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+                ],
+            )
+            .and_then(|builder| {
+                builder.set_buckets_for_metric(
+                    Matcher::Full("bullseye_payload_deserialize_seconds".to_string()),
+                    &[0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05],
+                )
+            })
+            .and_then(|builder| builder.install_recorder())
+            .expect("Failed to install the Prometheus recorder")
+    })
+}
+
+/// Counts requests and their latency per matched route.
+pub async fn track_metrics(request: axum::extract::Request, next: Next) -> Response {
+    let start = std::time::Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels)
+        .record(start.elapsed().as_secs_f64());
+
+    response
+}
+
+#[derive(Clone, Copy)]
+pub enum SubmissionOutcome {
+    Accepted,
+    Unauthorized,
+    Duplicate,
+    ParseFailure,
+    TooLarge,
+    Invalid,
+    Error,
+}
+
+pub fn record_submission(outcome: SubmissionOutcome) {
+    let outcome = match outcome {
+        SubmissionOutcome::Accepted => "accepted",
+        SubmissionOutcome::Unauthorized => "unauthorized",
+        SubmissionOutcome::Duplicate => "duplicate",
+        SubmissionOutcome::ParseFailure => "parse_failure",
+        SubmissionOutcome::TooLarge => "too_large",
+        SubmissionOutcome::Invalid => "invalid",
+        SubmissionOutcome::Error => "error",
+    };
+    metrics::counter!("bullseye_submissions_total", "outcome" => outcome).increment(1);
+}
+
+/// Refreshes the gauges that are cheaper to read at scrape time than to maintain.
+pub async fn refresh_gauges(pool: &AnyPool, config: &Config) -> Result<(), sqlx::Error> {
+    metrics::gauge!("bullseye_db_pool_connections").set(pool.size() as f64);
+    metrics::gauge!("bullseye_db_pool_idle_connections").set(pool.num_idle() as f64);
+    metrics::gauge!("bullseye_db_pool_max_connections").set(config.database.max_connections as f64);
+
+    let games: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM games WHERE deleted_at IS NULL")
+        .fetch_one(pool)
+        .await?;
+    let players: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM players")
+        .fetch_one(pool)
+        .await?;
+    let rounds: i64 = sqlx::query_scalar(storage(pool).rounds_query())
+        .fetch_one(pool)
+        .await?;
+
+    metrics::gauge!("bullseye_games").set(games as f64);
+    metrics::gauge!("bullseye_players").set(players as f64);
+    metrics::gauge!("bullseye_rounds").set(rounds as f64);
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! The payload posted by the browser extension and helpers to read it.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct BullseyePayload {
+    pub code: Option<String>,
+    #[serde(rename = "gameId")]
+    pub game_id: Option<String>,
+    #[serde(rename = "playerId")]
+    pub player_id: Option<String>,
+    pub payload: Option<serde_json::Value>,
+    pub timestamp: Option<String>,
+    pub lobby: Option<serde_json::Value>,
+    #[serde(rename = "countryGuess")]
+    pub country_guess: Option<serde_json::Value>,
+    #[serde(rename = "coordinateGuess")]
+    pub coordinate_guess: Option<serde_json::Value>,
+    #[serde(rename = "battleRoyaleGameState")]
+    pub battle_royale_game_state: Option<serde_json::Value>,
+    #[serde(rename = "battleRoyalePlayer")]
+    pub battle_royale_player: Option<serde_json::Value>,
+    pub duel: Option<serde_json::Value>,
+    pub bullseye: Option<BullseyeData>,
+    #[serde(rename = "liveChallenge")]
+    pub live_challenge: Option<serde_json::Value>,
+    #[serde(rename = "totalDuration")]
+    pub total_duration: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct BullseyeData {
+    pub state: Option<BullseyeState>,
+    pub guess: Option<Guess>,
+    pub location: Option<serde_json::Value>,
+    #[serde(rename = "recipientPlayerId")]
+    pub recipient_player_id: Option<String>,
+    #[serde(rename = "playerId")]
+    pub player_id: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct BullseyeState {
+    #[serde(rename = "gameId")]
+    pub game_id: Option<String>,
+    pub status: Option<String>,
+    pub options: Option<GameOptions>,
+    pub version: Option<i32>,
+    #[serde(rename = "currentRoundNumber")]
+    pub current_round_number: Option<i32>,
+    pub rounds: Option<Vec<Round>>,
+    pub players: Option<Vec<Player>>,
+    #[serde(rename = "boundingBox")]
+    pub bounding_box: Option<BoundingBox>,
+    #[serde(rename = "hostPlayerId")]
+    pub host_player_id: Option<String>,
+    #[serde(rename = "mapName")]
+    pub map_name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct GameOptions {
+    #[serde(rename = "movementOptions")]
+    pub movement_options: Option<MovementOptions>,
+    #[serde(rename = "roundCount")]
+    pub round_count: Option<i32>,
+    #[serde(rename = "mapSlug")]
+    pub map_slug: Option<String>,
+    #[serde(rename = "roundTime")]
+    pub round_time: Option<i32>,
+    #[serde(rename = "guessMapType")]
+    pub guess_map_type: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct MovementOptions {
+    #[serde(rename = "forbidMoving")]
+    pub forbid_moving: Option<bool>,
+    #[serde(rename = "forbidZooming")]
+    pub forbid_zooming: Option<bool>,
+    #[serde(rename = "forbidRotating")]
+    pub forbid_rotating: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct Round {
+    #[serde(rename = "roundNumber")]
+    pub round_number: Option<i32>,
+    pub panorama: Option<Panorama>,
+    #[serde(rename = "startTime")]
+    pub start_time: Option<String>,
+    #[serde(rename = "endTime")]
+    pub end_time: Option<String>,
+    pub state: Option<String>,
+    pub score: Option<Score>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct Panorama {
+    #[serde(rename = "panoId")]
+    pub pano_id: Option<String>,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+    #[serde(rename = "countryCode")]
+    pub country_code: Option<String>,
+    pub heading: Option<f64>,
+    pub pitch: Option<f64>,
+    pub zoom: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct Player {
+    #[serde(rename = "playerId")]
+    pub player_id: Option<String>,
+    pub nick: Option<String>,
+    pub guesses: Option<Vec<Guess>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct Guess {
+    #[serde(rename = "roundNumber")]
+    pub round_number: Option<i32>,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+    pub size: Option<i32>,
+    #[serde(rename = "isDraft")]
+    pub is_draft: Option<bool>,
+    pub score: Option<Score>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct Score {
+    #[serde(rename = "isAnswerWithinRadius")]
+    pub is_answer_within_radius: Option<bool>,
+    #[serde(default, deserialize_with = "lenient_points")]
+    pub points: Option<i32>,
+    #[serde(rename = "maxPoints")]
+    pub max_points: Option<i32>,
+    pub distance: Option<f64>,
+}
+
+/// Points of a score, rounded when sent as a float and left out when not a number, so that one
+/// malformed score does not make the whole payload unreadable. The JSONB stats read them alike.
+fn lenient_points<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i32>, D::Error> {
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(value
+        .and_then(|v| v.as_f64())
+        .map(|points| points.round() as i32))
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct BoundingBox {
+    pub min: Option<LatLng>,
+    pub max: Option<LatLng>,
+}
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+pub struct LatLng {
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+}
+
+/// Game ID of a payload: the top-level `gameId`, or the one of the bullseye state.
+pub fn payload_game_id(payload: &BullseyePayload) -> Option<&str> {
+    payload.game_id.as_deref().or_else(|| {
+        payload
+            .bullseye
+            .as_ref()
+            .and_then(|b| b.state.as_ref().and_then(|s| s.game_id.as_deref()))
+    })
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct PlayerInfo {
+    pub id: String,
+    pub name: String,
+}
+
+/// Real date of a game: start of round 1 if known, otherwise the stored `played_at`.
+pub fn game_played_at(state: &BullseyeState, stored: String) -> String {
+    if let Some(rounds) = &state.rounds {
+        let first = rounds
+            .iter()
+            .find(|r| r.round_number == Some(1))
+            .or_else(|| rounds.first());
+        if let Some(start) = first.and_then(|r| r.start_time.clone()) {
+            return start;
+        }
+    }
+    stored
+}
+
+/// Movement rules of a game: "Moving", "No Move" or "NMPZ".
+pub fn game_mode(state: &BullseyeState) -> String {
+    let Some(opts) = state
+        .options
+        .as_ref()
+        .and_then(|o| o.movement_options.as_ref())
+    else {
+        return "Moving".to_string();
+    };
+
+    let forbid_moving = opts.forbid_moving.unwrap_or(false);
+    let forbid_zooming = opts.forbid_zooming.unwrap_or(false);
+    let forbid_rotating = opts.forbid_rotating.unwrap_or(false);
+
+    if !forbid_moving {
+        "Moving".to_string()
+    } else if forbid_zooming || forbid_rotating {
+        "NMPZ".to_string()
+    } else {
+        "No Move".to_string()
+    }
+}
+
+/// Deserializes a stored `data` blob, recording the time spent for `/metrics`.
+pub fn parse_payload(data: &str) -> Result<BullseyePayload, serde_json::Error> {
+    let start = std::time::Instant::now();
+    let payload = serde_json::from_str(data);
+    metrics::histogram!("bullseye_payload_deserialize_seconds")
+        .record(start.elapsed().as_secs_f64());
+    payload
+}
@@ -0,0 +1,629 @@
+//! Admin endpoints: import, backup, trash, audit log, player links and their suggestions, and
+//! stats upkeep.
+
+use crate::storage::backend::storage;
+use crate::{
+    config::Config,
+    import::{import_games, import_items, ImportReport},
+    logging::redact,
+    routes::{check_api_key, Actor},
+    stats::{
+        aliases::{alias_suggestions, AliasSuggestion, SuggestionQuery},
+        parse_timestamp,
+    },
+    storage::{
+        aggregates::{rebuild_aggregates, RecomputeReport},
+        audit::{undo_audit_entry, AuditEntry, UndoError, AUDIT_COLUMNS},
+        backup::{
+            audit_restore, create_backup, restore_backup, BackupArchive, RestoreError,
+            RestoreReport,
+        },
+        get_nullable,
+        names::{pin_player_name, player_names, PlayerNames},
+        players::{
+            accept_alias_pair, backfill_players, link_players, reject_alias_pair, unlink_players,
+            LinkError,
+        },
+        trash::{purge_game, restore_trashed_game, TrashedGame, Untrash},
+    },
+};
+use axum::{
+    extract::{FromRequest, Json, Path, Query, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{AnyPool, Row};
+use std::sync::Arc;
+use tracing::{debug, error, info};
+use utoipa::{IntoParams, ToSchema};
+
+#[utoipa::path(
+    post,
+    path = "/api/import",
+    request_body(content = Vec<Object>, description = "Games array stored by the extension (`chrome.storage.local` `games`)"),
+    responses(
+        (status = 200, description = "Per-item import report", body = ImportReport),
+        (status = 400, description = "Body is not an array of games"),
+        (status = 401, description = "Unauthorized"),
+        (status = 413, description = "Body larger than 64 MiB"),
+        (status = 429, description = "Too many requests from this IP or token; see `Retry-After`")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub(crate) async fn import_handler(
+    State(pool): State<AnyPool>,
+    State(config): State<Arc<Config>>,
+    request: Request,
+) -> Result<Json<ImportReport>, Response> {
+    // Up to 64 MiB are only parsed once the caller is known
+    check_api_key(&pool, &config.auth, request.headers())
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let Json(body) = Json::<serde_json::Value>::from_request(request, &())
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let items = import_items(body).ok_or_else(|| StatusCode::BAD_REQUEST.into_response())?;
+    let report = import_games(&pool, &config, items).await;
+    info!(
+        "Import: {} imported, {} skipped, {} failed",
+        report.imported, report.skipped, report.failed
+    );
+
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/backup",
+    responses(
+        (status = 200, description = "Versioned JSON archive of the whole database"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub(crate) async fn backup_handler(State(pool): State<AnyPool>) -> Result<Response, StatusCode> {
+    let archive = create_backup(&pool).await.map_err(|e| {
+        error!("Backup failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let filename = format!(
+        "bullseye-backup-{}.json",
+        Utc::now().format("%Y%m%d-%H%M%S")
+    );
+
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )],
+        Json(archive),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/restore",
+    request_body(content = Object, description = "Archive produced by /api/admin/backup"),
+    responses(
+        (status = 200, description = "Backup restored", body = RestoreReport),
+        (status = 400, description = "Invalid or unsupported archive"),
+        (status = 409, description = "Target database is not empty")
+    )
+)]
+pub(crate) async fn restore_handler(
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+    Json(archive): Json<BackupArchive>,
+) -> Result<Json<RestoreReport>, (StatusCode, String)> {
+    let report = restore_backup(&pool, &archive).await.map_err(|e| {
+        error!("Restore failed: {}", e);
+        let status = match e {
+            RestoreError::Invalid(_) => StatusCode::BAD_REQUEST,
+            RestoreError::NotEmpty(_) => StatusCode::CONFLICT,
+            RestoreError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, e.to_string())
+    })?;
+
+    audit_restore(&pool, &actor, &archive, &report).await;
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/trash",
+    responses(
+        (status = 200, description = "Deleted games, most recently deleted first", body = Vec<TrashedGame>)
+    )
+)]
+pub(crate) async fn get_trash(
+    State(pool): State<AnyPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<Vec<TrashedGame>>, StatusCode> {
+    let rows = sqlx::query(&format!(
+        "SELECT id, game_id, map_name, score, {}, deleted_at FROM games
+         WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+        storage(&pool).timestamp_column("played_at")
+    ))
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch the trash: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let retention = config.trash.retention_days;
+    let games = rows
+        .iter()
+        .map(|row| {
+            let deleted_at: String = row.try_get("deleted_at").unwrap_or_default();
+            let purge_after = (retention > 0)
+                .then(|| DateTime::parse_from_rfc3339(&deleted_at).ok())
+                .flatten()
+                .map(|d| (d + chrono::Duration::days(i64::from(retention))).to_rfc3339());
+            TrashedGame {
+                id: row.try_get("id").unwrap_or_default(),
+                game_id: row.try_get("game_id").unwrap_or_default(),
+                map_name: row.try_get("map_name").unwrap_or_default(),
+                score: row.try_get("score").unwrap_or_default(),
+                played_at: row.try_get("played_at").unwrap_or_default(),
+                deleted_at,
+                purge_after,
+            }
+        })
+        .collect();
+
+    Ok(Json(games))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/trash/{id}/restore",
+    params(
+        ("id" = i64, Path, description = "Game ID")
+    ),
+    responses(
+        (status = 200, description = "Game restored"),
+        (status = 404, description = "Game not in the trash"),
+        (status = 409, description = "The game was submitted again since it was deleted")
+    )
+)]
+pub(crate) async fn restore_game(
+    Path(id): Path<i64>,
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+) -> StatusCode {
+    match restore_trashed_game(&pool, id, &actor).await {
+        Ok(Untrash::Restored) => StatusCode::OK,
+        Ok(Untrash::NotInTrash) => StatusCode::NOT_FOUND,
+        Ok(Untrash::StoredAgain) => StatusCode::CONFLICT,
+        Err(e) => {
+            error!("Failed to restore game {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/trash/{id}",
+    params(
+        ("id" = i64, Path, description = "Game ID")
+    ),
+    responses(
+        (status = 200, description = "Game permanently deleted"),
+        (status = 404, description = "Game not in the trash")
+    )
+)]
+pub(crate) async fn purge_trashed_game(
+    Path(id): Path<i64>,
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+) -> StatusCode {
+    match purge_game(&pool, id, &actor).await {
+        Ok(true) => StatusCode::OK,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to purge game {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct AuditQuery {
+    action: Option<String>,
+    actor: Option<String>,
+    target_type: Option<String>,
+    target_id: Option<String>,
+    /// Inclusive lower bound on the entry time
+    from: Option<String>,
+    /// Exclusive upper bound on the entry time
+    to: Option<String>,
+    /// Defaults to 100, at most 1000
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit",
+    params(
+        AuditQuery
+    ),
+    responses(
+        (status = 200, description = "Audit log entries, newest first", body = Vec<AuditEntry>),
+        (status = 400, description = "Invalid date")
+    )
+)]
+pub(crate) async fn get_audit_log(
+    Query(params): Query<AuditQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    let mut conditions = Vec::new();
+    let mut binds = Vec::new();
+
+    for (column, value) in [
+        ("action", &params.action),
+        ("actor", &params.actor),
+        ("target_type", &params.target_type),
+        ("target_id", &params.target_id),
+    ] {
+        if let Some(value) = value {
+            binds.push(value.clone());
+            conditions.push(format!("{} = ${}", column, binds.len()));
+        }
+    }
+    for (op, value) in [(">=", &params.from), ("<", &params.to)] {
+        if let Some(value) = value {
+            let time = parse_timestamp(value).ok_or(StatusCode::BAD_REQUEST)?;
+            binds.push(time.to_rfc3339());
+            conditions.push(format!("created_at {} ${}", op, binds.len()));
+        }
+    }
+
+    let filter = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+    let sql = format!(
+        "SELECT {} FROM audit_log {} ORDER BY id DESC LIMIT {} OFFSET {}",
+        AUDIT_COLUMNS,
+        filter,
+        params.limit.unwrap_or(100).clamp(1, 1000),
+        params.offset.unwrap_or(0).max(0)
+    );
+
+    let mut query = sqlx::query(&sql);
+    for value in binds {
+        query = query.bind(value);
+    }
+    let rows = query.fetch_all(&pool).await.map_err(|e| {
+        error!("Failed to fetch the audit log: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rows.iter().map(AuditEntry::from_row).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/audit/{id}/undo",
+    params(
+        ("id" = i64, Path, description = "Audit entry ID")
+    ),
+    responses(
+        (status = 200, description = "Action reverted", body = AuditEntry),
+        (status = 400, description = "This action cannot be undone"),
+        (status = 404, description = "Audit entry not found"),
+        (status = 409, description = "Already undone, or the data changed since")
+    )
+)]
+pub(crate) async fn undo_audit(
+    Path(id): Path<i64>,
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+) -> Result<Json<AuditEntry>, (StatusCode, String)> {
+    undo_audit_entry(&pool, id, &actor)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            let status = match e {
+                UndoError::NotFound => StatusCode::NOT_FOUND,
+                UndoError::AlreadyUndone | UndoError::Conflict(_) => StatusCode::CONFLICT,
+                UndoError::NotUndoable(_) => StatusCode::BAD_REQUEST,
+                UndoError::Database(ref e) => {
+                    error!("Failed to undo audit entry {}: {}", id, e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            };
+            (status, e.to_string())
+        })
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct PlayerLinkRequest {
+    alias_id: String,
+    primary_id: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct PlayerUnlinkRequest {
+    alias_id: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct PinNameRequest {
+    /// Name to show whatever the nicks; `null` removes the pin
+    name: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct AdminPlayerInfo {
+    id: String,
+    name: String,
+    pinned_name: Option<String>,
+    primary_id: Option<String>,
+    aliases: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/players",
+    responses(
+        (status = 200, description = "List all players with alias info", body = Vec<AdminPlayerInfo>)
+    )
+)]
+pub(crate) async fn get_admin_players(State(pool): State<AnyPool>) -> Json<Vec<AdminPlayerInfo>> {
+    // 1. Get all players (kept up to date by every stored game)
+    let players = sqlx::query("SELECT id, name, pinned_name FROM players")
+        .fetch_all(&pool)
+        .await
+        .unwrap_or_default();
+
+    debug!("Final fetch - Got {} players", players.len());
+
+    // 2. Get all aliases
+    let aliases = sqlx::query("SELECT alias_id, primary_id FROM player_aliases")
+        .fetch_all(&pool)
+        .await
+        .unwrap_or_default();
+
+    // Build lookup maps
+    let mut alias_map: std::collections::HashMap<String, String> = std::collections::HashMap::new(); // alias -> primary
+    let mut reverse_alias_map: std::collections::HashMap<String, Vec<String>> =
+        std::collections::HashMap::new(); // primary -> [aliases]
+
+    for row in aliases {
+        let alias_id: String = row.get("alias_id");
+        let primary_id: String = row.get("primary_id");
+        alias_map.insert(alias_id.clone(), primary_id.clone());
+        reverse_alias_map
+            .entry(primary_id)
+            .or_default()
+            .push(alias_id);
+    }
+
+    let mut result = Vec::new();
+    for row in players {
+        let id: String = row.get("id");
+        let name: String = row.get("name");
+        let pinned_name = get_nullable(&row, "pinned_name").unwrap_or_default();
+
+        let primary_id = alias_map.get(&id).cloned();
+        let aliases = reverse_alias_map.get(&id).cloned().unwrap_or_default();
+
+        result.push(AdminPlayerInfo {
+            id,
+            name,
+            pinned_name,
+            primary_id,
+            aliases,
+        });
+    }
+
+    Json(result)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/link",
+    request_body = PlayerLinkRequest,
+    responses(
+        (status = 200, description = "Player linked successfully"),
+        (status = 400, description = "Invalid request (circular link, etc.)")
+    )
+)]
+pub(crate) async fn link_player(
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+    Json(payload): Json<PlayerLinkRequest>,
+) -> StatusCode {
+    match link_players(&pool, &payload.alias_id, &payload.primary_id, &actor).await {
+        Ok(()) => StatusCode::OK,
+        Err(LinkError::Database(e)) => {
+            error!("Failed to link {}: {}", redact(&payload.alias_id), e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/unlink",
+    request_body = PlayerUnlinkRequest,
+    responses(
+        (status = 200, description = "Player unlinked successfully")
+    )
+)]
+pub(crate) async fn unlink_player(
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+    Json(payload): Json<PlayerUnlinkRequest>,
+) -> StatusCode {
+    if let Err(e) = unlink_players(&pool, &payload.alias_id, &actor).await {
+        error!("Failed to unlink {}: {}", redact(&payload.alias_id), e);
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
+    StatusCode::OK
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/aliases/suggestions",
+    params(
+        SuggestionQuery
+    ),
+    responses(
+        (status = 200, description = "Pairs of players that may be one person, never seen in the same game, the most likely first", body = Vec<AliasSuggestion>),
+        (status = 400, description = "min_confidence outside [0, 1]")
+    )
+)]
+pub(crate) async fn get_alias_suggestions(
+    Query(params): Query<SuggestionQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Json<Vec<AliasSuggestion>>, StatusCode> {
+    // NaN is in no range either
+    if params
+        .min_confidence
+        .is_some_and(|c| !(0.0..=1.0).contains(&c))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    alias_suggestions(&pool, &params)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("Failed to suggest aliases: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/aliases/accept",
+    request_body = PlayerLinkRequest,
+    responses(
+        (status = 200, description = "Suggested pair linked, as with /api/admin/link"),
+        (status = 400, description = "Invalid request (circular link, etc.)")
+    )
+)]
+pub(crate) async fn accept_alias(
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+    Json(payload): Json<PlayerLinkRequest>,
+) -> StatusCode {
+    match accept_alias_pair(&pool, &payload.alias_id, &payload.primary_id, &actor).await {
+        Ok(()) => StatusCode::OK,
+        Err(LinkError::Database(e)) => {
+            error!("Failed to link {}: {}", redact(&payload.alias_id), e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/aliases/reject",
+    request_body = PlayerLinkRequest,
+    responses(
+        (status = 200, description = "Pair no longer suggested"),
+        (status = 400, description = "Both ids are the same player")
+    )
+)]
+pub(crate) async fn reject_alias(
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+    Json(payload): Json<PlayerLinkRequest>,
+) -> StatusCode {
+    if payload.alias_id == payload.primary_id {
+        return StatusCode::BAD_REQUEST;
+    }
+    if let Err(e) = reject_alias_pair(&pool, &payload.alias_id, &payload.primary_id, &actor).await {
+        error!(
+            "Failed to reject the pair of {}: {}",
+            redact(&payload.alias_id),
+            e
+        );
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
+    StatusCode::OK
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/players/{id}/name",
+    params(
+        ("id" = String, Path, description = "Player ID; an alias pins the name of its primary")
+    ),
+    request_body = PinNameRequest,
+    responses(
+        (status = 200, description = "Name pinned or unpinned", body = PlayerNames),
+        (status = 400, description = "Empty name"),
+        (status = 404, description = "Player not found")
+    )
+)]
+pub(crate) async fn pin_name(
+    Path(id): Path<String>,
+    State(pool): State<AnyPool>,
+    State(config): State<Arc<Config>>,
+    Actor(actor): Actor,
+    Json(payload): Json<PinNameRequest>,
+) -> Result<Json<PlayerNames>, StatusCode> {
+    let name = payload.name.as_deref().map(str::trim);
+    if name == Some("") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let internal_error = |e: sqlx::Error| {
+        error!("Failed to pin the name of {}: {}", redact(&id), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let primary_id = pin_player_name(&pool, &id, name, config.players.display_name, &actor)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    player_names(&pool, &primary_id)
+        .await
+        .map_err(internal_error)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/stats/recompute",
+    responses(
+        (status = 200, description = "Players, nicknames and stats aggregates rebuilt from the stored games", body = RecomputeReport),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub(crate) async fn recompute_stats(
+    State(pool): State<AnyPool>,
+    State(config): State<Arc<Config>>,
+    Actor(actor): Actor,
+) -> Result<Json<RecomputeReport>, StatusCode> {
+    let players = backfill_players(&pool, config.players.display_name)
+        .await
+        .map_err(|e| {
+            error!("Failed to rebuild the players: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    info!("{} players refreshed from stored games", players);
+    let report = rebuild_aggregates(&pool, &actor).await.map_err(|e| {
+        error!("Failed to rebuild the stats aggregates: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    info!("Stats aggregates rebuilt from {} games", report.games);
+    Ok(Json(report))
+}
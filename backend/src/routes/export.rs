@@ -0,0 +1,321 @@
+//! Export endpoints.
+
+use crate::storage::backend::storage;
+use crate::{
+    export::{run_export, ExportFormat, ExportKind},
+    model::{game_played_at, parse_payload},
+    stats::{game_matches, parse_timestamp, resolve_season_window, SeasonWindow, StatsQuery},
+};
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::{AnyPool, Row};
+use tracing::error;
+use utoipa::IntoParams;
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct ExportQuery {
+    /// `csv` (default) or `ndjson`
+    format: Option<ExportFormat>,
+    exclude_abandons: Option<bool>,
+    map: Option<String>,
+    season: Option<i64>,
+    include_excluded: Option<bool>,
+}
+
+impl ExportQuery {
+    fn filters(&self) -> StatsQuery {
+        StatsQuery {
+            exclude_abandons: self.exclude_abandons,
+            map: self.map.clone(),
+            score_type: None,
+            season: self.season,
+            include_excluded: self.include_excluded,
+        }
+    }
+}
+
+pub(crate) async fn export_response(
+    pool: AnyPool,
+    query: ExportQuery,
+    kind: ExportKind,
+) -> Result<Response, StatusCode> {
+    let window = resolve_season_window(&pool, query.season).await?;
+    let format = query.format.unwrap_or_default();
+    let filters = query.filters();
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(16);
+    let export = tokio::spawn(run_export(pool, kind, format, filters, window, tx));
+
+    // A failed export fails the body once its chunks are sent: the connection is dropped
+    // rather than the file ended, so the client cannot take a partial export for the whole one
+    let state = (rx, Some(export));
+    let stream = futures_util::stream::unfold(state, |(mut rx, export)| async move {
+        if let Some(chunk) = rx.recv().await {
+            return Some((Ok(chunk), (rx, export)));
+        }
+        let error = match export?.await {
+            Ok(Ok(())) => return None,
+            Ok(Err(e)) => e,
+            Err(e) => e.to_string(),
+        };
+        error!("Export failed: {}", error);
+        Some((Err(error), (rx, None)))
+    });
+
+    let name = match kind {
+        ExportKind::Games => "games",
+        ExportKind::Rounds => "rounds",
+    };
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.{}\"", name, extension),
+            ),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/export/games",
+    params(
+        ExportQuery
+    ),
+    responses(
+        (status = 200, description = "One line per game, streamed as CSV or NDJSON"),
+        (status = 404, description = "Season not found")
+    )
+)]
+pub(crate) async fn export_games(
+    Query(query): Query<ExportQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Response, StatusCode> {
+    export_response(pool, query, ExportKind::Games).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/export/rounds",
+    params(
+        ExportQuery
+    ),
+    responses(
+        (status = 200, description = "One line per round and guess, streamed as CSV or NDJSON"),
+        (status = 404, description = "Season not found")
+    )
+)]
+pub(crate) async fn export_rounds(
+    Query(query): Query<ExportQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Response, StatusCode> {
+    export_response(pool, query, ExportKind::Rounds).await
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct GeoJsonQuery {
+    /// Only keep the guesses of this player (aliases included) and the games they played
+    player: Option<String>,
+    map: Option<String>,
+    /// Lower bound of the game date (inclusive)
+    from: Option<String>,
+    /// Upper bound of the game date (exclusive)
+    to: Option<String>,
+    include_excluded: Option<bool>,
+}
+
+pub(crate) fn geojson_point(
+    lat: f64,
+    lng: f64,
+    properties: serde_json::Value,
+) -> serde_json::Value {
+    serde_json::json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": [lng, lat] },
+        "properties": properties,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/export/geojson",
+    params(
+        GeoJsonQuery
+    ),
+    responses(
+        (status = 200, description = "FeatureCollection of panoramas, guesses and guess-to-answer lines"),
+        (status = 400, description = "Invalid date range")
+    )
+)]
+pub(crate) async fn export_geojson(
+    Query(query): Query<GeoJsonQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Response, StatusCode> {
+    let parse_bound = |value: &Option<String>| match value {
+        Some(v) => parse_timestamp(v).map(Some).ok_or(StatusCode::BAD_REQUEST),
+        None => Ok(None),
+    };
+    let from = parse_bound(&query.from)?;
+    let to = parse_bound(&query.to)?;
+    let window = (from.is_some() || to.is_some()).then(|| SeasonWindow {
+        start: from.unwrap_or(DateTime::<Utc>::MIN_UTC),
+        end: to.unwrap_or(DateTime::<Utc>::MAX_UTC),
+    });
+    let filters = StatsQuery {
+        exclude_abandons: None,
+        map: query.map.clone(),
+        score_type: None,
+        season: None,
+        include_excluded: query.include_excluded,
+    };
+
+    let alias_map: std::collections::HashMap<String, String> =
+        sqlx::query("SELECT alias_id, primary_id FROM player_aliases")
+            .fetch_all(&pool)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|row| (row.get("alias_id"), row.get("primary_id")))
+            .collect();
+    let resolve = |pid: &String| alias_map.get(pid).cloned().unwrap_or_else(|| pid.clone());
+    let target_player = query.player.as_ref().map(resolve);
+
+    let rows = sqlx::query(&format!(
+        "SELECT game_id, map_name, {} FROM games WHERE {}",
+        storage(&pool).payload_columns(),
+        filters.counted_games()
+    ))
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch games: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut features = Vec::new();
+
+    for row in rows {
+        let data_str: Option<String> = row.get("data");
+        let Some(payload) = data_str.as_deref().and_then(|d| parse_payload(d).ok()) else {
+            continue;
+        };
+        let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) else {
+            continue;
+        };
+
+        let played_at = game_played_at(state, row.try_get("played_at").unwrap_or_default());
+        if !game_matches(&filters, window.as_ref(), state, &played_at) {
+            continue;
+        }
+
+        let players = state.players.as_deref().unwrap_or_default();
+        if let Some(target) = &target_player {
+            let played = players
+                .iter()
+                .any(|p| p.player_id.as_ref().map(&resolve).as_ref() == Some(target));
+            if !played {
+                continue;
+            }
+        }
+
+        let game_id: Option<String> = row.try_get("game_id").unwrap_or_default();
+        let map_name: Option<String> = row.try_get("map_name").unwrap_or_default();
+
+        for round in state.rounds.as_deref().unwrap_or_default() {
+            let Some(panorama) = &round.panorama else {
+                continue;
+            };
+            let (Some(pano_lat), Some(pano_lng)) = (panorama.lat, panorama.lng) else {
+                continue;
+            };
+
+            features.push(geojson_point(
+                pano_lat,
+                pano_lng,
+                serde_json::json!({
+                    "kind": "panorama",
+                    "game_id": game_id,
+                    "map_name": map_name,
+                    "played_at": played_at,
+                    "round_number": round.round_number,
+                    "pano_id": panorama.pano_id,
+                    "country_code": panorama.country_code,
+                    "points": round.score.as_ref().and_then(|s| s.points),
+                    "distance": round.score.as_ref().and_then(|s| s.distance),
+                }),
+            ));
+
+            for player in players {
+                let primary_id = player.player_id.as_ref().map(&resolve);
+                if target_player.is_some() && primary_id != target_player {
+                    continue;
+                }
+
+                let guesses = player.guesses.as_deref().unwrap_or_default();
+                for guess in guesses
+                    .iter()
+                    .filter(|g| g.round_number == round.round_number && g.is_draft != Some(true))
+                {
+                    let (Some(lat), Some(lng)) = (guess.lat, guess.lng) else {
+                        continue;
+                    };
+
+                    let properties = serde_json::json!({
+                        "game_id": game_id,
+                        "map_name": map_name,
+                        "played_at": played_at,
+                        "round_number": round.round_number,
+                        "country_code": panorama.country_code,
+                        "player_id": player.player_id,
+                        "primary_player_id": primary_id,
+                        "player_name": player.nick,
+                        "size": guess.size,
+                        "points": guess.score.as_ref().and_then(|s| s.points),
+                        "distance": guess.score.as_ref().and_then(|s| s.distance),
+                        "within_radius": guess.score.as_ref().and_then(|s| s.is_answer_within_radius),
+                    });
+
+                    let mut point_properties = properties.clone();
+                    point_properties["kind"] = "guess".into();
+                    features.push(geojson_point(lat, lng, point_properties));
+
+                    let mut line_properties = properties;
+                    line_properties["kind"] = "guess_line".into();
+                    features.push(serde_json::json!({
+                        "type": "Feature",
+                        "geometry": {
+                            "type": "LineString",
+                            "coordinates": [[lng, lat], [pano_lng, pano_lat]],
+                        },
+                        "properties": line_properties,
+                    }));
+                }
+            }
+        }
+    }
+
+    let collection = serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/geo+json")],
+        collection.to_string(),
+    )
+        .into_response())
+}
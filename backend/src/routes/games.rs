@@ -0,0 +1,231 @@
+//! Game submission, listing and annotation endpoints.
+
+use crate::{
+    config::Config,
+    metrics::{record_submission, SubmissionOutcome},
+    model::{payload_game_id, BullseyePayload},
+    routes::{check_api_key, stream::json_array, Actor},
+    stats::games::stream_games,
+    storage::{
+        annotations::{
+            annotate_game, normalize_tags, GameAnnotations, GameAnnotationsUpdate, MAX_NOTES_CHARS,
+        },
+        store_game,
+        trash::trash_game,
+    },
+    validation::{check_payload_limits, validate_payload, ValidationErrors},
+};
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest, Json, Path, Query, Request, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use sqlx::AnyPool;
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+use utoipa::IntoParams;
+
+#[utoipa::path(
+    post,
+    path = "/api/submit-game",
+    request_body = BullseyePayload,
+    responses(
+        (status = 200, description = "Game submitted successfully, or already stored"),
+        (status = 400, description = "Malformed JSON"),
+        (status = 401, description = "Unauthorized"),
+        (status = 413, description = "Body larger than `limits.max_body_bytes`, or too many rounds, players or guesses"),
+        (status = 422, description = "Payload failed validation (problems listed), or JSON does not match the payload schema", body = ValidationErrors),
+        (status = 429, description = "Too many submissions from this IP or token; see `Retry-After`"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub(crate) async fn submit_game(
+    State(pool): State<AnyPool>,
+    State(config): State<Arc<Config>>,
+    request: Request,
+) -> Result<StatusCode, Response> {
+    // The body is only read once the caller is known
+    if let Err(status) = check_api_key(&pool, &config.auth, request.headers()).await {
+        record_submission(if status == StatusCode::UNAUTHORIZED {
+            SubmissionOutcome::Unauthorized
+        } else {
+            SubmissionOutcome::Error
+        });
+        return Err(status.into_response());
+    }
+
+    let payload: Result<Json<BullseyePayload>, JsonRejection> =
+        Json::from_request(request, &()).await;
+    let Json(payload) = payload.map_err(|rejection| {
+        warn!("Invalid game payload: {}", rejection.body_text());
+        record_submission(if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            SubmissionOutcome::TooLarge
+        } else {
+            SubmissionOutcome::ParseFailure
+        });
+        rejection.into_response()
+    })?;
+
+    if let Err(reason) = check_payload_limits(&payload, &config.limits) {
+        warn!("Game payload rejected: {}", reason);
+        record_submission(SubmissionOutcome::TooLarge);
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into_response());
+    }
+
+    let problems = validate_payload(&payload);
+    if !problems.is_empty() {
+        if config.validation.lenient {
+            warn!(
+                problems = problems.len(),
+                "Accepting invalid game payload (lenient mode): {:?}", problems
+            );
+        } else {
+            warn!(
+                problems = problems.len(),
+                "Game payload rejected as invalid"
+            );
+            record_submission(SubmissionOutcome::Invalid);
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(ValidationErrors { problems }),
+            )
+                .into_response());
+        }
+    }
+
+    let state = payload.bullseye.as_ref().and_then(|b| b.state.as_ref());
+    debug!(
+        game_id = payload_game_id(&payload),
+        map = state.and_then(|s| s.map_name.as_deref()),
+        rounds = state.and_then(|s| s.rounds.as_ref()).map_or(0, Vec::len),
+        players = state.and_then(|s| s.players.as_ref()).map_or(0, Vec::len),
+        "Received game payload"
+    );
+
+    // The extension may send the same game again (e.g. after a retry): keep the first copy
+    let stored = store_game(&pool, &payload, config.players.display_name)
+        .await
+        .map_err(|e| {
+            error!(
+                game_id = payload_game_id(&payload),
+                "Submission rolled back: {}", e
+            );
+            record_submission(SubmissionOutcome::Error);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        })?;
+    if !stored {
+        info!(
+            "Game {} already stored, ignoring",
+            payload_game_id(&payload).unwrap_or_default()
+        );
+        record_submission(SubmissionOutcome::Duplicate);
+        return Ok(StatusCode::OK);
+    }
+
+    record_submission(SubmissionOutcome::Accepted);
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct GamesQuery {
+    /// Comma-separated tags: only the games carrying all of them
+    tags: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/games",
+    params(
+        GamesQuery
+    ),
+    responses(
+        (status = 200, description = "List of games, excluded ones included; the connection is dropped if reading the games fails midway", body = Vec<GameSummary>),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub(crate) async fn get_games(
+    Query(query): Query<GamesQuery>,
+    State(pool): State<AnyPool>,
+) -> Response {
+    match stream_games(pool, query.tags).await {
+        Ok(games) => json_array(games),
+        Err(e) => {
+            error!("Failed to list the games: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/games/{id}",
+    params(
+        ("id" = i64, Path, description = "Game ID")
+    ),
+    responses(
+        (status = 200, description = "Game moved to the trash"),
+        (status = 404, description = "Game not found")
+    )
+)]
+pub(crate) async fn delete_game(
+    Path(id): Path<i64>,
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+) -> StatusCode {
+    match trash_game(&pool, id, &actor).await {
+        Ok(true) => StatusCode::OK,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to delete game {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/games/{id}",
+    params(
+        ("id" = i64, Path, description = "Game ID")
+    ),
+    request_body = GameAnnotationsUpdate,
+    responses(
+        (status = 200, description = "Annotations updated", body = GameAnnotations),
+        (status = 400, description = "Invalid tags or notes"),
+        (status = 404, description = "Game not found")
+    )
+)]
+pub(crate) async fn update_game_annotations(
+    Path(id): Path<i64>,
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+    Json(mut update): Json<GameAnnotationsUpdate>,
+) -> Result<Json<GameAnnotations>, (StatusCode, String)> {
+    if let Some(tags) = &update.tags {
+        update.tags = Some(normalize_tags(tags).map_err(|e| (StatusCode::BAD_REQUEST, e))?);
+    }
+    if update
+        .notes
+        .as_ref()
+        .is_some_and(|n| n.chars().count() > MAX_NOTES_CHARS)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("notes must be at most {} characters", MAX_NOTES_CHARS),
+        ));
+    }
+
+    match annotate_game(&pool, id, &update, &actor).await {
+        Ok(Some(annotations)) => Ok(Json(annotations)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "game not found".to_string())),
+        Err(e) => {
+            error!("Failed to annotate game {}: {}", id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, String::new()))
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! HTTP server: router, shared state, middleware and authentication.
+
+pub(crate) mod admin;
+pub(crate) mod export;
+pub(crate) mod games;
+pub(crate) mod players;
+pub(crate) mod probes;
+pub(crate) mod seasons;
+pub(crate) mod stats;
+pub(crate) mod stream;
+
+use crate::{
+    cache::{http_cache, ResponseCache},
+    config::{AuthConfig, Config},
+    docs::ApiDoc,
+    limits::{rate_limit, SubmissionLimiter},
+    metrics::{metrics_handle, track_metrics},
+    routes::{
+        admin::{
+            accept_alias, backup_handler, get_admin_players, get_alias_suggestions, get_audit_log,
+            get_trash, import_handler, link_player, pin_name, purge_trashed_game, recompute_stats,
+            reject_alias, restore_game, restore_handler, undo_audit, unlink_player,
+        },
+        export::{export_games, export_geojson, export_rounds},
+        games::{delete_game, get_games, submit_game, update_game_annotations},
+        players::get_player_names,
+        probes::{health_check, healthz, metrics_handler, readyz},
+        seasons::{close_season, create_season, get_season_standings, get_seasons},
+        stats::{get_player_stats, get_stats, get_team_leaderboard, get_team_stats},
+    },
+    storage::{hash_token, names::apply_display_name_policy, trash::run_trash_purge},
+};
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, FromRef, FromRequestParts, MatchedPath},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    middleware,
+    response::Response,
+    routing::{delete, get, post},
+    Router,
+};
+use sqlx::AnyPool;
+use std::sync::Arc;
+use tower_http::{
+    compression::CompressionLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use tracing::{error, info, warn};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+/// Runs the HTTP server until it stops; returns the process exit code.
+pub async fn serve(pool: AnyPool, config: Config) -> i32 {
+    let addr = format!("{}:{}", config.server.bind, config.server.port);
+    // The policy may have changed since the names were chosen
+    match apply_display_name_policy(&pool, config.players.display_name).await {
+        Ok(0) => {}
+        Ok(changed) => info!("Display names of {} players updated", changed),
+        Err(e) => warn!("Failed to apply the display name policy: {}", e),
+    }
+    if config.trash.retention_days > 0 {
+        tokio::spawn(run_trash_purge(pool.clone(), config.trash.retention_days));
+    }
+    let app = build_router(AppState {
+        pool: pool.clone(),
+        config: Arc::new(config),
+    });
+
+    // Run server
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Cannot listen on {}: {}", addr, e);
+            return 1;
+        }
+    };
+    info!("listening on {}", addr);
+    let served = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await;
+    pool.close().await;
+    if let Err(e) = served {
+        error!("Server error: {}", e);
+        return 1;
+    }
+    info!("Server stopped");
+    0
+}
+
+/// The whole HTTP API; `serve` adds the listener and the background tasks.
+pub fn build_router(state: AppState) -> Router {
+    let config = &state.config;
+    let cors = config.cors_layer();
+    let features = config.features.clone();
+    let limiter = Arc::new(SubmissionLimiter::new(&config.limits));
+    let rate_limited = middleware::from_fn_with_state(limiter, rate_limit);
+    let cache = Arc::new(ResponseCache::new(state.pool.clone(), &config.cache));
+    let cached = middleware::from_fn_with_state(cache, http_cache);
+
+    // Build app
+    let mut app = Router::new()
+        .route("/", get(health_check))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route(
+            "/api/submit-game",
+            post(submit_game)
+                .layer(DefaultBodyLimit::max(config.limits.max_body_bytes))
+                .layer(rate_limited.clone()),
+        )
+        .route("/api/games", get(get_games).layer(cached.clone()))
+        .route("/api/stats", get(get_stats).layer(cached.clone()))
+        .route(
+            "/api/leaderboard/teams",
+            get(get_team_leaderboard).layer(cached.clone()),
+        )
+        .route(
+            "/api/players/:id/stats",
+            get(get_player_stats).layer(cached.clone()),
+        )
+        .route(
+            "/api/players/:id/names",
+            get(get_player_names).layer(cached.clone()),
+        )
+        .route(
+            "/api/teams/:id/stats",
+            get(get_team_stats).layer(cached.clone()),
+        )
+        .route("/api/seasons", get(get_seasons).layer(cached.clone()))
+        .route(
+            "/api/seasons/:id/standings",
+            get(get_season_standings).layer(cached),
+        )
+        .route(
+            "/api/games/:id",
+            delete(delete_game).patch(update_game_annotations),
+        )
+        .route("/api/admin/trash", get(get_trash))
+        .route("/api/admin/trash/:id", delete(purge_trashed_game))
+        .route("/api/admin/trash/:id/restore", post(restore_game))
+        .route("/api/admin/players", get(get_admin_players))
+        .route("/api/admin/players/:id/name", post(pin_name))
+        .route("/api/admin/link", post(link_player))
+        .route("/api/admin/unlink", post(unlink_player))
+        .route("/api/admin/aliases/suggestions", get(get_alias_suggestions))
+        .route("/api/admin/aliases/accept", post(accept_alias))
+        .route("/api/admin/aliases/reject", post(reject_alias))
+        .route("/api/admin/stats/recompute", post(recompute_stats))
+        .route("/api/admin/seasons", post(create_season))
+        .route("/api/admin/seasons/:id/close", post(close_season))
+        .route("/api/admin/audit", get(get_audit_log))
+        .route("/api/admin/audit/:id/undo", post(undo_audit));
+
+    if features.import {
+        app = app.route(
+            "/api/import",
+            post(import_handler)
+                .layer(DefaultBodyLimit::max(64 * 1024 * 1024))
+                .layer(rate_limited),
+        );
+    }
+    if features.export {
+        app = app
+            .route("/api/export/games", get(export_games))
+            .route("/api/export/rounds", get(export_rounds))
+            .route("/api/export/geojson", get(export_geojson));
+    }
+    if features.backup {
+        app = app.route("/api/admin/backup", get(backup_handler)).route(
+            "/api/admin/restore",
+            post(restore_handler).layer(DefaultBodyLimit::max(512 * 1024 * 1024)),
+        );
+    }
+
+    if features.metrics {
+        metrics_handle();
+        app = app
+            .route("/metrics", get(metrics_handler))
+            .route_layer(middleware::from_fn(track_metrics));
+    }
+
+    let request_id = header::HeaderName::from_static(REQUEST_ID_HEADER);
+    let app = app
+        // gzip or brotli, as the client's Accept-Encoding allows
+        .layer(CompressionLayer::new())
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_request(())
+                .on_response(on_response)
+                .on_failure(()),
+        )
+        .layer(PropagateRequestIdLayer::new(request_id.clone()))
+        .layer(SetRequestIdLayer::new(request_id, MakeRequestUuid));
+
+    let mut app = app.layer(cors).with_state(state);
+    if features.swagger_ui {
+        app = app
+            .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()));
+    }
+    app
+}
+
+/// Span of one HTTP request; the status and latency are logged when the response is sent.
+pub fn request_span(request: &axum::http::Request<Body>) -> tracing::Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        .unwrap_or("unmatched");
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        request_id,
+        method = %request.method(),
+        route,
+        status = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+    )
+}
+
+pub fn on_response(response: &Response, latency: std::time::Duration, span: &tracing::Span) {
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+    if response.status().is_server_error() {
+        error!("request failed");
+    } else {
+        info!("request finished");
+    }
+}
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Shared state of the HTTP handlers; most of them only extract the pool.
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: AnyPool,
+    pub config: Arc<Config>,
+}
+
+impl FromRef<AppState> for AnyPool {
+    fn from_ref(state: &AppState) -> AnyPool {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Arc<Config> {
+        state.config.clone()
+    }
+}
+
+/// Resolves on SIGTERM (Kubernetes) or Ctrl+C, letting in-flight requests finish.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, draining in-flight requests...");
+}
+
+/// Checks the `Authorization: Bearer <token>` header against the configured API key and the
+/// tokens created with `create-token`. Submissions stay open while neither is configured,
+/// unless `auth.require_token` is set.
+pub async fn check_api_key(
+    pool: &AnyPool,
+    auth: &AuthConfig,
+    headers: &HeaderMap,
+) -> Result<(), StatusCode> {
+    let bearer = bearer_token(headers);
+
+    if let (Some(expected), Some(token)) = (&auth.api_key, bearer) {
+        if expected == token {
+            return Ok(());
+        }
+    }
+
+    if let Some(token) = bearer {
+        let known = sqlx::query("SELECT 1 FROM api_tokens WHERE token_hash = $1")
+            .bind(hash_token(token))
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to check API token: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        if known.is_some() {
+            return Ok(());
+        }
+    }
+
+    if auth.api_key.is_none() && !auth.require_token {
+        let token_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_tokens")
+            .fetch_one(pool)
+            .await
+            .unwrap_or(0);
+        if token_count == 0 {
+            return Ok(());
+        }
+    }
+
+    warn!("Unauthorized access attempt");
+    Err(StatusCode::UNAUTHORIZED)
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+}
+
+/// Header set by the Traefik basicAuth middleware (`headerField`) with the admin user name.
+pub const ADMIN_USER_HEADER: &str = "x-webauth-user";
+
+/// Header carrying `auth.proxy_secret`, added by the proxy next to `ADMIN_USER_HEADER`.
+pub const PROXY_SECRET_HEADER: &str = "x-bullseye-proxy-secret";
+
+/// Whether the request came through the proxy holding `auth.proxy_secret`.
+fn from_trusted_proxy(auth: &AuthConfig, headers: &HeaderMap) -> bool {
+    let Some(secret) = auth.proxy_secret.as_deref() else {
+        return false;
+    };
+    // Compared through their hashes, so the time taken says nothing about the secret
+    headers
+        .get(PROXY_SECRET_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|sent| hash_token(sent) == hash_token(secret))
+}
+
+/// Who performed an action: `admin:<user>`, `token:<name>`, `api_key`, `anonymous` or `cli`.
+pub struct Actor(String);
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for Actor
+where
+    S: Send + Sync,
+    AnyPool: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let config = Arc::<Config>::from_ref(state);
+        if let Some(user) = parts
+            .headers
+            .get(ADMIN_USER_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|u| !u.is_empty())
+        {
+            if from_trusted_proxy(&config.auth, &parts.headers) {
+                return Ok(Actor(format!("admin:{}", user)));
+            }
+            warn!(
+                "Ignoring {} sent without the proxy secret",
+                ADMIN_USER_HEADER
+            );
+        }
+
+        if let Some(token) = bearer_token(&parts.headers) {
+            if config.auth.api_key.as_deref() == Some(token) {
+                return Ok(Actor("api_key".to_string()));
+            }
+            let name = sqlx::query_scalar::<_, String>(
+                "SELECT name FROM api_tokens WHERE token_hash = $1",
+            )
+            .bind(hash_token(token))
+            .fetch_optional(&AnyPool::from_ref(state))
+            .await;
+            if let Ok(Some(name)) = name {
+                return Ok(Actor(format!("token:{}", name)));
+            }
+        }
+
+        Ok(Actor("anonymous".to_string()))
+    }
+}
@@ -0,0 +1,38 @@
+//! Player endpoints.
+
+use crate::{
+    logging::redact,
+    storage::names::{player_names, PlayerNames},
+};
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use sqlx::AnyPool;
+use tracing::error;
+
+#[utoipa::path(
+    get,
+    path = "/api/players/{id}/names",
+    params(
+        ("id" = String, Path, description = "Player ID; an alias shows the timeline of its primary")
+    ),
+    responses(
+        (status = 200, description = "Nicknames of the player and its aliases, the oldest first", body = PlayerNames),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Player not found")
+    )
+)]
+pub(crate) async fn get_player_names(
+    Path(id): Path<String>,
+    State(pool): State<AnyPool>,
+) -> Result<Json<PlayerNames>, StatusCode> {
+    player_names(&pool, &id)
+        .await
+        .map_err(|e| {
+            error!("Failed to read the names of {}: {}", redact(&id), e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
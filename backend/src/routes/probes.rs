@@ -0,0 +1,190 @@
+//! Health, readiness and metrics endpoints.
+
+use crate::{
+    config::Config,
+    metrics::{metrics_handle, refresh_gauges},
+    storage::backend::storage,
+};
+use axum::{
+    extract::{Json, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sqlx::AnyPool;
+use std::sync::Arc;
+use tracing::error;
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    get,
+    path = "/",
+    responses(
+        (status = 200, description = "Health check passed", body = String)
+    )
+)]
+pub(crate) async fn health_check() -> &'static str {
+    "OK"
+}
+
+/// How long `/readyz` waits for the database before reporting it unreachable.
+pub(crate) const READINESS_DB_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Liveness {
+    status: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Readiness {
+    /// `ready` or `not_ready`
+    status: &'static str,
+    database: DatabaseCheck,
+    migrations: MigrationCheck,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct DatabaseCheck {
+    ok: bool,
+    latency_ms: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema, Default)]
+pub(crate) struct MigrationCheck {
+    ok: bool,
+    /// Latest migration applied to the database
+    schema_version: Option<i64>,
+    /// Latest migration known to this binary
+    expected_version: Option<i64>,
+    /// Migrations embedded in the binary but not applied (or failed)
+    pending: Vec<i64>,
+    error: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "The process is alive", body = Liveness)
+    )
+)]
+pub(crate) async fn healthz() -> Json<Liveness> {
+    Json(Liveness {
+        status: "ok",
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Database reachable and migrations applied", body = Readiness),
+        (status = 503, description = "Not ready to serve traffic", body = Readiness)
+    )
+)]
+pub(crate) async fn readyz(State(pool): State<AnyPool>) -> (StatusCode, Json<Readiness>) {
+    let start = std::time::Instant::now();
+    let ping =
+        tokio::time::timeout(READINESS_DB_TIMEOUT, sqlx::query("SELECT 1").execute(&pool)).await;
+    let database = match ping {
+        Ok(Ok(_)) => DatabaseCheck {
+            ok: true,
+            latency_ms: Some(start.elapsed().as_millis() as u64),
+            error: None,
+        },
+        Ok(Err(e)) => DatabaseCheck {
+            ok: false,
+            latency_ms: None,
+            error: Some(e.to_string()),
+        },
+        Err(_) => DatabaseCheck {
+            ok: false,
+            latency_ms: None,
+            error: Some("timed out".to_string()),
+        },
+    };
+
+    let migrations = if database.ok {
+        check_migrations(&pool).await
+    } else {
+        MigrationCheck {
+            error: Some("database unreachable".to_string()),
+            ..Default::default()
+        }
+    };
+
+    let ready = database.ok && migrations.ok;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(Readiness {
+            status: if ready { "ready" } else { "not_ready" },
+            database,
+            migrations,
+        }),
+    )
+}
+
+/// Compares the migrations recorded by sqlx with the ones embedded in the binary.
+pub(crate) async fn check_migrations(pool: &AnyPool) -> MigrationCheck {
+    // Read as text: the Any driver truncates SQLite integers this large
+    let applied: Vec<i64> = match sqlx::query_scalar::<_, String>(
+        "SELECT CAST(version AS TEXT) FROM _sqlx_migrations WHERE success",
+    )
+    .fetch_all(pool)
+    .await
+    {
+        Ok(versions) => versions.iter().filter_map(|v| v.parse().ok()).collect(),
+        Err(e) => {
+            return MigrationCheck {
+                error: Some(e.to_string()),
+                ..Default::default()
+            }
+        }
+    };
+
+    let migrator = storage(pool).migrator();
+    let pending: Vec<i64> = migrator
+        .iter()
+        .map(|m| m.version)
+        .filter(|v| !applied.contains(v))
+        .collect();
+
+    MigrationCheck {
+        ok: pending.is_empty(),
+        schema_version: applied.iter().copied().max(),
+        expected_version: migrator.iter().map(|m| m.version).max(),
+        pending,
+        error: None,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String)
+    )
+)]
+pub(crate) async fn metrics_handler(
+    State(pool): State<AnyPool>,
+    State(config): State<Arc<Config>>,
+) -> Response {
+    if let Err(e) = refresh_gauges(&pool, &config).await {
+        error!("Failed to refresh metrics gauges: {}", e);
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics_handle().render(),
+    )
+        .into_response()
+}
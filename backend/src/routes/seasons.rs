@@ -0,0 +1,260 @@
+//! Season endpoints.
+
+use crate::{
+    routes::Actor,
+    stats::{
+        parse_timestamp,
+        seasons::{fetch_season, fetch_season_standings, Season, SeasonStandings},
+    },
+    storage::{
+        audit::{record_audit, AuditEvent},
+        version::bump_data_version,
+    },
+};
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::StatusCode,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::AnyPool;
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SeasonCreateRequest {
+    name: String,
+    starts_at: String,
+    ends_at: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct StandingsQuery {
+    /// Count excluded games in live standings (closed seasons keep their snapshot)
+    include_excluded: Option<bool>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/seasons",
+    responses(
+        (status = 200, description = "List of seasons", body = Vec<Season>),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match")
+    )
+)]
+pub(crate) async fn get_seasons(
+    State(pool): State<AnyPool>,
+) -> Result<Json<Vec<Season>>, StatusCode> {
+    let rows = sqlx::query(
+        "SELECT id, name, starts_at, ends_at, closed_at, created_at FROM seasons ORDER BY starts_at DESC",
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch seasons: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rows.iter().map(Season::from_row).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/seasons/{id}/standings",
+    params(
+        ("id" = i64, Path, description = "Season ID"),
+        StandingsQuery
+    ),
+    responses(
+        (status = 200, description = "Final (closed) or current standings of a season", body = SeasonStandings),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Season not found")
+    )
+)]
+pub(crate) async fn get_season_standings(
+    Path(id): Path<i64>,
+    Query(query): Query<StandingsQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Json<SeasonStandings>, StatusCode> {
+    let season = fetch_season(&pool, id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(
+        fetch_season_standings(&pool, season, query.include_excluded).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/seasons",
+    request_body = SeasonCreateRequest,
+    responses(
+        (status = 201, description = "Season created", body = Season),
+        (status = 400, description = "Invalid name or dates")
+    )
+)]
+pub(crate) async fn create_season(
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+    Json(payload): Json<SeasonCreateRequest>,
+) -> Result<(StatusCode, Json<Season>), StatusCode> {
+    let name = payload.name.trim();
+    let (Some(start), Some(end)) = (
+        parse_timestamp(&payload.starts_at),
+        parse_timestamp(&payload.ends_at),
+    ) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    if name.is_empty() || end <= start {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let db_error = |e: sqlx::Error| {
+        error!("Failed to create season: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    let row = sqlx::query(
+        "INSERT INTO seasons (name, starts_at, ends_at, created_at)
+         VALUES ($1, $2, $3, $4)
+         RETURNING id, name, starts_at, ends_at, closed_at, created_at",
+    )
+    .bind(name)
+    .bind(start.to_rfc3339())
+    .bind(end.to_rfc3339())
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    let season = Season::from_row(&row);
+    bump_data_version(&mut *tx).await.map_err(db_error)?;
+
+    record_audit(
+        &mut tx,
+        &actor,
+        AuditEvent {
+            action: "season.create",
+            target_type: "season",
+            target_id: season.id.to_string(),
+            before: None,
+            after: serde_json::to_value(&season).ok(),
+        },
+    )
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    Ok((StatusCode::CREATED, Json(season)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/seasons/{id}/close",
+    params(
+        ("id" = i64, Path, description = "Season ID")
+    ),
+    responses(
+        (status = 200, description = "Season closed and final standings stored", body = SeasonStandings),
+        (status = 404, description = "Season not found"),
+        (status = 409, description = "Season already closed")
+    )
+)]
+pub(crate) async fn close_season(
+    Path(id): Path<i64>,
+    State(pool): State<AnyPool>,
+    Actor(actor): Actor,
+) -> Result<Json<SeasonStandings>, StatusCode> {
+    let season = fetch_season(&pool, id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if season.closed_at.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let mut standings = fetch_season_standings(&pool, season, None).await?;
+    let closed_at = Utc::now().to_rfc3339();
+
+    let db_error = |e: sqlx::Error| {
+        error!("Failed to store standings of season {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    // Claims the season first: of two concurrent closes, only one stores standings
+    let closed =
+        sqlx::query("UPDATE seasons SET closed_at = $1 WHERE id = $2 AND closed_at IS NULL")
+            .bind(&closed_at)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    if closed.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    for (rank, team) in standings.teams.iter().enumerate() {
+        let team_key = team
+            .members
+            .iter()
+            .map(|p| p.id.clone())
+            .collect::<Vec<_>>()
+            .join(",");
+        sqlx::query(
+            "INSERT INTO season_standings (season_id, kind, standing_rank, standing_key, name, members, games_played, total_score, average_score, total_duration)
+             VALUES ($1, 'team', $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(id)
+        .bind(rank as i64 + 1)
+        .bind(team_key)
+        .bind(&team.team_name)
+        .bind(serde_json::to_string(&team.members).unwrap_or_else(|_| "[]".to_string()))
+        .bind(team.games_played as i64)
+        .bind(team.total_score as i64)
+        .bind(team.average_score)
+        .bind(team.total_duration)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    }
+
+    for (rank, player) in standings.players.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO season_standings (season_id, kind, standing_rank, standing_key, name, members, games_played, total_score, average_score, total_duration)
+             VALUES ($1, 'player', $2, $3, $4, '[]', $5, $6, $7, $8)",
+        )
+        .bind(id)
+        .bind(rank as i64 + 1)
+        .bind(&player.player_id)
+        .bind(&player.name)
+        .bind(player.games_played as i64)
+        .bind(player.total_score as i64)
+        .bind(player.average_score)
+        .bind(player.total_duration)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    }
+
+    bump_data_version(&mut *tx).await.map_err(db_error)?;
+
+    record_audit(
+        &mut tx,
+        &actor,
+        AuditEvent {
+            action: "season.close",
+            target_type: "season",
+            target_id: id.to_string(),
+            before: None,
+            after: Some(serde_json::json!({ "closed_at": closed_at })),
+        },
+    )
+    .await
+    .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+
+    standings.season.closed_at = Some(closed_at);
+    standings.is_snapshot = true;
+    Ok(Json(standings))
+}
@@ -0,0 +1,95 @@
+//! Statistics endpoints.
+
+use crate::stats::{
+    overall_stats,
+    players::{player_stats, PlayerStatsDetailed},
+    team_leaderboard,
+    teams::{team_stats, TeamStatsDetailed},
+    GameStats, StatsQuery, TeamStats,
+};
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::StatusCode,
+};
+use sqlx::AnyPool;
+
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    params(
+        StatsQuery
+    ),
+    responses(
+        (status = 200, description = "Aggregated statistics", body = GameStats),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Season not found")
+    )
+)]
+pub(crate) async fn get_stats(
+    Query(params): Query<StatsQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Json<GameStats>, StatusCode> {
+    overall_stats(&pool, &params).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/leaderboard/teams",
+    params(
+        StatsQuery
+    ),
+    responses(
+        (status = 200, description = "Team Leaderboard; the stored final standings for a closed season", body = Vec<TeamStats>),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 400, description = "map, exclude_abandons or include_excluded asked for a closed season, whose standings cannot be filtered"),
+        (status = 404, description = "Season not found")
+    )
+)]
+pub(crate) async fn get_team_leaderboard(
+    Query(params): Query<StatsQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Json<Vec<TeamStats>>, StatusCode> {
+    team_leaderboard(&pool, &params).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/players/{id}/stats",
+    params(
+        ("id" = String, Path, description = "Player ID"),
+        StatsQuery
+    ),
+    responses(
+        (status = 200, description = "Detailed Player Statistics", body = PlayerStatsDetailed),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Season not found")
+    )
+)]
+pub(crate) async fn get_player_stats(
+    Path(id): Path<String>,
+    Query(params): Query<StatsQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Json<PlayerStatsDetailed>, StatusCode> {
+    player_stats(&pool, id, &params).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/teams/{id}/stats",
+    params(
+        ("id" = String, Path, description = "Comma separated Player IDs"),
+        StatsQuery
+    ),
+    responses(
+        (status = 200, description = "Detailed Team Statistics", body = TeamStatsDetailed),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Season not found")
+    )
+)]
+pub(crate) async fn get_team_stats(
+    Path(id): Path<String>,
+    Query(params): Query<StatsQuery>,
+    State(pool): State<AnyPool>,
+) -> Result<Json<TeamStatsDetailed>, StatusCode> {
+    team_stats(&pool, id, &params).await.map(Json)
+}
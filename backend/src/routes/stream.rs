@@ -0,0 +1,56 @@
+//! JSON responses written while their items are produced, for the lists that grow with the
+//! history: the serialized output is never held whole in memory.
+
+use axum::{
+    body::{Body, Bytes},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures_util::{stream, Stream, StreamExt};
+use serde::Serialize;
+
+/// Items serialized together into one chunk of the body.
+const ITEMS_PER_CHUNK: usize = 64;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An error item fails the body: the connection is dropped rather than the JSON closed, so
+/// the client cannot take a partial list for the whole one.
+fn json_body<S, T, E>(prefix: Bytes, items: S, suffix: &'static [u8]) -> Response
+where
+    S: Stream<Item = Result<T, E>> + Send + 'static,
+    T: Serialize + Send + 'static,
+    E: Into<BoxError>,
+{
+    let mut first = true;
+    let chunks = items.ready_chunks(ITEMS_PER_CHUNK).map(move |batch| {
+        let mut buffer = Vec::new();
+        for item in batch {
+            let item = item.map_err(Into::into)?;
+            if !std::mem::take(&mut first) {
+                buffer.push(b',');
+            }
+            serde_json::to_writer(&mut buffer, &item)?;
+        }
+        Ok::<_, BoxError>(Bytes::from(buffer))
+    });
+    let body = stream::once(async { Ok(prefix) })
+        .chain(chunks)
+        .chain(stream::once(async move { Ok(Bytes::from_static(suffix)) }));
+
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+/// A JSON array of the items of `items`.
+pub fn json_array<S, T, E>(items: S) -> Response
+where
+    S: Stream<Item = Result<T, E>> + Send + 'static,
+    T: Serialize + Send + 'static,
+    E: Into<BoxError>,
+{
+    json_body(Bytes::from_static(b"["), items, b"]")
+}
@@ -0,0 +1,245 @@
+//! Stats read from the aggregate tables kept by `storage::aggregates`.
+//!
+//! They answer the queries without a season; a season needs the date of every game, so those
+//! still go through the games themselves.
+
+use crate::{
+    model::PlayerInfo,
+    stats::{CountryStat, GameStats, StatsQuery, TeamStats},
+    storage::names::display_names,
+};
+use sqlx::{AnyPool, Row};
+use std::collections::HashMap;
+
+/// Condition on `finished` and `excluded`, bound to `$1` and `$2` by `bind_flags`.
+const FLAGS: &str = "excluded <= $1 AND finished >= $2";
+
+fn bind_flags<'q>(
+    query: sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>>,
+    params: &StatsQuery,
+) -> sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>> {
+    query
+        .bind(i64::from(params.include_excluded == Some(true)))
+        .bind(i64::from(params.exclude_abandons == Some(true)))
+}
+
+/// Whether the totals of `map_name` pass the map filter; '' holds the games without a map.
+fn map_matches(params: &StatsQuery, map_name: &str) -> bool {
+    match &params.map {
+        Some(filter) => {
+            !map_name.is_empty() && map_name.to_lowercase().contains(&filter.to_lowercase())
+        }
+        None => true,
+    }
+}
+
+/// Totals of a player or a team.
+pub struct Totals {
+    pub games: i64,
+    pub score: i64,
+    pub duration: i64,
+}
+
+impl Totals {
+    pub fn average_score(&self) -> f64 {
+        if self.games > 0 {
+            self.score as f64 / self.games as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// `/api/stats` without a season.
+pub async fn overall_stats(pool: &AnyPool, params: &StatsQuery) -> Result<GameStats, sqlx::Error> {
+    let maps = bind_flags(
+        sqlx::query(&format!(
+            "SELECT map_name, games, scored_games, score_sum, duration_sum FROM stats_maps WHERE {}",
+            FLAGS
+        )),
+        params,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut total_games: i64 = 0;
+    let mut scored_games: i64 = 0;
+    let mut score_sum: i64 = 0;
+    let mut total_duration_seconds: i64 = 0;
+    for row in maps
+        .iter()
+        .filter(|row| map_matches(params, &row.get::<String, _>("map_name")))
+    {
+        total_games += row.get::<i64, _>("games");
+        scored_games += row.get::<i64, _>("scored_games");
+        score_sum += row.get::<i64, _>("score_sum");
+        total_duration_seconds += row.get::<i64, _>("duration_sum");
+    }
+
+    let countries = bind_flags(
+        sqlx::query(&format!(
+            "SELECT map_name, country_code, rounds, points FROM stats_countries WHERE {}",
+            FLAGS
+        )),
+        params,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut country_stats: HashMap<String, (i64, i64)> = HashMap::new();
+    for row in countries
+        .iter()
+        .filter(|row| map_matches(params, &row.get::<String, _>("map_name")))
+    {
+        let entry = country_stats.entry(row.get("country_code")).or_default();
+        entry.0 += row.get::<i64, _>("points");
+        entry.1 += row.get::<i64, _>("rounds");
+    }
+
+    let mut best_country_guesses: Vec<CountryStat> = country_stats
+        .into_iter()
+        .map(|(country_code, (total, count))| CountryStat {
+            country_code,
+            total_score: total as i32,
+            count: count as i32,
+            average: total as f64 / count as f64,
+        })
+        .collect();
+    best_country_guesses.sort_by(|a, b| {
+        b.average
+            .partial_cmp(&a.average)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    best_country_guesses.truncate(10);
+
+    Ok(GameStats {
+        total_games,
+        average_score: if scored_games > 0 {
+            score_sum as f64 / scored_games as f64
+        } else {
+            0.0
+        },
+        total_duration_seconds,
+        best_country_guesses,
+    })
+}
+
+/// `/api/leaderboard/teams` without a season.
+pub async fn team_leaderboard(
+    pool: &AnyPool,
+    params: &StatsQuery,
+) -> Result<Vec<TeamStats>, sqlx::Error> {
+    let rows = bind_flags(
+        sqlx::query(&format!(
+            "SELECT team_key, CAST(SUM(games) AS BIGINT) AS games,
+                    CAST(SUM(score_sum) AS BIGINT) AS score_sum,
+                    CAST(SUM(duration_sum) AS BIGINT) AS duration_sum
+             FROM stats_teams WHERE {} GROUP BY team_key",
+            FLAGS
+        )),
+        params,
+    )
+    .fetch_all(pool)
+    .await?;
+    let names = display_names(pool).await?;
+
+    let mut leaderboard: Vec<TeamStats> = rows
+        .iter()
+        .map(|row| {
+            let team_key: String = row.get("team_key");
+            let members: Vec<PlayerInfo> = team_key
+                .split(',')
+                .map(|id| PlayerInfo {
+                    id: id.to_string(),
+                    name: names
+                        .get(id)
+                        .cloned()
+                        .unwrap_or_else(|| "Unknown".to_string()),
+                })
+                .collect();
+            let totals = Totals {
+                games: row.get("games"),
+                score: row.get("score_sum"),
+                duration: row.get("duration_sum"),
+            };
+            TeamStats {
+                team_name: members
+                    .iter()
+                    .map(|p| p.name.clone())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                members,
+                games_played: totals.games as i32,
+                average_score: totals.average_score(),
+                total_score: totals.score as i32,
+                total_duration: totals.duration,
+            }
+        })
+        .collect();
+
+    leaderboard.sort_by(|a, b| {
+        b.average_score
+            .partial_cmp(&a.average_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(leaderboard)
+}
+
+/// Totals of a primary player, with their personal or game scores as `score_type` asks.
+pub async fn player_totals(
+    pool: &AnyPool,
+    player_id: &str,
+    params: &StatsQuery,
+) -> Result<Totals, sqlx::Error> {
+    let score_column = if params.score_type.as_deref() == Some("game") {
+        "game_score_sum"
+    } else {
+        "personal_score_sum"
+    };
+    let row = bind_flags(
+        sqlx::query(&format!(
+            "SELECT CAST(COALESCE(SUM(games), 0) AS BIGINT) AS games,
+                    CAST(COALESCE(SUM({}), 0) AS BIGINT) AS score,
+                    CAST(COALESCE(SUM(duration_sum), 0) AS BIGINT) AS duration
+             FROM stats_players WHERE {} AND player_id = $3",
+            score_column, FLAGS
+        )),
+        params,
+    )
+    .bind(player_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Totals {
+        games: row.get("games"),
+        score: row.get("score"),
+        duration: row.get("duration"),
+    })
+}
+
+/// Totals of the team of the sorted, comma separated primary ids `team_key`.
+pub async fn team_totals(
+    pool: &AnyPool,
+    team_key: &str,
+    params: &StatsQuery,
+) -> Result<Totals, sqlx::Error> {
+    let row = bind_flags(
+        sqlx::query(&format!(
+            "SELECT CAST(COALESCE(SUM(games), 0) AS BIGINT) AS games,
+                    CAST(COALESCE(SUM(score_sum), 0) AS BIGINT) AS score,
+                    CAST(COALESCE(SUM(duration_sum), 0) AS BIGINT) AS duration
+             FROM stats_teams WHERE {} AND team_key = $3",
+            FLAGS
+        )),
+        params,
+    )
+    .bind(team_key)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Totals {
+        games: row.get("games"),
+        score: row.get("score"),
+        duration: row.get("duration"),
+    })
+}
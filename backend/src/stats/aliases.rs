@@ -0,0 +1,325 @@
+//! Suggested alias links: pairs of players that look like one person playing from two accounts.
+//!
+//! Two players are only suggested when they never played in the same game. The confidence then
+//! weighs how close their nicks are, how many teammates they share and how alike their guesses
+//! score. Games in the trash are left out; excluded games still count, they say who played.
+
+use crate::storage::backend::storage;
+use crate::{
+    model::{parse_payload, PlayerInfo},
+    storage::{
+        get_nullable,
+        names::display_names,
+        players::{ordered_pair, rejected_pairs},
+    },
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{AnyPool, Row};
+use std::collections::{HashMap, HashSet};
+use utoipa::{IntoParams, ToSchema};
+
+const NICK_WEIGHT: f64 = 0.35;
+const TEAMMATES_WEIGHT: f64 = 0.35;
+const SKILL_WEIGHT: f64 = 0.3;
+
+#[derive(Deserialize, IntoParams)]
+pub struct SuggestionQuery {
+    /// Lowest confidence returned, from 0 to 1; defaults to 0.5
+    pub min_confidence: Option<f64>,
+    /// Defaults to 20, at most 200
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AliasSuggestion {
+    /// Player keeping its id, the one with the most games
+    pub primary: PlayerInfo,
+    /// Player to link to it
+    pub alias: PlayerInfo,
+    /// Weighted sum of the three signals below, from 0 to 1
+    pub confidence: f64,
+    /// Closeness of the two most alike nicks of the players, from 0 to 1
+    pub nick_similarity: f64,
+    /// Teammates in common over all their teammates, from 0 to 1
+    pub shared_teammates: f64,
+    /// Closeness of their points per guess and of their share of guesses within the radius,
+    /// from 0 to 1
+    pub skill_similarity: f64,
+    pub primary_games: i64,
+    pub alias_games: i64,
+}
+
+/// What the games tell about a player, its aliases included.
+#[derive(Default)]
+struct Profile {
+    games: i64,
+    /// Every other player met in a game
+    teammates: HashSet<String>,
+    guesses: i64,
+    /// Sum of `points / max_points` over the guesses
+    accuracy_sum: f64,
+    /// Guesses within the radius
+    hits: i64,
+}
+
+impl Profile {
+    /// Points per guess and share of guesses within the radius; `None` without guesses.
+    fn skill(&self) -> Option<(f64, f64)> {
+        (self.guesses > 0).then(|| {
+            let guesses = self.guesses as f64;
+            (self.accuracy_sum / guesses, self.hits as f64 / guesses)
+        })
+    }
+}
+
+/// Lowercase words of a nick, without punctuation.
+fn normalize_nick(nick: &str) -> String {
+    nick.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Similarity of two normalized nicks from 0 to 1: one minus their relative edit distance, at
+/// least 0.9 when one holds the other ("pierre" and "pierre 2").
+fn nick_similarity(a: &str, b: &str) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let (a_chars, b_chars): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let longest = a_chars.len().max(b_chars.len());
+    let similarity = 1.0 - levenshtein(&a_chars, &b_chars) as f64 / longest as f64;
+
+    let (shorter, longer) = if a_chars.len() <= b_chars.len() {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    if shorter.chars().count() >= 3 && longer.contains(shorter) {
+        similarity.max(0.9)
+    } else {
+        similarity
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+/// Candidate alias pairs, the most likely first, leaving out the rejected ones.
+pub async fn alias_suggestions(
+    pool: &AnyPool,
+    params: &SuggestionQuery,
+) -> Result<Vec<AliasSuggestion>, sqlx::Error> {
+    let alias_map: HashMap<String, String> =
+        sqlx::query("SELECT alias_id, primary_id FROM player_aliases")
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.get("alias_id"), row.get("primary_id")))
+            .collect();
+    let primary_of = |id: &str| alias_map.get(id).cloned().unwrap_or_else(|| id.to_string());
+    let with_aliases: HashSet<&String> = alias_map.values().collect();
+
+    let mut nicks: HashMap<String, HashSet<String>> = HashMap::new();
+    for row in sqlx::query("SELECT player_id, nick FROM player_names")
+        .fetch_all(pool)
+        .await?
+    {
+        let player_id: String = row.get("player_id");
+        nicks
+            .entry(primary_of(&player_id))
+            .or_default()
+            .insert(normalize_nick(row.get("nick")));
+    }
+    let names = display_names(pool).await?;
+    let rejected = rejected_pairs(pool).await?;
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM games WHERE deleted_at IS NULL",
+        storage(pool).json_column("data")
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    let mut profiles: HashMap<String, Profile> = HashMap::new();
+    for row in rows {
+        let data = get_nullable::<String>(&row, "data")?;
+        let Some(payload) = data.as_deref().and_then(|d| parse_payload(d).ok()) else {
+            continue;
+        };
+        let Some(players) = payload
+            .bullseye
+            .as_ref()
+            .and_then(|b| b.state.as_ref())
+            .and_then(|s| s.players.as_ref())
+        else {
+            continue;
+        };
+
+        let in_game: HashSet<String> = players
+            .iter()
+            .filter_map(|p| p.player_id.as_deref().map(&primary_of))
+            .collect();
+        for id in &in_game {
+            let profile = profiles.entry(id.clone()).or_default();
+            profile.games += 1;
+            profile
+                .teammates
+                .extend(in_game.iter().filter(|other| *other != id).cloned());
+        }
+        for player in players {
+            let Some(id) = player.player_id.as_deref().map(&primary_of) else {
+                continue;
+            };
+            let profile = profiles.entry(id).or_default();
+            for score in player
+                .guesses
+                .iter()
+                .flatten()
+                .filter(|g| g.is_draft != Some(true))
+                .filter_map(|g| g.score.as_ref())
+            {
+                let (Some(points), Some(max_points)) = (score.points, score.max_points) else {
+                    continue;
+                };
+                if max_points <= 0 {
+                    continue;
+                }
+                profile.guesses += 1;
+                profile.accuracy_sum += f64::from(points) / f64::from(max_points);
+                profile.hits += i64::from(score.is_answer_within_radius == Some(true));
+            }
+        }
+    }
+
+    let mut ids: Vec<&String> = profiles.keys().collect();
+    ids.sort();
+    let min_confidence = params.min_confidence.unwrap_or(0.5);
+    let no_nicks = HashSet::new();
+    let mut suggestions = Vec::new();
+
+    for (i, a) in ids.iter().enumerate() {
+        for b in &ids[i + 1..] {
+            let (profile_a, profile_b) = (&profiles[*a], &profiles[*b]);
+            if profile_a.teammates.contains(*b) {
+                continue;
+            }
+
+            // The signals are computed from the cheapest on, leaving the pair as soon as the
+            // best it could still reach is below the threshold; nicks would count at most 1
+            let skill_score = match (profile_a.skill(), profile_b.skill()) {
+                (Some((accuracy_a, hits_a)), Some((accuracy_b, hits_b))) => {
+                    1.0 - ((accuracy_a - accuracy_b).abs() + (hits_a - hits_b).abs()) / 2.0
+                }
+                _ => 0.0,
+            };
+            let (fewer, more) = (
+                profile_a.teammates.len().min(profile_b.teammates.len()),
+                profile_a.teammates.len().max(profile_b.teammates.len()),
+            );
+            let teammates_bound = if more > 0 {
+                fewer as f64 / more as f64
+            } else {
+                0.0
+            };
+            if NICK_WEIGHT + TEAMMATES_WEIGHT * teammates_bound + SKILL_WEIGHT * skill_score
+                < min_confidence
+            {
+                continue;
+            }
+
+            let shared = profile_a
+                .teammates
+                .intersection(&profile_b.teammates)
+                .count();
+            let union = profile_a.teammates.len() + profile_b.teammates.len() - shared;
+            let teammates_score = if union > 0 {
+                shared as f64 / union as f64
+            } else {
+                0.0
+            };
+            if NICK_WEIGHT + TEAMMATES_WEIGHT * teammates_score + SKILL_WEIGHT * skill_score
+                < min_confidence
+            {
+                continue;
+            }
+
+            let nick_score = nicks
+                .get(*a)
+                .unwrap_or(&no_nicks)
+                .iter()
+                .flat_map(|x| {
+                    nicks
+                        .get(*b)
+                        .unwrap_or(&no_nicks)
+                        .iter()
+                        .map(move |y| nick_similarity(x, y))
+                })
+                .fold(0.0, f64::max);
+            let confidence = NICK_WEIGHT * nick_score
+                + TEAMMATES_WEIGHT * teammates_score
+                + SKILL_WEIGHT * skill_score;
+            if confidence < min_confidence {
+                continue;
+            }
+
+            let (player_a, player_b) = ordered_pair(a, b);
+            if rejected.contains(&(player_a.to_string(), player_b.to_string())) {
+                continue;
+            }
+
+            // The player with the most games keeps its id; an alias cannot have aliases
+            let (mut primary, mut alias) = if profile_b.games > profile_a.games {
+                (*b, *a)
+            } else {
+                (*a, *b)
+            };
+            if with_aliases.contains(alias) {
+                if with_aliases.contains(primary) {
+                    continue;
+                }
+                std::mem::swap(&mut primary, &mut alias);
+            }
+
+            let info = |id: &str| PlayerInfo {
+                id: id.to_string(),
+                name: names.get(id).cloned().unwrap_or_else(|| id.to_string()),
+            };
+            suggestions.push(AliasSuggestion {
+                primary: info(primary),
+                alias: info(alias),
+                confidence: round(confidence),
+                nick_similarity: round(nick_score),
+                shared_teammates: round(teammates_score),
+                skill_similarity: round(skill_score),
+                primary_games: profiles[primary].games,
+                alias_games: profiles[alias].games,
+            });
+        }
+    }
+
+    suggestions.sort_by(|x, y| {
+        y.confidence
+            .partial_cmp(&x.confidence)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| x.primary.id.cmp(&y.primary.id))
+            .then_with(|| x.alias.id.cmp(&y.alias.id))
+    });
+    suggestions.truncate(params.limit.unwrap_or(20).clamp(1, 200));
+    Ok(suggestions)
+}
@@ -0,0 +1,191 @@
+//! Game listing.
+
+use crate::storage::backend::storage;
+use crate::{
+    model::{game_mode, PlayerInfo},
+    stats::parse_payload,
+    storage::{
+        annotations::{fetch_game_tags, GameAnnotations, ANNOTATION_COLUMNS},
+        names::display_names,
+    },
+};
+use futures_util::{stream, Stream, StreamExt};
+use serde::Serialize;
+use sqlx::{any::AnyRow, AnyPool, Row};
+use std::collections::HashMap;
+use tracing::{debug, error};
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct GameSummary {
+    pub id: i64,
+    pub game_id: Option<String>,
+    pub map_name: Option<String>,
+    pub score: Option<i64>,
+    pub round_time: Option<i64>,
+    pub total_duration: Option<i64>,
+    pub played_at: String,
+    pub players: Vec<PlayerInfo>,
+    pub country_codes: Vec<String>,
+    pub round_count: i64,
+    pub max_score: i64,
+    pub is_finished: bool,
+    pub game_mode: String,
+    pub annotations: GameAnnotations,
+}
+
+/// Every stored game outside the trash, newest first; `wanted` keeps the games carrying all of
+/// its comma separated tags. The games are read and summarized as the stream is polled; a
+/// failure once it started ends the stream with the error.
+pub async fn stream_games(
+    pool: AnyPool,
+    wanted: Option<String>,
+) -> Result<impl Stream<Item = Result<GameSummary, sqlx::Error>> + Send + 'static, sqlx::Error> {
+    let names = display_names(&pool).await?;
+    let tags = fetch_game_tags(&pool).await?;
+    let (sender, receiver) = tokio::sync::mpsc::channel(64);
+    tokio::spawn(async move {
+        let wanted_tags: Vec<String> = wanted
+            .unwrap_or_default()
+            .split(',')
+            .map(|t| t.trim().to_lowercase())
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        let sql = format!(
+            "SELECT id, game_id, map_name, score, round_time, total_duration, {}, {} FROM games WHERE deleted_at IS NULL ORDER BY played_at DESC",
+            storage(&pool).payload_columns(),
+            ANNOTATION_COLUMNS
+        );
+        let mut rows = sqlx::query(&sql).fetch(&pool);
+        let mut count = 0;
+        while let Some(row) = rows.next().await {
+            let row = match row {
+                Ok(row) => row,
+                Err(e) => {
+                    error!("Failed to fetch games: {}", e);
+                    let _ = sender.send(Err(e)).await;
+                    return;
+                }
+            };
+            let annotations = GameAnnotations::from_row(&row, &tags);
+            if !wanted_tags.iter().all(|t| annotations.tags.contains(t)) {
+                continue;
+            }
+            count += 1;
+            let summary = summarize_game(&row, annotations, &names);
+            if sender.send(Ok(summary)).await.is_err() {
+                // The client went away
+                return;
+            }
+        }
+        debug!("Listed {} games", count);
+    });
+
+    Ok(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|summary| (summary, receiver))
+    }))
+}
+
+/// Summary of a row of `games`, its players under their display names.
+fn summarize_game(
+    row: &AnyRow,
+    annotations: GameAnnotations,
+    names: &HashMap<String, String>,
+) -> GameSummary {
+    let mut players: Vec<PlayerInfo> = Vec::new();
+    let mut country_codes = Vec::new();
+    let mut round_count: i64 = 0;
+    let mut is_finished = false;
+    let mut mode = "Moving".to_string();
+    let mut played_at: String = row.try_get("played_at").unwrap_or_default();
+
+    let data_str: Option<String> = row.get("data");
+    if let Some(ref data_str) = data_str {
+        if let Ok(payload) = parse_payload(data_str) {
+            if let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) {
+                // Extract Players
+                if let Some(p_list) = &state.players {
+                    players = p_list
+                        .iter()
+                        .map(|p| {
+                            let id = p.player_id.clone().unwrap_or_default();
+                            let name = names
+                                .get(&id)
+                                .cloned()
+                                .or_else(|| p.nick.clone())
+                                .unwrap_or_else(|| id.clone());
+
+                            PlayerInfo { id, name }
+                        })
+                        .collect();
+                }
+
+                // Extract Country Codes (Flags)
+                if let Some(rounds) = &state.rounds {
+                    round_count = rounds.len() as i64;
+                    for round in rounds {
+                        if let Some(cc) = round
+                            .panorama
+                            .as_ref()
+                            .and_then(|p| p.country_code.as_ref())
+                        {
+                            country_codes.push(cc.clone());
+                        }
+                    }
+                }
+
+                // Check Status
+                if let Some(status) = &state.status {
+                    is_finished = status.eq_ignore_ascii_case("finished");
+                }
+
+                mode = game_mode(state);
+
+                // Extract real played_at from rounds
+                if let Some(rounds) = &state.rounds {
+                    if let Some(first_round) = rounds.iter().find(|r| r.round_number == Some(1)) {
+                        if let Some(start) = &first_round.start_time {
+                            played_at = start.clone();
+                        }
+                    } else if let Some(first) = rounds.first() {
+                        if let Some(start) = &first.start_time {
+                            played_at = start.clone();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let id: i64 = match row.try_get("id") {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Error getting id: {}", e);
+            0
+        }
+    };
+    let game_id: Option<String> = row.try_get("game_id").unwrap_or_default();
+    let map_name: Option<String> = row.try_get("map_name").unwrap_or_default();
+    let score: Option<i64> = row.try_get("score").unwrap_or_default();
+    let round_time: Option<i64> = row.try_get("round_time").unwrap_or_default();
+    let total_duration: Option<i64> = row.try_get("total_duration").unwrap_or_default();
+    // played_at is already set (either from DB or overridden by JSON)
+
+    GameSummary {
+        id,
+        game_id,
+        map_name,
+        score,
+        round_time,
+        round_count, // Calculated above
+        total_duration,
+        played_at,
+        players,
+        country_codes,
+        max_score: round_count * 5000,
+        is_finished,
+        game_mode: mode,
+        annotations,
+    }
+}
@@ -0,0 +1,265 @@
+//! Postgres versions of the stats, filtered and aggregated in SQL over the JSONB payloads.
+//!
+//! They give the same numbers as the Rust scans of `stats` without shipping every payload to
+//! the server; `idx_games_players` serves the player lookups. Payloads are stored compressed,
+//! so each query reads `data->'bullseye'` once per game into `payloads` and works from there.
+
+use crate::{
+    model::PlayerInfo,
+    stats::{CountryStat, GameStats, SeasonWindow, StatsQuery, TeamStats},
+    storage::{get_nullable, names::display_names},
+};
+use sqlx::{AnyPool, Row};
+
+const STATE: &str = "bullseye->'state'";
+
+/// The rounds of a game, or an empty array when the payload has none.
+const ROUNDS: &str = "CASE WHEN jsonb_typeof(bullseye->'state'->'rounds') = 'array' \
+     THEN bullseye->'state'->'rounds' ELSE '[]'::jsonb END";
+
+/// Adds the map, abandon and season filters on `payloads` to `conditions`, their values to
+/// `binds`.
+fn push_filters(
+    params: &StatsQuery,
+    window: Option<&SeasonWindow>,
+    conditions: &mut Vec<String>,
+    binds: &mut Vec<String>,
+) {
+    // Games without a readable state only count when unfiltered, as in the scans
+    if params.map.is_some() || params.exclude_abandons == Some(true) || window.is_some() {
+        conditions.push(format!("jsonb_typeof({}) = 'object'", STATE));
+    }
+    if let Some(map) = &params.map {
+        binds.push(map.to_lowercase());
+        conditions.push(format!(
+            "strpos(lower({}->>'mapName'), ${}) > 0",
+            STATE,
+            binds.len()
+        ));
+    }
+    if params.exclude_abandons == Some(true) {
+        conditions.push(format!("lower({}->>'status') = 'finished'", STATE));
+    }
+    if let Some(window) = window {
+        binds.push(window.start.to_rfc3339());
+        binds.push(window.end.to_rfc3339());
+        conditions.push(format!(
+            "bullseye_played_at({0}, played_at) >= CAST(${1} AS TIMESTAMPTZ) \
+             AND bullseye_played_at({0}, played_at) < CAST(${2} AS TIMESTAMPTZ)",
+            STATE,
+            binds.len() - 1,
+            binds.len()
+        ));
+    }
+}
+
+/// Adds a condition on `games` keeping the games where one of `ids` played.
+pub fn push_player_filter(ids: &[String], conditions: &mut Vec<String>, binds: &mut Vec<String>) {
+    let mut alternatives = Vec::new();
+    for id in ids {
+        binds.push(serde_json::json!([{ "playerId": id }]).to_string());
+        // Written as in `idx_games_players` so the index is used
+        alternatives.push(format!(
+            "data->'bullseye'->'state'->'players' @> CAST(${} AS JSONB)",
+            binds.len()
+        ));
+    }
+    if alternatives.is_empty() {
+        conditions.push("FALSE".to_string());
+    } else {
+        conditions.push(format!("({})", alternatives.join(" OR ")));
+    }
+}
+
+/// `payloads` CTE of the counted games, and the `WHERE` clause of those matching the filters.
+fn counted_games(
+    params: &StatsQuery,
+    window: Option<&SeasonWindow>,
+) -> (String, String, Vec<String>) {
+    let payloads = format!(
+        "WITH payloads AS MATERIALIZED (
+             SELECT id, score, total_duration, played_at, data->'bullseye' AS bullseye
+             FROM games WHERE {}
+         )",
+        params.counted_games()
+    );
+    let mut conditions = vec!["TRUE".to_string()];
+    let mut binds = Vec::new();
+    push_filters(params, window, &mut conditions, &mut binds);
+    (payloads, conditions.join(" AND "), binds)
+}
+
+fn query_with<'q>(
+    sql: &'q str,
+    binds: &'q [String],
+) -> sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>> {
+    binds
+        .iter()
+        .fold(sqlx::query(sql), |query, value| query.bind(value))
+}
+
+/// `points` of the JSONB score `score` as the payload model reads them: rounded when a number,
+/// NULL otherwise, rather than failing the whole query on one malformed payload.
+fn points(score: &str) -> String {
+    format!(
+        "CASE WHEN jsonb_typeof({0}->'points') = 'number' \
+         THEN CAST(CAST({0}->>'points' AS NUMERIC) AS INTEGER) END",
+        score
+    )
+}
+
+/// Points of a game: the guess score of the payload, else the sum of its rounds.
+fn game_score() -> String {
+    format!(
+        "COALESCE({}, \
+         (SELECT COALESCE(SUM({}), 0) FROM jsonb_array_elements({}) AS r))",
+        points("bullseye->'guess'->'score'"),
+        points("r->'score'"),
+        ROUNDS
+    )
+}
+
+/// `/api/stats` in SQL.
+pub async fn overall_stats(
+    pool: &AnyPool,
+    params: &StatsQuery,
+    window: Option<&SeasonWindow>,
+) -> Result<GameStats, sqlx::Error> {
+    let (payloads, filter, binds) = counted_games(params, window);
+
+    // One pass over the payloads: the totals are repeated on each of the (at most 10) countries
+    let sql = format!(
+        "{payloads},
+         matching AS MATERIALIZED (SELECT score, total_duration, bullseye FROM payloads WHERE {filter}),
+         totals AS (
+             SELECT COUNT(*) AS total_games,
+                    CAST(COALESCE(AVG(score), 0) AS DOUBLE PRECISION) AS average_score,
+                    CAST(COALESCE(SUM(total_duration), 0) AS BIGINT) AS total_duration_seconds
+             FROM matching
+         ),
+         countries AS (
+             SELECT lower(r->'panorama'->>'countryCode') AS country_code,
+                    CAST(SUM(COALESCE({points}, 0)) AS BIGINT) AS total_score,
+                    COUNT(*) AS count
+             FROM matching CROSS JOIN jsonb_array_elements({rounds}) AS r
+             WHERE r->'panorama'->>'countryCode' IS NOT NULL
+             GROUP BY 1
+             ORDER BY CAST(SUM(COALESCE({points}, 0)) AS DOUBLE PRECISION) / COUNT(*) DESC
+             LIMIT 10
+         )
+         SELECT * FROM totals LEFT JOIN countries ON TRUE",
+        payloads = payloads,
+        filter = filter,
+        rounds = ROUNDS,
+        points = points("r->'score'"),
+    );
+    let rows = query_with(&sql, &binds).fetch_all(pool).await?;
+
+    let mut best_country_guesses = Vec::new();
+    for row in &rows {
+        let Some(country_code) = get_nullable::<String>(row, "country_code")? else {
+            continue;
+        };
+        let total_score = row.get::<i64, _>("total_score") as i32;
+        let count = row.get::<i64, _>("count") as i32;
+        best_country_guesses.push(CountryStat {
+            country_code,
+            total_score,
+            count,
+            average: total_score as f64 / count as f64,
+        });
+    }
+    let totals = &rows[0];
+
+    Ok(GameStats {
+        total_games: totals.get("total_games"),
+        average_score: totals.get("average_score"),
+        total_duration_seconds: totals.get("total_duration_seconds"),
+        best_country_guesses,
+    })
+}
+
+/// `/api/leaderboard/teams` in SQL: teams are the sorted primary ids of the players of a game.
+pub async fn team_leaderboard(
+    pool: &AnyPool,
+    params: &StatsQuery,
+    window: Option<&SeasonWindow>,
+) -> Result<Vec<TeamStats>, sqlx::Error> {
+    // Like the scan, the leaderboard ignores the map filter
+    let params = StatsQuery {
+        exclude_abandons: params.exclude_abandons,
+        map: None,
+        score_type: None,
+        season: params.season,
+        include_excluded: params.include_excluded,
+    };
+    let (payloads, filter, binds) = counted_games(&params, window);
+
+    let sql = format!(
+        "{payloads},
+         counted AS (
+             SELECT id, {score} AS score, COALESCE(total_duration, 0) AS duration, {state}->'players' AS players
+             FROM payloads WHERE {filter} AND jsonb_typeof({state}->'players') = 'array'
+         ),
+         members AS (
+             SELECT DISTINCT c.id, COALESCE(a.primary_id, p->>'playerId', '') AS player_id
+             FROM counted c
+             CROSS JOIN jsonb_array_elements(c.players) AS p
+             LEFT JOIN player_aliases a ON a.alias_id = p->>'playerId'
+         ),
+         teams AS (
+             SELECT id, string_agg(player_id, ',' ORDER BY player_id COLLATE \"C\") AS team_key
+             FROM members GROUP BY id
+         )
+         SELECT t.team_key, COUNT(*) AS games_played,
+                CAST(SUM(c.score) AS BIGINT) AS total_score,
+                CAST(SUM(c.duration) AS BIGINT) AS total_duration
+         FROM teams t JOIN counted c ON c.id = t.id
+         GROUP BY t.team_key",
+        payloads = payloads,
+        score = game_score(),
+        filter = filter,
+        state = STATE,
+    );
+    let rows = query_with(&sql, &binds).fetch_all(pool).await?;
+
+    let names = display_names(pool).await?;
+
+    let mut leaderboard: Vec<TeamStats> = rows
+        .iter()
+        .map(|row| {
+            let team_key: String = row.get("team_key");
+            let members: Vec<PlayerInfo> = team_key
+                .split(',')
+                .map(|id| PlayerInfo {
+                    id: id.to_string(),
+                    name: names
+                        .get(id)
+                        .cloned()
+                        .unwrap_or_else(|| "Unknown".to_string()),
+                })
+                .collect();
+            let games_played = row.get::<i64, _>("games_played") as i32;
+            let total_score = row.get::<i64, _>("total_score") as i32;
+            TeamStats {
+                team_name: members
+                    .iter()
+                    .map(|p| p.name.clone())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                members,
+                games_played,
+                average_score: total_score as f64 / games_played as f64,
+                total_score,
+                total_duration: row.get("total_duration"),
+            }
+        })
+        .collect();
+
+    leaderboard.sort_by(|a, b| {
+        b.average_score
+            .partial_cmp(&a.average_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(leaderboard)
+}
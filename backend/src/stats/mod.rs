@@ -0,0 +1,488 @@
+//! Statistics computed from the stored games.
+
+pub mod aggregates;
+pub mod aliases;
+pub mod games;
+pub mod jsonb;
+pub mod players;
+pub mod seasons;
+pub mod teams;
+
+use crate::storage::{backend::storage, names::display_names};
+use crate::{
+    model::{game_played_at, parse_payload, BullseyeState, PlayerInfo},
+    stats::seasons::{fetch_season, fetch_season_standings},
+};
+use axum::http::StatusCode;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{AnyPool, Row};
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, ToSchema)]
+pub struct GameStats {
+    pub total_games: i64,
+    pub average_score: f64,
+    pub total_duration_seconds: i64,
+    pub best_country_guesses: Vec<CountryStat>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct CountryStat {
+    pub country_code: String,
+    pub total_score: i32,
+    pub count: i32,
+    pub average: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TeamStats {
+    pub team_name: String, // Comma separated player names
+    pub members: Vec<PlayerInfo>,
+    pub games_played: i32,
+    pub average_score: f64,
+    pub total_score: i32,
+    pub total_duration: i64,
+}
+
+/// Leaderboard of the teams: from the aggregates without a season, in SQL when the database
+/// supports it, else by scanning the payloads.
+pub async fn compute_team_leaderboard(
+    pool: &AnyPool,
+    params: &StatsQuery,
+    window: Option<SeasonWindow>,
+) -> Result<Vec<TeamStats>, StatusCode> {
+    if window.is_none() {
+        return aggregates::team_leaderboard(pool, params)
+            .await
+            .map_err(|e| {
+                error!("Failed to read the team leaderboard: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            });
+    }
+    storage(pool)
+        .team_leaderboard(pool, params, window.as_ref())
+        .await
+        .map_err(|e| {
+            error!("Failed to compute the team leaderboard: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Leaderboard of the teams, computed by scanning the payloads.
+pub async fn scan_team_leaderboard(
+    pool: &AnyPool,
+    params: &StatsQuery,
+    window: Option<SeasonWindow>,
+) -> Vec<TeamStats> {
+    // 1. Fetch Alias Map
+    let aliases = sqlx::query("SELECT alias_id, primary_id FROM player_aliases")
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+
+    let mut alias_map: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    for row in aliases {
+        let alias_id: String = row.get("alias_id");
+        let primary_id: String = row.get("primary_id");
+        alias_map.insert(alias_id, primary_id);
+    }
+
+    let names = display_names(pool).await.unwrap_or_default();
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM games WHERE {}",
+        storage(pool).payload_columns(),
+        params.counted_games()
+    ))
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    // Map of Sorted Player IDs -> (Members, Total Score, Count, Total Duration)
+    let mut team_stats: std::collections::HashMap<String, (Vec<PlayerInfo>, i32, i32, i64)> =
+        std::collections::HashMap::new();
+
+    for row in rows {
+        let data_str: Option<String> = row.get("data");
+        if let Some(data_str) = data_str {
+            if let Ok(payload) = parse_payload(&data_str) {
+                if let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) {
+                    // Filter Abandons
+                    let is_finished = state
+                        .status
+                        .as_deref()
+                        .map(|s| s.eq_ignore_ascii_case("finished"))
+                        .unwrap_or(false);
+                    if params.exclude_abandons == Some(true) && !is_finished {
+                        continue;
+                    }
+
+                    if let Some(window) = &window {
+                        let played_at =
+                            game_played_at(state, row.try_get("played_at").unwrap_or_default());
+                        if !window.contains(&played_at) {
+                            continue;
+                        }
+                    }
+
+                    if let Some(players) = &state.players {
+                        if !players.is_empty() {
+                            // Extract player info and resolve to Primary IDs
+                            let mut current_team_players: Vec<PlayerInfo> = players
+                                .iter()
+                                .map(|p| {
+                                    let pid = p.player_id.clone().unwrap_or_default();
+                                    let primary_id =
+                                        alias_map.get(&pid).cloned().unwrap_or(pid.clone());
+
+                                    let name = names
+                                        .get(&primary_id)
+                                        .cloned()
+                                        .or_else(|| p.nick.clone())
+                                        .unwrap_or_else(|| "Unknown".to_string());
+
+                                    PlayerInfo {
+                                        id: primary_id,
+                                        name,
+                                    }
+                                })
+                                .collect();
+
+                            // Sort by Player ID to ensure consistent team key
+                            current_team_players.sort_by(|a, b| a.id.cmp(&b.id));
+                            // Deduplicate (in case alias + primary were in same game?)
+                            current_team_players.dedup_by(|a, b| a.id == b.id);
+
+                            let team_key = current_team_players
+                                .iter()
+                                .map(|p| p.id.clone())
+                                .collect::<Vec<_>>()
+                                .join(",");
+
+                            // Calculate Score for this game
+                            let mut game_score = 0;
+                            // Try from guess first
+                            if let Some(score) = payload
+                                .bullseye
+                                .as_ref()
+                                .and_then(|b| b.guess.as_ref())
+                                .and_then(|g| g.score.as_ref())
+                                .and_then(|s| s.points)
+                            {
+                                game_score = score;
+                            } else if let Some(rounds) = &state.rounds {
+                                game_score = rounds
+                                    .iter()
+                                    .filter_map(|r| r.score.as_ref().and_then(|s| s.points))
+                                    .sum();
+                            }
+
+                            let duration = payload.total_duration.unwrap_or(0) as i64;
+
+                            // Update stats
+                            let entry =
+                                team_stats
+                                    .entry(team_key.clone())
+                                    .or_insert((Vec::new(), 0, 0, 0));
+
+                            // Update members if not set (first time)
+                            if entry.0.is_empty() {
+                                entry.0 = current_team_players;
+                            }
+
+                            entry.1 += game_score;
+                            entry.2 += 1;
+                            entry.3 += duration;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut leaderboard: Vec<TeamStats> = team_stats
+        .into_iter()
+        .map(|(_key, (members, total_score, count, total_duration))| {
+            let team_name = members
+                .iter()
+                .map(|p| p.name.clone())
+                .collect::<Vec<_>>()
+                .join(", ");
+            TeamStats {
+                team_name,
+                members,
+                games_played: count,
+                average_score: if count > 0 {
+                    total_score as f64 / count as f64
+                } else {
+                    0.0
+                },
+                total_score,
+                total_duration,
+            }
+        })
+        .collect();
+
+    // Sort by Average Score DESC
+    leaderboard.sort_by(|a, b| {
+        b.average_score
+            .partial_cmp(&a.average_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    leaderboard
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct StatsQuery {
+    pub exclude_abandons: Option<bool>,
+    pub map: Option<String>,
+    pub score_type: Option<String>, // "personal" or "game"
+    pub season: Option<i64>,
+    /// Also count the games flagged as excluded
+    pub include_excluded: Option<bool>,
+}
+
+impl StatsQuery {
+    /// `WHERE` condition selecting the games that count towards stats.
+    pub fn counted_games(&self) -> &'static str {
+        if self.include_excluded == Some(true) {
+            "deleted_at IS NULL"
+        } else {
+            "deleted_at IS NULL AND excluded = 0"
+        }
+    }
+}
+
+/// Date range of a season, used to restrict stats to the games played within it.
+#[derive(Clone, Copy)]
+pub struct SeasonWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl SeasonWindow {
+    pub fn contains(&self, played_at: &str) -> bool {
+        parse_timestamp(played_at)
+            .map(|t| t >= self.start && t < self.end)
+            .unwrap_or(false)
+    }
+}
+
+/// Parses the timestamps we store: RFC 3339 from payloads, or SQL `CURRENT_TIMESTAMP` values.
+pub fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+        return Some(t.with_timezone(&Utc));
+    }
+    let trimmed = value.trim_end_matches("+00").trim_end_matches('Z');
+    NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%d %H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%dT%H:%M:%S%.f"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(trimmed, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN))
+        })
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// Applies the map, abandon and season filters shared by the stats endpoints.
+pub fn game_matches(
+    params: &StatsQuery,
+    window: Option<&SeasonWindow>,
+    state: &BullseyeState,
+    played_at: &str,
+) -> bool {
+    if let Some(map_filter) = &params.map {
+        let matches = state
+            .map_name
+            .as_ref()
+            .map(|m| m.to_lowercase().contains(&map_filter.to_lowercase()))
+            .unwrap_or(false);
+        if !matches {
+            return false;
+        }
+    }
+
+    let is_finished = state
+        .status
+        .as_deref()
+        .map(|s| s.eq_ignore_ascii_case("finished"))
+        .unwrap_or(false);
+    if params.exclude_abandons == Some(true) && !is_finished {
+        return false;
+    }
+
+    window.map(|w| w.contains(played_at)).unwrap_or(true)
+}
+
+/// Looks up the window of the requested season, or `None` when no season filter was given.
+pub async fn resolve_season_window(
+    pool: &AnyPool,
+    season: Option<i64>,
+) -> Result<Option<SeasonWindow>, StatusCode> {
+    let Some(season_id) = season else {
+        return Ok(None);
+    };
+
+    let season = fetch_season(pool, season_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    season.window().map(Some).ok_or_else(|| {
+        error!("Season {} has invalid dates", season_id);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Overall statistics of the games matching the filters: from the aggregates without a season,
+/// in SQL when the database supports it, else by scanning the payloads.
+pub async fn overall_stats(pool: &AnyPool, params: &StatsQuery) -> Result<GameStats, StatusCode> {
+    let window = resolve_season_window(pool, params.season).await?;
+    if window.is_none() {
+        return aggregates::overall_stats(pool, params).await.map_err(|e| {
+            error!("Failed to read stats: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        });
+    }
+    storage(pool)
+        .overall_stats(pool, params, window.as_ref())
+        .await
+        .map_err(|e| {
+            error!("Failed to compute stats: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Overall statistics of the games matching the filters, computed by scanning the payloads.
+pub async fn scan_overall_stats(
+    pool: &AnyPool,
+    params: &StatsQuery,
+    window: Option<SeasonWindow>,
+) -> GameStats {
+    let needs_payload =
+        window.is_some() || params.map.is_some() || params.exclude_abandons == Some(true);
+
+    let rows = sqlx::query(&format!(
+        "SELECT score, total_duration, {} FROM games WHERE {}",
+        storage(pool).payload_columns(),
+        params.counted_games()
+    ))
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    let mut total_games: i64 = 0;
+    let mut scored_games: i64 = 0;
+    let mut score_sum: i64 = 0;
+    let mut total_duration_seconds: i64 = 0;
+    let mut country_stats: std::collections::HashMap<String, (i32, i32)> =
+        std::collections::HashMap::new();
+
+    for row in rows {
+        let data_str: Option<String> = row.get("data");
+        let payload = data_str.as_deref().and_then(|d| parse_payload(d).ok());
+        let state = payload
+            .as_ref()
+            .and_then(|p| p.bullseye.as_ref())
+            .and_then(|b| b.state.as_ref());
+
+        // Filters need the payload; games we cannot parse only count when unfiltered
+        if needs_payload {
+            let Some(state) = state else {
+                continue;
+            };
+            let played_at = game_played_at(state, row.try_get("played_at").unwrap_or_default());
+            if !game_matches(params, window.as_ref(), state, &played_at) {
+                continue;
+            }
+        }
+
+        total_games += 1;
+        if let Ok(Some(score)) = row.try_get::<Option<i64>, _>("score") {
+            score_sum += score;
+            scored_games += 1;
+        }
+        total_duration_seconds += row
+            .try_get::<Option<i64>, _>("total_duration")
+            .unwrap_or_default()
+            .unwrap_or(0);
+
+        // Advanced stats (Best Country Guesses)
+        if let Some(rounds) = state.and_then(|s| s.rounds.as_ref()) {
+            for round in rounds {
+                if let Some(cc) = round
+                    .panorama
+                    .as_ref()
+                    .and_then(|p| p.country_code.as_ref())
+                {
+                    let points = round.score.as_ref().and_then(|s| s.points).unwrap_or(0);
+                    let entry = country_stats.entry(cc.to_lowercase()).or_insert((0, 0));
+                    entry.0 += points;
+                    entry.1 += 1;
+                }
+            }
+        }
+    }
+
+    let average_score = if scored_games > 0 {
+        score_sum as f64 / scored_games as f64
+    } else {
+        0.0
+    };
+
+    let mut best_countries: Vec<CountryStat> = country_stats
+        .into_iter()
+        .map(|(code, (total, count))| CountryStat {
+            country_code: code,
+            total_score: total,
+            count,
+            average: if count > 0 {
+                total as f64 / count as f64
+            } else {
+                0.0
+            },
+        })
+        .collect();
+
+    // Sort by average score descending
+    best_countries.sort_by(|a, b| {
+        b.average
+            .partial_cmp(&a.average)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    best_countries.truncate(10); // Top 10
+
+    GameStats {
+        total_games,
+        average_score,
+        total_duration_seconds,
+        best_country_guesses: best_countries,
+    }
+}
+
+/// Leaderboard of the teams; closed seasons come from their snapshot, which cannot be filtered:
+/// asking for a map, for abandons to be left out or for excluded games to count is a 400 there.
+pub async fn team_leaderboard(
+    pool: &AnyPool,
+    params: &StatsQuery,
+) -> Result<Vec<TeamStats>, StatusCode> {
+    if let Some(season_id) = params.season {
+        let season = fetch_season(pool, season_id)
+            .await?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        // Closed seasons are served from their snapshot so they stay stable
+        if season.closed_at.is_some() {
+            let filtered = params.map.is_some()
+                || params.exclude_abandons == Some(true)
+                || params.include_excluded == Some(true);
+            if filtered {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            let standings = fetch_season_standings(pool, season, None).await?;
+            return Ok(standings.teams);
+        }
+    }
+
+    let window = resolve_season_window(pool, params.season).await?;
+    compute_team_leaderboard(pool, params, window).await
+}
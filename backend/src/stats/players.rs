@@ -0,0 +1,399 @@
+//! Per-player statistics.
+
+use crate::storage::backend::storage;
+use crate::{
+    model::{game_mode, PlayerInfo},
+    stats::{
+        aggregates, game_played_at, games::GameSummary, parse_payload, resolve_season_window,
+        CountryStat, StatsQuery,
+    },
+    storage::{
+        annotations::{fetch_game_tags, GameAnnotations, ANNOTATION_COLUMNS},
+        names::display_names,
+    },
+};
+use axum::http::StatusCode;
+use serde::Serialize;
+use sqlx::{AnyPool, Row};
+use tracing::error;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct PlayerStatsDetailed {
+    pub player_id: String,
+    pub total_games: i32,
+    pub average_score: f64,
+    pub total_duration: i64,
+    pub best_countries: Vec<CountryStat>,
+    pub worst_countries: Vec<CountryStat>,
+    pub best_teams: Vec<TeamStatSimple>,
+    pub score_history: Vec<ScorePoint>,
+    pub player_name: Option<String>,
+    pub games: Vec<GameSummary>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TeamStatSimple {
+    pub team_name: String,
+    pub members: Vec<PlayerInfo>,
+    pub average_score: f64,
+    pub games_played: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ScorePoint {
+    pub date: String,
+    pub score: i32,
+    pub map_name: String,
+}
+
+/// Detailed statistics of a player, aliases folded into their primary id.
+///
+/// Only the headline totals can come from the aggregates, when no map or season filter applies.
+/// The game list, score history and country and team breakdowns are per game, so the games of
+/// the player are read either way; on Postgres only theirs, found through the players index.
+pub async fn player_stats(
+    pool: &AnyPool,
+    id: String,
+    params: &StatsQuery,
+) -> Result<PlayerStatsDetailed, StatusCode> {
+    let window = resolve_season_window(pool, params.season).await?;
+
+    // 1. Resolve Identity
+    // Check if the requested ID is an alias
+    let primary_id_opt: Option<String> =
+        sqlx::query_scalar("SELECT primary_id FROM player_aliases WHERE alias_id = $1")
+            .bind(&id)
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None);
+
+    let effective_primary_id = primary_id_opt.unwrap_or_else(|| id.clone());
+
+    // Get all IDs belonging to this identity (Primary + Aliases)
+    let mut all_ids: Vec<String> = vec![effective_primary_id.clone()];
+    let aliases: Vec<String> =
+        sqlx::query_scalar("SELECT alias_id FROM player_aliases WHERE primary_id = $1")
+            .bind(&effective_primary_id)
+            .fetch_all(pool)
+            .await
+            .unwrap_or_default();
+    all_ids.extend(aliases);
+
+    // Postgres only sends back the games of this identity, found through the players index
+    let mut conditions = vec![params.counted_games().to_string()];
+    let mut binds = Vec::new();
+    storage(pool).push_player_filter(&all_ids, &mut conditions, &mut binds);
+    let sql = format!(
+        "SELECT id, game_id, map_name, score, round_time, total_duration, {}, {} FROM games WHERE {} ORDER BY played_at DESC",
+        storage(pool).payload_columns(),
+        ANNOTATION_COLUMNS,
+        conditions.join(" AND ")
+    );
+    let rows = binds
+        .iter()
+        .fold(sqlx::query(&sql), |query, value| query.bind(value))
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+    let tags = fetch_game_tags(pool).await.unwrap_or_default();
+
+    // Use a HashSet for fast lookup
+    let target_ids: std::collections::HashSet<String> = all_ids.into_iter().collect();
+
+    let mut total_games = 0;
+    let mut total_score = 0;
+    let mut total_duration = 0;
+    let mut country_stats: std::collections::HashMap<String, (i32, i32)> =
+        std::collections::HashMap::new();
+    let mut team_stats: std::collections::HashMap<String, (Vec<PlayerInfo>, i32, i32)> =
+        std::collections::HashMap::new();
+    let mut score_history: Vec<ScorePoint> = Vec::new();
+    let mut player_games: Vec<GameSummary> = Vec::new();
+
+    let names = display_names(pool).await.unwrap_or_default();
+
+    for row in rows {
+        let data_str: Option<String> = row.get("data");
+        if let Some(data_str) = data_str {
+            if let Ok(payload) = parse_payload(&data_str) {
+                if let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) {
+                    // Filter by Map
+                    if let Some(map_filter) = &params.map {
+                        if let Some(map_name) = &state.map_name {
+                            if !map_name.to_lowercase().contains(&map_filter.to_lowercase()) {
+                                continue;
+                            }
+                        } else {
+                            continue;
+                        }
+                    }
+
+                    // Check if ANY of our target IDs are in this game
+                    let mut player_in_game = false;
+                    let mut player_score = 0;
+                    let mut team_members: Vec<PlayerInfo> = Vec::new();
+
+                    if let Some(players) = &state.players {
+                        // Find the first matching player from our target_ids list
+                        // In theory, a game shouldn't have multiple players that are actually the same person (unless multi-boxing?)
+                        // We'll take the first match.
+                        if let Some(p) = players.iter().find(|p| {
+                            p.player_id
+                                .as_ref()
+                                .map(|pid| target_ids.contains(pid))
+                                .unwrap_or(false)
+                        }) {
+                            player_in_game = true;
+
+                            // Calculate score based on preference
+                            let use_game_score = params.score_type.as_deref() == Some("game");
+
+                            if use_game_score {
+                                // Force Game Score (sum of round scores)
+                                if let Some(rounds) = &state.rounds {
+                                    player_score = rounds
+                                        .iter()
+                                        .filter_map(|r| r.score.as_ref().and_then(|s| s.points))
+                                        .sum();
+                                }
+                            } else {
+                                // Default / Personal Score
+                                if let Some(guesses) = &p.guesses {
+                                    player_score = guesses
+                                        .iter()
+                                        .filter_map(|g| g.score.as_ref().and_then(|s| s.points))
+                                        .sum();
+                                } else {
+                                    // Fallback to round scores sum if no guesses found (legacy/co-op fallback)
+                                    if let Some(rounds) = &state.rounds {
+                                        player_score = rounds
+                                            .iter()
+                                            .filter_map(|r| r.score.as_ref().and_then(|s| s.points))
+                                            .sum();
+                                    }
+                                }
+                            }
+
+                            // Extract team members
+                            team_members = players
+                                .iter()
+                                .map(|p| {
+                                    let pid = p.player_id.clone().unwrap_or_default();
+                                    let name = names
+                                        .get(&pid)
+                                        .cloned()
+                                        .or_else(|| p.nick.clone())
+                                        .or_else(|| p.player_id.clone())
+                                        .unwrap_or_else(|| "Unknown".to_string());
+                                    PlayerInfo { id: pid, name }
+                                })
+                                .collect();
+                        }
+                    }
+
+                    if !player_in_game {
+                        continue;
+                    }
+
+                    // Filter Abandons
+                    let is_finished = state
+                        .status
+                        .as_deref()
+                        .map(|s| s.eq_ignore_ascii_case("finished"))
+                        .unwrap_or(false);
+                    if params.exclude_abandons == Some(true) && !is_finished {
+                        continue;
+                    }
+
+                    let played_at_str =
+                        game_played_at(state, row.try_get("played_at").unwrap_or_default());
+                    if let Some(window) = &window {
+                        if !window.contains(&played_at_str) {
+                            continue;
+                        }
+                    }
+
+                    // --- Aggregation ---
+                    total_games += 1;
+                    total_score += player_score;
+                    total_duration += payload.total_duration.unwrap_or(0) as i64;
+
+                    // Country Stats
+                    if let Some(rounds) = &state.rounds {
+                        for round in rounds {
+                            if let Some(cc) = round
+                                .panorama
+                                .as_ref()
+                                .and_then(|p| p.country_code.as_ref())
+                            {
+                                let points =
+                                    round.score.as_ref().and_then(|s| s.points).unwrap_or(0);
+                                let entry =
+                                    country_stats.entry(cc.to_lowercase()).or_insert((0, 0));
+                                entry.0 += points;
+                                entry.1 += 1;
+                            }
+                        }
+                    }
+
+                    // Team Stats (excluding solo games if desired, but user said "best teams")
+                    if team_members.len() > 1 {
+                        team_members.sort_by(|a, b| a.id.cmp(&b.id));
+                        let team_key = team_members
+                            .iter()
+                            .map(|p| p.id.clone())
+                            .collect::<Vec<_>>()
+                            .join(",");
+                        // We need to store the resolved members, so we use the ones we just extracted
+                        let entry =
+                            team_stats
+                                .entry(team_key)
+                                .or_insert((team_members.clone(), 0, 0));
+                        entry.1 += player_score;
+                        entry.2 += 1;
+                    }
+
+                    // Score History
+                    score_history.push(ScorePoint {
+                        date: played_at_str.clone(),
+                        score: player_score,
+                        map_name: state.map_name.clone().unwrap_or_default(),
+                    });
+
+                    // Add to games list (simplified summary)
+                    // Reuse logic from get_games or just construct it
+                    let id_db: i64 = row.try_get("id").unwrap_or(0);
+                    let game_id_str: Option<String> = row.try_get("game_id").unwrap_or_default();
+                    let map_name_str: Option<String> = row.try_get("map_name").unwrap_or_default();
+                    let round_time_val: Option<i64> = row.try_get("round_time").unwrap_or_default();
+                    let total_duration_val: Option<i64> =
+                        row.try_get("total_duration").unwrap_or_default();
+
+                    // Extract Country Codes
+                    let mut country_codes = Vec::new();
+                    let mut round_count = 0;
+                    if let Some(rounds) = &state.rounds {
+                        round_count = rounds.len() as i64;
+                        for round in rounds {
+                            if let Some(cc) = round
+                                .panorama
+                                .as_ref()
+                                .and_then(|p| p.country_code.as_ref())
+                            {
+                                country_codes.push(cc.clone());
+                            }
+                        }
+                    }
+
+                    // Re-extract team members for the summary (already have them)
+
+                    player_games.push(GameSummary {
+                        id: id_db,
+                        game_id: game_id_str,
+                        map_name: map_name_str,
+                        score: Some(player_score as i64),
+                        round_time: round_time_val,
+                        round_count,
+                        total_duration: total_duration_val,
+                        played_at: played_at_str,
+                        players: team_members.clone(), // Use the ones extracted above
+                        country_codes,
+                        max_score: round_count * 5000,
+                        is_finished,
+                        game_mode: game_mode(state),
+                        annotations: GameAnnotations::from_row(&row, &tags),
+                    });
+                }
+            }
+        }
+    }
+
+    // Process Country Stats
+    let mut all_countries: Vec<CountryStat> = country_stats
+        .into_iter()
+        .map(|(code, (total, count))| CountryStat {
+            country_code: code,
+            total_score: total,
+            count,
+            average: if count > 0 {
+                total as f64 / count as f64
+            } else {
+                0.0
+            },
+        })
+        .collect();
+
+    // Sort for Best (Avg DESC)
+    all_countries.sort_by(|a, b| {
+        b.average
+            .partial_cmp(&a.average)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let best_countries = all_countries.iter().take(3).cloned().collect();
+
+    // Sort for Worst (Avg ASC)
+    all_countries.sort_by(|a, b| {
+        a.average
+            .partial_cmp(&b.average)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let worst_countries = all_countries.iter().take(3).cloned().collect();
+
+    // Process Team Stats
+    let mut best_teams: Vec<TeamStatSimple> = team_stats
+        .into_iter()
+        .map(|(_key, (members, total, count))| TeamStatSimple {
+            team_name: members
+                .iter()
+                .map(|p| p.name.clone())
+                .collect::<Vec<_>>()
+                .join(", "),
+            members,
+            average_score: if count > 0 {
+                total as f64 / count as f64
+            } else {
+                0.0
+            },
+            games_played: count,
+        })
+        .collect();
+    best_teams.sort_by(|a, b| {
+        b.average_score
+            .partial_cmp(&a.average_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let player_name = names.get(&effective_primary_id).cloned();
+
+    let mut average_score = if total_games > 0 {
+        total_score as f64 / total_games as f64
+    } else {
+        0.0
+    };
+    // Without a map or season filter the headline numbers come from the aggregates
+    if params.map.is_none() && window.is_none() {
+        let totals = aggregates::player_totals(pool, &effective_primary_id, params)
+            .await
+            .map_err(|e| {
+                error!("Failed to read the totals of a player: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        total_games = totals.games as i32;
+        average_score = totals.average_score();
+        total_duration = totals.duration;
+    }
+
+    Ok(PlayerStatsDetailed {
+        player_id: effective_primary_id, // Return the primary ID
+        total_games,
+        average_score,
+        total_duration,
+        best_countries,
+        worst_countries,
+        best_teams,
+        score_history,
+        player_name,
+        games: player_games,
+    })
+}
@@ -0,0 +1,270 @@
+//! Seasons and their standings.
+
+use crate::storage::backend::storage;
+use crate::{
+    model::{game_played_at, parse_payload},
+    stats::{compute_team_leaderboard, parse_timestamp, SeasonWindow, StatsQuery, TeamStats},
+    storage::names::display_names,
+};
+use axum::http::StatusCode;
+use serde::Serialize;
+use sqlx::{any::AnyRow, AnyPool, Row};
+use tracing::error;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct Season {
+    pub id: i64,
+    pub name: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub closed_at: Option<String>,
+    pub created_at: String,
+}
+
+impl Season {
+    pub fn from_row(row: &AnyRow) -> Self {
+        Season {
+            id: row.get("id"),
+            name: row.get("name"),
+            starts_at: row.get("starts_at"),
+            ends_at: row.get("ends_at"),
+            closed_at: row.try_get("closed_at").unwrap_or_default(),
+            created_at: row.get("created_at"),
+        }
+    }
+
+    pub fn window(&self) -> Option<SeasonWindow> {
+        Some(SeasonWindow {
+            start: parse_timestamp(&self.starts_at)?,
+            end: parse_timestamp(&self.ends_at)?,
+        })
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PlayerStanding {
+    pub player_id: String,
+    pub name: String,
+    pub games_played: i32,
+    pub average_score: f64,
+    pub total_score: i32,
+    pub total_duration: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SeasonStandings {
+    pub season: Season,
+    pub is_snapshot: bool,
+    pub teams: Vec<TeamStats>,
+    pub players: Vec<PlayerStanding>,
+}
+
+pub async fn fetch_season(pool: &AnyPool, id: i64) -> Result<Option<Season>, StatusCode> {
+    sqlx::query(
+        "SELECT id, name, starts_at, ends_at, closed_at, created_at FROM seasons WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map(|row| row.as_ref().map(Season::from_row))
+    .map_err(|e| {
+        error!("Failed to fetch season {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Standings of a season: the stored snapshot once closed, computed live otherwise.
+pub async fn fetch_season_standings(
+    pool: &AnyPool,
+    season: Season,
+    include_excluded: Option<bool>,
+) -> Result<SeasonStandings, StatusCode> {
+    if season.closed_at.is_none() {
+        let window = season.window().ok_or_else(|| {
+            error!("Season {} has invalid dates", season.id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let params = StatsQuery {
+            exclude_abandons: None,
+            map: None,
+            score_type: None,
+            season: Some(season.id),
+            include_excluded,
+        };
+        let teams = compute_team_leaderboard(pool, &params, Some(window)).await?;
+        let players = compute_player_standings(pool, &params, Some(window)).await;
+        return Ok(SeasonStandings {
+            season,
+            is_snapshot: false,
+            teams,
+            players,
+        });
+    }
+
+    let rows = sqlx::query(
+        "SELECT kind, standing_key, name, members, games_played, total_score, average_score, total_duration
+         FROM season_standings WHERE season_id = $1 ORDER BY kind, standing_rank",
+    )
+    .bind(season.id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch standings of season {}: {}", season.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut teams = Vec::new();
+    let mut players = Vec::new();
+    for row in rows {
+        let kind: String = row.get("kind");
+        let games_played = row.get::<i64, _>("games_played") as i32;
+        let total_score = row.get::<i64, _>("total_score") as i32;
+        let average_score: f64 = row.get("average_score");
+        let total_duration: i64 = row.get("total_duration");
+
+        if kind == "team" {
+            let members: String = row.get("members");
+            teams.push(TeamStats {
+                team_name: row.get("name"),
+                members: serde_json::from_str(&members).unwrap_or_default(),
+                games_played,
+                average_score,
+                total_score,
+                total_duration,
+            });
+        } else {
+            players.push(PlayerStanding {
+                player_id: row.get("standing_key"),
+                name: row.get("name"),
+                games_played,
+                average_score,
+                total_score,
+                total_duration,
+            });
+        }
+    }
+
+    Ok(SeasonStandings {
+        season,
+        is_snapshot: true,
+        teams,
+        players,
+    })
+}
+
+/// Per-player ranking (personal score, aliases merged) over the games of a window.
+pub async fn compute_player_standings(
+    pool: &AnyPool,
+    params: &StatsQuery,
+    window: Option<SeasonWindow>,
+) -> Vec<PlayerStanding> {
+    let alias_map: std::collections::HashMap<String, String> =
+        sqlx::query("SELECT alias_id, primary_id FROM player_aliases")
+            .fetch_all(pool)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|row| (row.get("alias_id"), row.get("primary_id")))
+            .collect();
+
+    let names = display_names(pool).await.unwrap_or_default();
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM games WHERE {}",
+        storage(pool).payload_columns(),
+        params.counted_games()
+    ))
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    // Primary ID -> (Total Score, Count, Total Duration)
+    let mut player_stats: std::collections::HashMap<String, (i32, i32, i64)> =
+        std::collections::HashMap::new();
+
+    for row in rows {
+        let data_str: Option<String> = row.get("data");
+        let Some(payload) = data_str.as_deref().and_then(|d| parse_payload(d).ok()) else {
+            continue;
+        };
+        let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) else {
+            continue;
+        };
+        let Some(players) = &state.players else {
+            continue;
+        };
+
+        if let Some(window) = &window {
+            let played_at = game_played_at(state, row.try_get("played_at").unwrap_or_default());
+            if !window.contains(&played_at) {
+                continue;
+            }
+        }
+
+        let round_score: i32 = state
+            .rounds
+            .as_ref()
+            .map(|rounds| {
+                rounds
+                    .iter()
+                    .filter_map(|r| r.score.as_ref().and_then(|s| s.points))
+                    .sum()
+            })
+            .unwrap_or(0);
+        let duration = payload.total_duration.unwrap_or(0) as i64;
+
+        let mut counted: std::collections::HashSet<String> = std::collections::HashSet::new();
+        for p in players {
+            let Some(pid) = &p.player_id else {
+                continue;
+            };
+            let primary_id = alias_map.get(pid).cloned().unwrap_or_else(|| pid.clone());
+            if !counted.insert(primary_id.clone()) {
+                continue;
+            }
+
+            let score = match &p.guesses {
+                Some(guesses) => guesses
+                    .iter()
+                    .filter_map(|g| g.score.as_ref().and_then(|s| s.points))
+                    .sum(),
+                None => round_score,
+            };
+
+            let entry = player_stats.entry(primary_id).or_insert((0, 0, 0));
+            entry.0 += score;
+            entry.1 += 1;
+            entry.2 += duration;
+        }
+    }
+
+    let mut standings: Vec<PlayerStanding> = player_stats
+        .into_iter()
+        .map(
+            |(player_id, (total_score, count, total_duration))| PlayerStanding {
+                name: names
+                    .get(&player_id)
+                    .cloned()
+                    .unwrap_or_else(|| player_id.clone()),
+                player_id,
+                games_played: count,
+                average_score: if count > 0 {
+                    total_score as f64 / count as f64
+                } else {
+                    0.0
+                },
+                total_score,
+                total_duration,
+            },
+        )
+        .collect();
+
+    standings.sort_by(|a, b| {
+        b.average_score
+            .partial_cmp(&a.average_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    standings
+}
@@ -0,0 +1,343 @@
+//! Per-team statistics.
+
+use crate::storage::backend::storage;
+use crate::{
+    model::{game_mode, PlayerInfo},
+    stats::{
+        aggregates, game_played_at, games::GameSummary, parse_payload, players::ScorePoint,
+        resolve_season_window, CountryStat, StatsQuery,
+    },
+    storage::{
+        annotations::{fetch_game_tags, GameAnnotations, ANNOTATION_COLUMNS},
+        names::display_names,
+    },
+};
+use axum::http::StatusCode;
+use serde::Serialize;
+use sqlx::{AnyPool, Row};
+use tracing::error;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct TeamStatsDetailed {
+    pub team_id: String,
+    pub team_name: String,
+    pub members: Vec<PlayerInfo>,
+    pub total_games: i32,
+    pub average_score: f64,
+    pub total_duration: i64,
+    pub best_countries: Vec<CountryStat>,
+    pub worst_countries: Vec<CountryStat>,
+    pub score_history: Vec<ScorePoint>,
+    pub games: Vec<GameSummary>,
+}
+
+/// Detailed statistics of the team made of the comma separated player ids.
+pub async fn team_stats(
+    pool: &AnyPool,
+    id: String,
+    params: &StatsQuery,
+) -> Result<TeamStatsDetailed, StatusCode> {
+    let window = resolve_season_window(pool, params.season).await?;
+
+    // 1. Fetch Alias Map
+    let aliases = sqlx::query("SELECT alias_id, primary_id FROM player_aliases")
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+
+    let mut alias_map: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    for row in aliases {
+        let alias_id: String = row.get("alias_id");
+        let primary_id: String = row.get("primary_id");
+        alias_map.insert(alias_id, primary_id);
+    }
+
+    // 2. Resolve Requested Team IDs to Primary IDs
+    let team_player_ids: Vec<String> = id
+        .split(',')
+        .map(|s| {
+            let trimmed = s.trim().to_string();
+            alias_map.get(&trimmed).cloned().unwrap_or(trimmed)
+        })
+        .collect();
+
+    let mut team_player_ids_sorted = team_player_ids.clone();
+    team_player_ids_sorted.sort();
+    // Deduplicate in case multiple aliases of the same person were requested
+    team_player_ids_sorted.dedup();
+
+    // Postgres only sends back the games where every member played, found through the players index
+    let mut conditions = vec![params.counted_games().to_string()];
+    let mut binds = Vec::new();
+    // Players without an id cannot be looked up, the scan below still checks them
+    for member in team_player_ids_sorted.iter().filter(|m| !m.is_empty()) {
+        let mut ids = vec![member.clone()];
+        ids.extend(
+            alias_map
+                .iter()
+                .filter(|(_, primary)| *primary == member)
+                .map(|(alias, _)| alias.clone()),
+        );
+        storage(pool).push_player_filter(&ids, &mut conditions, &mut binds);
+    }
+    let sql = format!(
+        "SELECT id, game_id, map_name, score, round_time, total_duration, {}, {} FROM games WHERE {} ORDER BY played_at DESC",
+        storage(pool).payload_columns(),
+        ANNOTATION_COLUMNS,
+        conditions.join(" AND ")
+    );
+    let rows = binds
+        .iter()
+        .fold(sqlx::query(&sql), |query, value| query.bind(value))
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+    let tags = fetch_game_tags(pool).await.unwrap_or_default();
+
+    let mut total_games = 0;
+    let mut total_score = 0;
+    let mut total_duration = 0;
+    let mut country_stats: std::collections::HashMap<String, (i32, i32)> =
+        std::collections::HashMap::new();
+    let mut score_history: Vec<ScorePoint> = Vec::new();
+    let mut team_games: Vec<GameSummary> = Vec::new();
+    let mut team_members_info: Vec<PlayerInfo> = Vec::new();
+
+    let names = display_names(pool).await.unwrap_or_default();
+
+    for row in rows {
+        let data_str: Option<String> = row.get("data");
+        if let Some(data_str) = data_str {
+            if let Ok(payload) = parse_payload(&data_str) {
+                if let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) {
+                    // Filter by Map
+                    if let Some(map_filter) = &params.map {
+                        if let Some(map_name) = &state.map_name {
+                            if !map_name.to_lowercase().contains(&map_filter.to_lowercase()) {
+                                continue;
+                            }
+                        } else {
+                            continue;
+                        }
+                    }
+
+                    // Check if this game matches the team
+                    if let Some(players) = &state.players {
+                        // Resolve game players to Primary IDs
+                        let mut game_player_ids: Vec<String> = players
+                            .iter()
+                            .map(|p| {
+                                let pid = p.player_id.clone().unwrap_or_default();
+                                alias_map.get(&pid).cloned().unwrap_or(pid)
+                            })
+                            .collect();
+                        game_player_ids.sort();
+                        game_player_ids.dedup();
+
+                        if game_player_ids != team_player_ids_sorted {
+                            continue;
+                        }
+
+                        // Members as of the first matching game, under their primary ids
+                        if team_members_info.is_empty() {
+                            team_members_info = players
+                                .iter()
+                                .map(|p| {
+                                    let pid = p.player_id.clone().unwrap_or_default();
+                                    // Try to get the name of the PRIMARY ID if it's an alias
+                                    let primary_id =
+                                        alias_map.get(&pid).cloned().unwrap_or(pid.clone());
+
+                                    let name = names
+                                        .get(&primary_id)
+                                        .cloned()
+                                        .or_else(|| p.nick.clone())
+                                        .unwrap_or_else(|| "Unknown".to_string());
+
+                                    PlayerInfo {
+                                        id: primary_id, // Use Primary ID
+                                        name,
+                                    }
+                                })
+                                .collect();
+                        }
+                    } else {
+                        continue;
+                    }
+
+                    // Filter Abandons
+                    let is_finished = state
+                        .status
+                        .as_deref()
+                        .map(|s| s.eq_ignore_ascii_case("finished"))
+                        .unwrap_or(false);
+                    if params.exclude_abandons == Some(true) && !is_finished {
+                        continue;
+                    }
+
+                    let played_at_str =
+                        game_played_at(state, row.try_get("played_at").unwrap_or_default());
+                    if let Some(window) = &window {
+                        if !window.contains(&played_at_str) {
+                            continue;
+                        }
+                    }
+
+                    // Calculate Game Score
+                    let mut game_score = 0;
+                    if let Some(score) = payload
+                        .bullseye
+                        .as_ref()
+                        .and_then(|b| b.guess.as_ref())
+                        .and_then(|g| g.score.as_ref())
+                        .and_then(|s| s.points)
+                    {
+                        game_score = score;
+                    } else if let Some(rounds) = &state.rounds {
+                        game_score = rounds
+                            .iter()
+                            .filter_map(|r| r.score.as_ref().and_then(|s| s.points))
+                            .sum();
+                    }
+
+                    // --- Aggregation ---
+                    total_games += 1;
+                    total_score += game_score;
+                    total_duration += payload.total_duration.unwrap_or(0) as i64;
+
+                    // Country Stats
+                    if let Some(rounds) = &state.rounds {
+                        for round in rounds {
+                            if let Some(cc) = round
+                                .panorama
+                                .as_ref()
+                                .and_then(|p| p.country_code.as_ref())
+                            {
+                                let points =
+                                    round.score.as_ref().and_then(|s| s.points).unwrap_or(0);
+                                let entry =
+                                    country_stats.entry(cc.to_lowercase()).or_insert((0, 0));
+                                entry.0 += points;
+                                entry.1 += 1;
+                            }
+                        }
+                    }
+
+                    // Score History
+                    score_history.push(ScorePoint {
+                        date: played_at_str.clone(),
+                        score: game_score,
+                        map_name: state.map_name.clone().unwrap_or_default(),
+                    });
+
+                    // Add to games list
+                    let id_db: i64 = row.try_get("id").unwrap_or(0);
+                    let game_id_str: Option<String> = row.try_get("game_id").unwrap_or_default();
+                    let map_name_str: Option<String> = row.try_get("map_name").unwrap_or_default();
+                    let round_time_val: Option<i64> = row.try_get("round_time").unwrap_or_default();
+                    let total_duration_val: Option<i64> =
+                        row.try_get("total_duration").unwrap_or_default();
+
+                    let mut country_codes = Vec::new();
+                    let mut round_count = 0;
+                    if let Some(rounds) = &state.rounds {
+                        round_count = rounds.len() as i64;
+                        for round in rounds {
+                            if let Some(cc) = round
+                                .panorama
+                                .as_ref()
+                                .and_then(|p| p.country_code.as_ref())
+                            {
+                                country_codes.push(cc.clone());
+                            }
+                        }
+                    }
+
+                    team_games.push(GameSummary {
+                        id: id_db,
+                        game_id: game_id_str,
+                        map_name: map_name_str,
+                        score: Some(game_score as i64),
+                        round_time: round_time_val,
+                        round_count,
+                        total_duration: total_duration_val,
+                        played_at: played_at_str,
+                        players: team_members_info.clone(),
+                        country_codes,
+                        max_score: round_count * 5000,
+                        is_finished,
+                        game_mode: game_mode(state),
+                        annotations: GameAnnotations::from_row(&row, &tags),
+                    });
+                }
+            }
+        }
+    }
+
+    // Process Country Stats
+    let mut all_countries: Vec<CountryStat> = country_stats
+        .into_iter()
+        .map(|(code, (total, count))| CountryStat {
+            country_code: code,
+            total_score: total,
+            count,
+            average: if count > 0 {
+                total as f64 / count as f64
+            } else {
+                0.0
+            },
+        })
+        .collect();
+
+    all_countries.sort_by(|a, b| {
+        b.average
+            .partial_cmp(&a.average)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let best_countries = all_countries.iter().take(3).cloned().collect();
+
+    all_countries.sort_by(|a, b| {
+        a.average
+            .partial_cmp(&b.average)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let worst_countries = all_countries.iter().take(3).cloned().collect();
+
+    let team_name = team_members_info
+        .iter()
+        .map(|p| p.name.clone())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut average_score = if total_games > 0 {
+        total_score as f64 / total_games as f64
+    } else {
+        0.0
+    };
+    // Without a map or season filter the headline numbers come from the aggregates
+    if params.map.is_none() && window.is_none() {
+        let totals = aggregates::team_totals(pool, &team_player_ids_sorted.join(","), params)
+            .await
+            .map_err(|e| {
+                error!("Failed to read the totals of a team: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        total_games = totals.games as i32;
+        average_score = totals.average_score();
+        total_duration = totals.duration;
+    }
+
+    Ok(TeamStatsDetailed {
+        team_id: id,
+        team_name,
+        members: team_members_info,
+        total_games,
+        average_score,
+        total_duration,
+        best_countries,
+        worst_countries,
+        score_history,
+        games: team_games,
+    })
+}
@@ -0,0 +1,397 @@
+//! Pre-aggregated statistics: running totals per map, country, team and player.
+//!
+//! The totals of a game are added when it is stored or restored and removed when it is
+//! trashed; changing its excluded flag moves them. Teams and players are keyed by primary
+//! ids, so alias changes rebuild the tables with `recompute_aggregates`.
+
+use crate::{
+    model::parse_payload,
+    storage::{
+        audit::{record_audit, AuditEvent},
+        backend::storage_for_connection,
+        get_nullable,
+    },
+};
+use serde::Serialize;
+use sqlx::{any::AnyRow, AnyConnection, AnyPool, Row};
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Tables derived from `games` and `player_aliases`; backups leave them out.
+pub const AGGREGATE_TABLES: [&str; 4] = [
+    "stats_maps",
+    "stats_countries",
+    "stats_teams",
+    "stats_players",
+];
+
+#[derive(Serialize, ToSchema)]
+pub struct RecomputeReport {
+    /// Games outside the trash the totals were rebuilt from
+    pub games: usize,
+}
+
+#[derive(Default)]
+struct MapTotals {
+    games: i64,
+    scored_games: i64,
+    score_sum: i64,
+    duration_sum: i64,
+}
+
+#[derive(Default)]
+struct CountryTotals {
+    rounds: i64,
+    points: i64,
+}
+
+#[derive(Default)]
+struct TeamTotals {
+    games: i64,
+    score_sum: i64,
+    duration_sum: i64,
+}
+
+#[derive(Default)]
+struct PlayerTotals {
+    games: i64,
+    personal_score_sum: i64,
+    game_score_sum: i64,
+    duration_sum: i64,
+}
+
+/// Changes to apply to the aggregate tables, keyed like them (`finished`, `excluded` last).
+#[derive(Default)]
+struct Aggregates {
+    maps: HashMap<(String, i64, i64), MapTotals>,
+    countries: HashMap<(String, String, i64, i64), CountryTotals>,
+    teams: HashMap<(String, i64, i64), TeamTotals>,
+    players: HashMap<(String, i64, i64), PlayerTotals>,
+}
+
+impl Aggregates {
+    /// Adds (`sign` 1) or removes (`sign` -1) the totals of a row of `games`, the same way the
+    /// stats scans count it.
+    fn add_game(&mut self, row: &AnyRow, aliases: &HashMap<String, String>, sign: i64) {
+        let excluded: i64 = row.try_get("excluded").unwrap_or(0);
+        let score = get_nullable::<i64>(row, "score").ok().flatten();
+        let duration = get_nullable::<i64>(row, "total_duration")
+            .ok()
+            .flatten()
+            .unwrap_or(0);
+        let data = get_nullable::<String>(row, "data").ok().flatten();
+        let payload = data.as_deref().and_then(|d| parse_payload(d).ok());
+        let state = payload
+            .as_ref()
+            .and_then(|p| p.bullseye.as_ref())
+            .and_then(|b| b.state.as_ref());
+
+        let finished = i64::from(
+            state
+                .and_then(|s| s.status.as_deref())
+                .is_some_and(|s| s.eq_ignore_ascii_case("finished")),
+        );
+        let map_name = state.and_then(|s| s.map_name.clone()).unwrap_or_default();
+
+        let totals = self
+            .maps
+            .entry((map_name.clone(), finished, excluded))
+            .or_default();
+        totals.games += sign;
+        if let Some(score) = score {
+            totals.scored_games += sign;
+            totals.score_sum += sign * score;
+        }
+        totals.duration_sum += sign * duration;
+
+        let (Some(payload), Some(state)) = (&payload, state) else {
+            return;
+        };
+
+        let rounds = state.rounds.as_deref().unwrap_or_default();
+        for round in rounds {
+            if let Some(cc) = round
+                .panorama
+                .as_ref()
+                .and_then(|p| p.country_code.as_ref())
+            {
+                let points = round.score.as_ref().and_then(|s| s.points).unwrap_or(0);
+                let totals = self
+                    .countries
+                    .entry((map_name.clone(), cc.to_lowercase(), finished, excluded))
+                    .or_default();
+                totals.rounds += sign;
+                totals.points += sign * i64::from(points);
+            }
+        }
+
+        let rounds_score: i64 = rounds
+            .iter()
+            .filter_map(|r| r.score.as_ref().and_then(|s| s.points))
+            .map(i64::from)
+            .sum();
+        let game_score = payload
+            .bullseye
+            .as_ref()
+            .and_then(|b| b.guess.as_ref())
+            .and_then(|g| g.score.as_ref())
+            .and_then(|s| s.points)
+            .map(i64::from)
+            .unwrap_or(rounds_score);
+        let payload_duration = i64::from(payload.total_duration.unwrap_or(0));
+
+        let players = state.players.as_deref().unwrap_or_default();
+        let primary_of = |id: &str| aliases.get(id).cloned().unwrap_or_else(|| id.to_string());
+
+        if !players.is_empty() {
+            let mut team: Vec<String> = players
+                .iter()
+                .map(|p| primary_of(p.player_id.as_deref().unwrap_or_default()))
+                .collect();
+            team.sort();
+            team.dedup();
+            let totals = self
+                .teams
+                .entry((team.join(","), finished, excluded))
+                .or_default();
+            totals.games += sign;
+            totals.score_sum += sign * game_score;
+            totals.duration_sum += sign * payload_duration;
+        }
+
+        // A player counts once per game, with the first of their ids found in it
+        let mut seen = HashSet::new();
+        for player in players {
+            let Some(player_id) = &player.player_id else {
+                continue;
+            };
+            let primary_id = primary_of(player_id);
+            if !seen.insert(primary_id.clone()) {
+                continue;
+            }
+            let personal_score = match &player.guesses {
+                Some(guesses) => guesses
+                    .iter()
+                    .filter_map(|g| g.score.as_ref().and_then(|s| s.points))
+                    .map(i64::from)
+                    .sum(),
+                None => rounds_score,
+            };
+            let totals = self
+                .players
+                .entry((primary_id, finished, excluded))
+                .or_default();
+            totals.games += sign;
+            totals.personal_score_sum += sign * personal_score;
+            totals.game_score_sum += sign * rounds_score;
+            totals.duration_sum += sign * payload_duration;
+        }
+    }
+
+    /// Adds the changes to the tables, dropping the rows left without games.
+    async fn write(&self, conn: &mut AnyConnection) -> Result<(), sqlx::Error> {
+        for ((map_name, finished, excluded), totals) in &self.maps {
+            sqlx::query(
+                "INSERT INTO stats_maps (map_name, finished, excluded, games, scored_games, score_sum, duration_sum)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (map_name, finished, excluded) DO UPDATE SET
+                     games = stats_maps.games + excluded.games,
+                     scored_games = stats_maps.scored_games + excluded.scored_games,
+                     score_sum = stats_maps.score_sum + excluded.score_sum,
+                     duration_sum = stats_maps.duration_sum + excluded.duration_sum",
+            )
+            .bind(map_name)
+            .bind(finished)
+            .bind(excluded)
+            .bind(totals.games)
+            .bind(totals.scored_games)
+            .bind(totals.score_sum)
+            .bind(totals.duration_sum)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        for ((map_name, country_code, finished, excluded), totals) in &self.countries {
+            sqlx::query(
+                "INSERT INTO stats_countries (map_name, country_code, finished, excluded, rounds, points)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (map_name, country_code, finished, excluded) DO UPDATE SET
+                     rounds = stats_countries.rounds + excluded.rounds,
+                     points = stats_countries.points + excluded.points",
+            )
+            .bind(map_name)
+            .bind(country_code)
+            .bind(finished)
+            .bind(excluded)
+            .bind(totals.rounds)
+            .bind(totals.points)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        for ((team_key, finished, excluded), totals) in &self.teams {
+            sqlx::query(
+                "INSERT INTO stats_teams (team_key, finished, excluded, games, score_sum, duration_sum)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (team_key, finished, excluded) DO UPDATE SET
+                     games = stats_teams.games + excluded.games,
+                     score_sum = stats_teams.score_sum + excluded.score_sum,
+                     duration_sum = stats_teams.duration_sum + excluded.duration_sum",
+            )
+            .bind(team_key)
+            .bind(finished)
+            .bind(excluded)
+            .bind(totals.games)
+            .bind(totals.score_sum)
+            .bind(totals.duration_sum)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        for ((player_id, finished, excluded), totals) in &self.players {
+            sqlx::query(
+                "INSERT INTO stats_players (player_id, finished, excluded, games, personal_score_sum, game_score_sum, duration_sum)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (player_id, finished, excluded) DO UPDATE SET
+                     games = stats_players.games + excluded.games,
+                     personal_score_sum = stats_players.personal_score_sum + excluded.personal_score_sum,
+                     game_score_sum = stats_players.game_score_sum + excluded.game_score_sum,
+                     duration_sum = stats_players.duration_sum + excluded.duration_sum",
+            )
+            .bind(player_id)
+            .bind(finished)
+            .bind(excluded)
+            .bind(totals.games)
+            .bind(totals.personal_score_sum)
+            .bind(totals.game_score_sum)
+            .bind(totals.duration_sum)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        for statement in [
+            "DELETE FROM stats_maps WHERE games <= 0",
+            "DELETE FROM stats_countries WHERE rounds <= 0",
+            "DELETE FROM stats_teams WHERE games <= 0",
+            "DELETE FROM stats_players WHERE games <= 0",
+        ] {
+            sqlx::query(statement).execute(&mut *conn).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn fetch_alias_map(conn: &mut AnyConnection) -> Result<HashMap<String, String>, sqlx::Error> {
+    Ok(
+        sqlx::query("SELECT alias_id, primary_id FROM player_aliases")
+            .fetch_all(conn)
+            .await?
+            .into_iter()
+            .map(|row| (row.get("alias_id"), row.get("primary_id")))
+            .collect(),
+    )
+}
+
+fn game_columns(conn: &AnyConnection) -> String {
+    format!(
+        "score, total_duration, excluded, {}",
+        storage_for_connection(conn).json_column("data")
+    )
+}
+
+async fn apply_game(conn: &mut AnyConnection, id: i64, sign: i64) -> Result<(), sqlx::Error> {
+    let Some(row) = sqlx::query(&format!(
+        "SELECT {} FROM games WHERE id = $1",
+        game_columns(conn)
+    ))
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let aliases = fetch_alias_map(conn).await?;
+    let mut aggregates = Aggregates::default();
+    aggregates.add_game(&row, &aliases, sign);
+    aggregates.write(conn).await
+}
+
+/// Adds a game to the totals, when it is stored or comes back from the trash.
+pub async fn add_game_to_stats(conn: &mut AnyConnection, id: i64) -> Result<(), sqlx::Error> {
+    apply_game(conn, id, 1).await
+}
+
+/// Removes a game from the totals, before it goes to the trash or its excluded flag changes.
+pub async fn remove_game_from_stats(conn: &mut AnyConnection, id: i64) -> Result<(), sqlx::Error> {
+    apply_game(conn, id, -1).await
+}
+
+/// Rebuilds every aggregate table from the games outside the trash; returns how many there are.
+pub async fn recompute_aggregates(conn: &mut AnyConnection) -> Result<usize, sqlx::Error> {
+    for table in AGGREGATE_TABLES {
+        sqlx::query(&format!("DELETE FROM {}", table))
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM games WHERE deleted_at IS NULL",
+        game_columns(conn)
+    ))
+    .fetch_all(&mut *conn)
+    .await?;
+    let aliases = fetch_alias_map(conn).await?;
+
+    let mut aggregates = Aggregates::default();
+    for row in &rows {
+        aggregates.add_game(row, &aliases, 1);
+    }
+    aggregates.write(conn).await?;
+    Ok(rows.len())
+}
+
+/// `recompute-stats` and `/api/admin/stats/recompute`: rebuilds the totals and records it.
+pub async fn rebuild_aggregates(
+    pool: &AnyPool,
+    actor: &str,
+) -> Result<RecomputeReport, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let games = recompute_aggregates(&mut tx).await?;
+    record_audit(
+        &mut tx,
+        actor,
+        AuditEvent {
+            action: "stats.recompute",
+            target_type: "stats",
+            target_id: "aggregates".to_string(),
+            before: None,
+            after: Some(serde_json::json!({ "games": games })),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(RecomputeReport { games })
+}
+
+/// Fills the tables of a database migrated from a version without them.
+pub async fn ensure_aggregates(pool: &AnyPool) -> Result<(), sqlx::Error> {
+    let built = sqlx::query("SELECT 1 FROM stats_maps LIMIT 1")
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+    let has_games = sqlx::query("SELECT 1 FROM games WHERE deleted_at IS NULL LIMIT 1")
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+    if built || !has_games {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    let games = recompute_aggregates(&mut tx).await?;
+    tx.commit().await?;
+    info!("Built the stats aggregates from {} games", games);
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//! Admin annotations on games: exclusion, notes and tags.
+
+use crate::storage::{
+    aggregates::{add_game_to_stats, remove_game_from_stats},
+    audit::{record_audit, AuditEvent},
+    get_nullable,
+    version::bump_data_version,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{any::AnyRow, AnyPool, Row};
+use utoipa::ToSchema;
+
+/// Annotation columns of `games`, selected alongside the game columns.
+pub const ANNOTATION_COLUMNS: &str = "excluded, excluded_reason, notes";
+
+pub const MAX_TAG_CHARS: usize = 32;
+
+pub const MAX_NOTES_CHARS: usize = 4000;
+
+/// Manual flags and remarks attached to a game.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct GameAnnotations {
+    /// Left out of the stats and leaderboards unless `include_excluded=true`
+    pub excluded: bool,
+    pub excluded_reason: Option<String>,
+    pub notes: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl GameAnnotations {
+    pub fn from_row(row: &AnyRow, tags: &std::collections::HashMap<i64, Vec<String>>) -> Self {
+        let id: i64 = row.try_get("id").unwrap_or_default();
+        GameAnnotations {
+            excluded: row.try_get::<i64, _>("excluded").unwrap_or_default() != 0,
+            excluded_reason: get_nullable(row, "excluded_reason").unwrap_or_default(),
+            notes: get_nullable(row, "notes").unwrap_or_default(),
+            tags: tags.get(&id).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// Partial update: fields left out keep their value, empty strings clear the text fields.
+#[derive(Deserialize, ToSchema)]
+pub struct GameAnnotationsUpdate {
+    pub excluded: Option<bool>,
+    pub excluded_reason: Option<String>,
+    pub notes: Option<String>,
+    /// Replaces the whole tag list
+    pub tags: Option<Vec<String>>,
+}
+
+/// Tags of every game, sorted by name.
+pub async fn fetch_game_tags(
+    conn: impl sqlx::Executor<'_, Database = sqlx::Any>,
+) -> Result<std::collections::HashMap<i64, Vec<String>>, sqlx::Error> {
+    let mut tags: std::collections::HashMap<i64, Vec<String>> = std::collections::HashMap::new();
+    for row in sqlx::query("SELECT game_id, tag FROM game_tags ORDER BY tag")
+        .fetch_all(conn)
+        .await?
+    {
+        tags.entry(row.try_get("game_id")?)
+            .or_default()
+            .push(row.try_get("tag")?);
+    }
+    Ok(tags)
+}
+
+/// Trims and lowercases tags, dropping duplicates.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized = std::collections::BTreeSet::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || tag.chars().count() > MAX_TAG_CHARS {
+            return Err(format!(
+                "tags must be between 1 and {} characters",
+                MAX_TAG_CHARS
+            ));
+        }
+        if tag.contains(',') {
+            return Err(format!("tag `{}` contains a comma", tag));
+        }
+        normalized.insert(tag);
+    }
+    Ok(normalized.into_iter().collect())
+}
+
+/// Empty strings clear a text field.
+pub fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+pub async fn fetch_annotations(
+    conn: &mut sqlx::AnyConnection,
+    id: i64,
+) -> Result<Option<GameAnnotations>, sqlx::Error> {
+    let Some(row) = sqlx::query(&format!(
+        "SELECT id, {} FROM games WHERE id = $1 AND deleted_at IS NULL",
+        ANNOTATION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let tags: Vec<String> =
+        sqlx::query_scalar("SELECT tag FROM game_tags WHERE game_id = $1 ORDER BY tag")
+            .bind(id)
+            .fetch_all(&mut *conn)
+            .await?;
+    Ok(Some(GameAnnotations {
+        tags,
+        ..GameAnnotations::from_row(&row, &std::collections::HashMap::new())
+    }))
+}
+
+/// Applies `update` to a game; returns the new annotations, or `None` if the game does not exist.
+pub async fn annotate_game(
+    pool: &AnyPool,
+    id: i64,
+    update: &GameAnnotationsUpdate,
+    actor: &str,
+) -> Result<Option<GameAnnotations>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let Some(before) = fetch_annotations(&mut tx, id).await? else {
+        return Ok(None);
+    };
+
+    let excluded = update.excluded.unwrap_or(before.excluded);
+    let mut after = GameAnnotations {
+        excluded,
+        // Un-excluding a game drops the reason with the flag
+        excluded_reason: match &update.excluded_reason {
+            _ if !excluded => None,
+            Some(reason) => non_empty(reason),
+            None => before.excluded_reason.clone(),
+        },
+        notes: match &update.notes {
+            Some(notes) => non_empty(notes),
+            None => before.notes.clone(),
+        },
+        tags: before.tags.clone(),
+    };
+
+    // The totals of the game move between the excluded and the counted ones
+    let moved = after.excluded != before.excluded;
+    if moved {
+        remove_game_from_stats(&mut tx, id).await?;
+    }
+    sqlx::query("UPDATE games SET excluded = $1, excluded_reason = $2, notes = $3 WHERE id = $4")
+        .bind(i64::from(after.excluded))
+        .bind(after.excluded_reason.clone())
+        .bind(after.notes.clone())
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    if moved {
+        add_game_to_stats(&mut tx, id).await?;
+    }
+    if let Some(tags) = &update.tags {
+        sqlx::query("DELETE FROM game_tags WHERE game_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        for tag in tags {
+            sqlx::query("INSERT INTO game_tags (game_id, tag) VALUES ($1, $2)")
+                .bind(id)
+                .bind(tag)
+                .execute(&mut *tx)
+                .await?;
+        }
+        after.tags = tags.clone();
+    }
+    bump_data_version(&mut *tx).await?;
+
+    record_audit(
+        &mut tx,
+        actor,
+        AuditEvent {
+            action: "game.annotate",
+            target_type: "game",
+            target_id: id.to_string(),
+            before: serde_json::to_value(&before).ok(),
+            after: serde_json::to_value(&after).ok(),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(after))
+}
@@ -0,0 +1,232 @@
+//! Audit log of admin actions and their undo.
+
+use crate::storage::{
+    players::{apply_link, apply_unlink, apply_unreject, current_primary, LinkError},
+    trash::{untrash_game, Untrash},
+};
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::{any::AnyRow, AnyPool, Row};
+use utoipa::ToSchema;
+
+/// One audited change; `before` and `after` hold what is needed to undo it.
+pub struct AuditEvent {
+    pub action: &'static str,
+    pub target_type: &'static str,
+    pub target_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+/// Appends to the audit log, normally inside the transaction of the audited change.
+pub async fn record_audit(
+    conn: &mut sqlx::AnyConnection,
+    actor: &str,
+    event: AuditEvent,
+) -> Result<i64, sqlx::Error> {
+    let to_text = |v: Option<serde_json::Value>| v.map(|v| v.to_string());
+    sqlx::query_scalar(
+        "INSERT INTO audit_log (created_at, actor, action, target_type, target_id, before_value, after_value)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING id",
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(actor)
+    .bind(event.action)
+    .bind(event.target_type)
+    .bind(event.target_id)
+    .bind(to_text(event.before))
+    .bind(to_text(event.after))
+    .fetch_one(conn)
+    .await
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub created_at: String,
+    pub actor: String,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub undone_at: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn from_row(row: &AnyRow) -> Self {
+        let json = |name: &str| {
+            row.try_get::<String, _>(name)
+                .ok()
+                .and_then(|v| serde_json::from_str(&v).ok())
+        };
+        AuditEntry {
+            id: row.get("id"),
+            created_at: row.get("created_at"),
+            actor: row.get("actor"),
+            action: row.get("action"),
+            target_type: row.get("target_type"),
+            target_id: row.get("target_id"),
+            before: json("before_value"),
+            after: json("after_value"),
+            undone_at: row.try_get("undone_at").unwrap_or_default(),
+        }
+    }
+}
+
+pub const AUDIT_COLUMNS: &str =
+    "id, created_at, actor, action, target_type, target_id, before_value, after_value, undone_at";
+
+pub enum UndoError {
+    NotFound,
+    AlreadyUndone,
+    NotUndoable(String),
+    Conflict(String),
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for UndoError {
+    fn from(e: sqlx::Error) -> Self {
+        UndoError::Database(e)
+    }
+}
+
+impl From<LinkError> for UndoError {
+    fn from(e: LinkError) -> Self {
+        match e {
+            LinkError::Database(e) => UndoError::Database(e),
+            other => UndoError::Conflict(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for UndoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UndoError::NotFound => write!(f, "audit entry not found"),
+            UndoError::AlreadyUndone => write!(f, "this action was already undone"),
+            UndoError::NotUndoable(action) => write!(f, "{} cannot be undone", action),
+            UndoError::Conflict(reason) => write!(f, "cannot undo: {}", reason),
+            UndoError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+/// Reverts an alias link, an unlink, a rejected alias suggestion or a game deletion recorded in
+/// the audit log.
+pub async fn undo_audit_entry(
+    pool: &AnyPool,
+    id: i64,
+    actor: &str,
+) -> Result<AuditEntry, UndoError> {
+    let mut tx = pool.begin().await?;
+
+    let entry = sqlx::query(&format!(
+        "SELECT {} FROM audit_log WHERE id = $1",
+        AUDIT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .map(|row| AuditEntry::from_row(&row))
+    .ok_or(UndoError::NotFound)?;
+    if entry.undone_at.is_some() {
+        return Err(UndoError::AlreadyUndone);
+    }
+
+    let primary_of = |value: &Option<serde_json::Value>| {
+        value
+            .as_ref()
+            .and_then(|v| v.get("primary_id"))
+            .and_then(|p| p.as_str())
+            .map(str::to_string)
+    };
+    let alias_id = entry.target_id.as_str();
+
+    match entry.action.as_str() {
+        "player.link" => {
+            let current = current_primary(&mut tx, alias_id).await?;
+            if current != primary_of(&entry.after) {
+                return Err(UndoError::Conflict(
+                    "the link was changed since".to_string(),
+                ));
+            }
+            match primary_of(&entry.before) {
+                Some(previous) => {
+                    apply_link(&mut tx, alias_id, &previous).await?;
+                }
+                None => {
+                    apply_unlink(&mut tx, alias_id).await?;
+                }
+            }
+        }
+        "player.unlink" => {
+            if current_primary(&mut tx, alias_id).await?.is_some() {
+                return Err(UndoError::Conflict(
+                    "the player was linked again since".to_string(),
+                ));
+            }
+            let previous = primary_of(&entry.before)
+                .ok_or_else(|| UndoError::NotUndoable(entry.action.clone()))?;
+            apply_link(&mut tx, alias_id, &previous).await?;
+        }
+        "alias.reject" => {
+            let pair = entry
+                .after
+                .as_ref()
+                .and_then(|v| v.get("rejected_pair"))
+                .and_then(|p| p.as_array())
+                .and_then(|p| Some((p.first()?.as_str()?, p.get(1)?.as_str()?)))
+                .ok_or_else(|| UndoError::NotUndoable(entry.action.clone()))?;
+            if !apply_unreject(&mut tx, pair.0, pair.1).await? {
+                return Err(UndoError::Conflict("the pair was linked since".to_string()));
+            }
+        }
+        "game.delete" => {
+            let id = entry
+                .target_id
+                .parse()
+                .map_err(|_| UndoError::NotUndoable(entry.action.clone()))?;
+            match untrash_game(&mut tx, id).await? {
+                Untrash::Restored => {}
+                Untrash::NotInTrash => {
+                    return Err(UndoError::Conflict(
+                        "the game was restored or purged since".to_string(),
+                    ))
+                }
+                Untrash::StoredAgain => {
+                    return Err(UndoError::Conflict(
+                        "the game was submitted again since".to_string(),
+                    ))
+                }
+            }
+        }
+        other => return Err(UndoError::NotUndoable(other.to_string())),
+    }
+
+    let undone_at = Utc::now().to_rfc3339();
+    sqlx::query("UPDATE audit_log SET undone_at = $1 WHERE id = $2")
+        .bind(&undone_at)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    record_audit(
+        &mut tx,
+        actor,
+        AuditEvent {
+            action: "audit.undo",
+            target_type: "audit",
+            target_id: id.to_string(),
+            before: None,
+            after: Some(serde_json::json!({ "undone_action": entry.action })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(AuditEntry {
+        undone_at: Some(undone_at),
+        ..entry
+    })
+}
@@ -0,0 +1,351 @@
+//! What differs between the supported databases.
+//!
+//! Queries stay portable SQL run through `AnyPool` (`$n` placeholders, RFC 3339 text for the
+//! timestamps the code writes itself). The `Storage` of the pool holds the rest: the fragments
+//! that cannot be portable, the migration set, and the work only one database can do, such as
+//! creating the SQLite file or computing the stats over the Postgres JSONB payloads.
+
+use crate::stats::{
+    jsonb, scan_overall_stats, scan_team_leaderboard, GameStats, SeasonWindow, StatsQuery,
+    TeamStats,
+};
+use futures_util::future::BoxFuture;
+use sqlx::{
+    migrate::{MigrateDatabase, Migrator},
+    AnyConnection, AnyPool,
+};
+use tracing::info;
+
+/// Migrations of each database, embedded in the binary.
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+pub trait Storage: Send + Sync {
+    /// Name used in logs.
+    fn name(&self) -> &'static str;
+
+    /// URL to connect with, given the configured one.
+    fn connect_url(&self, url: &str) -> String {
+        url.to_string()
+    }
+
+    /// Creates the database at `url` when it does not exist yet.
+    fn create_database<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<(), sqlx::Error>>;
+
+    /// Migration set of this database, shared by startup and `/readyz`.
+    fn migrator(&self) -> &'static Migrator;
+
+    /// Schema fixes the migrations cannot express, run after them at startup.
+    fn repair<'a>(&'a self, pool: &'a AnyPool) -> BoxFuture<'a, Result<(), sqlx::Error>>;
+
+    /// Selects a timestamp column as RFC 3339 text, under its own name.
+    fn timestamp_column(&self, column: &str) -> String;
+
+    /// Selects a JSON column as text, under its own name.
+    fn json_column(&self, column: &str) -> String;
+
+    /// Placeholder `$n` for an RFC 3339 timestamp bound as text.
+    fn timestamp_param(&self, n: usize) -> String;
+
+    /// Placeholder `$n` for a JSON document bound as text.
+    fn json_param(&self, n: usize) -> String;
+
+    /// `played_at` and `data` of `games`, both as text.
+    fn payload_columns(&self) -> String {
+        format!(
+            "{}, {}",
+            self.timestamp_column("played_at"),
+            self.json_column("data")
+        )
+    }
+
+    /// Whether `overall_stats` and `team_leaderboard` are filtered and aggregated in SQL
+    /// (`stats::jsonb`) rather than by scanning the payloads.
+    fn supports_jsonb(&self) -> bool {
+        false
+    }
+
+    /// Overall stats of the games matching `params` within `window`.
+    fn overall_stats<'a>(
+        &'a self,
+        pool: &'a AnyPool,
+        params: &'a StatsQuery,
+        window: Option<&'a SeasonWindow>,
+    ) -> BoxFuture<'a, Result<GameStats, sqlx::Error>> {
+        Box::pin(async move { Ok(scan_overall_stats(pool, params, window.copied()).await) })
+    }
+
+    /// Team leaderboard of the games matching `params` within `window`.
+    fn team_leaderboard<'a>(
+        &'a self,
+        pool: &'a AnyPool,
+        params: &'a StatsQuery,
+        window: Option<&'a SeasonWindow>,
+    ) -> BoxFuture<'a, Result<Vec<TeamStats>, sqlx::Error>> {
+        Box::pin(async move { Ok(scan_team_leaderboard(pool, params, window.copied()).await) })
+    }
+
+    /// Adds a condition on `games` keeping the games where one of `ids` played, when the
+    /// database can look players up; the callers check the players of each game either way.
+    fn push_player_filter(
+        &self,
+        _ids: &[String],
+        _conditions: &mut Vec<String>,
+        _binds: &mut Vec<String>,
+    ) {
+    }
+
+    /// Total number of rounds in the payloads of the games outside the trash.
+    fn rounds_query(&self) -> &'static str;
+
+    /// Compacts the database.
+    fn vacuum_statement(&self) -> &'static str;
+
+    /// Statements to run after rows were inserted with explicit ids.
+    fn reset_sequences(&self) -> &'static [&'static str];
+}
+
+pub struct SqliteStorage;
+
+pub struct PostgresStorage;
+
+impl Storage for SqliteStorage {
+    fn name(&self) -> &'static str {
+        "SQLite"
+    }
+
+    fn create_database<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        Box::pin(async move {
+            // In-memory databases and other URL forms are left to the driver
+            if url.starts_with("sqlite://") && !sqlx::Sqlite::database_exists(url).await? {
+                info!("Creating database {}", url);
+                sqlx::Sqlite::create_database(url).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn migrator(&self) -> &'static Migrator {
+        &SQLITE_MIGRATOR
+    }
+
+    fn repair<'a>(&'a self, pool: &'a AnyPool) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        Box::pin(repair_sqlite_game_ids(pool))
+    }
+
+    fn timestamp_column(&self, column: &str) -> String {
+        column.to_string()
+    }
+
+    fn json_column(&self, column: &str) -> String {
+        column.to_string()
+    }
+
+    fn timestamp_param(&self, n: usize) -> String {
+        format!("${}", n)
+    }
+
+    fn json_param(&self, n: usize) -> String {
+        format!("${}", n)
+    }
+
+    fn rounds_query(&self) -> &'static str {
+        "SELECT COALESCE(SUM(json_array_length(data, '$.bullseye.state.rounds')), 0) FROM games
+         WHERE deleted_at IS NULL AND json_valid(data)"
+    }
+
+    fn vacuum_statement(&self) -> &'static str {
+        "VACUUM"
+    }
+
+    fn reset_sequences(&self) -> &'static [&'static str] {
+        // AUTOINCREMENT continues after the highest id on its own
+        &[]
+    }
+}
+
+impl Storage for PostgresStorage {
+    fn name(&self) -> &'static str {
+        "PostgreSQL"
+    }
+
+    fn connect_url(&self, url: &str) -> String {
+        // The Any driver binds NULL as an integer, and a cached statement keeps the parameter
+        // types of its first execution: a text value later bound to the same parameter fails
+        if url.contains("statement-cache-capacity=") {
+            return url.to_string();
+        }
+        let separator = if url.contains('?') { '&' } else { '?' };
+        format!("{}{}statement-cache-capacity=0", url, separator)
+    }
+
+    fn create_database<'a>(&'a self, _url: &'a str) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        // Created along with its role by whoever runs the server
+        Box::pin(async { Ok(()) })
+    }
+
+    fn migrator(&self) -> &'static Migrator {
+        &POSTGRES_MIGRATOR
+    }
+
+    fn repair<'a>(&'a self, _pool: &'a AnyPool) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn timestamp_column(&self, column: &str) -> String {
+        // The Any driver cannot decode TIMESTAMPTZ
+        format!(
+            "to_char({0} AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS.US\"Z\"') AS {0}",
+            column
+        )
+    }
+
+    fn json_column(&self, column: &str) -> String {
+        // Nor JSONB
+        format!("CAST({0} AS TEXT) AS {0}", column)
+    }
+
+    // Through TEXT so that NULLs, bound as integers, cast too
+    fn timestamp_param(&self, n: usize) -> String {
+        format!("CAST(CAST(${} AS TEXT) AS TIMESTAMPTZ)", n)
+    }
+
+    fn json_param(&self, n: usize) -> String {
+        format!("CAST(CAST(${} AS TEXT) AS JSONB)", n)
+    }
+
+    fn supports_jsonb(&self) -> bool {
+        true
+    }
+
+    fn overall_stats<'a>(
+        &'a self,
+        pool: &'a AnyPool,
+        params: &'a StatsQuery,
+        window: Option<&'a SeasonWindow>,
+    ) -> BoxFuture<'a, Result<GameStats, sqlx::Error>> {
+        Box::pin(jsonb::overall_stats(pool, params, window))
+    }
+
+    fn team_leaderboard<'a>(
+        &'a self,
+        pool: &'a AnyPool,
+        params: &'a StatsQuery,
+        window: Option<&'a SeasonWindow>,
+    ) -> BoxFuture<'a, Result<Vec<TeamStats>, sqlx::Error>> {
+        Box::pin(jsonb::team_leaderboard(pool, params, window))
+    }
+
+    fn push_player_filter(
+        &self,
+        ids: &[String],
+        conditions: &mut Vec<String>,
+        binds: &mut Vec<String>,
+    ) {
+        // Served by `idx_games_players`
+        jsonb::push_player_filter(ids, conditions, binds);
+    }
+
+    fn rounds_query(&self) -> &'static str {
+        "SELECT CAST(COALESCE(SUM(jsonb_array_length(data->'bullseye'->'state'->'rounds')), 0) AS BIGINT) FROM games
+         WHERE deleted_at IS NULL AND jsonb_typeof(data->'bullseye'->'state'->'rounds') = 'array'"
+    }
+
+    fn vacuum_statement(&self) -> &'static str {
+        "VACUUM ANALYZE"
+    }
+
+    fn reset_sequences(&self) -> &'static [&'static str] {
+        // Explicit ids do not advance Postgres sequences
+        &[
+            "SELECT setval(pg_get_serial_sequence('games', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM games",
+            "SELECT setval(pg_get_serial_sequence('audit_log', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM audit_log",
+            "SELECT setval(pg_get_serial_sequence('seasons', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM seasons",
+        ]
+    }
+}
+
+/// Storage of the database behind `pool`, told apart by its URL.
+pub fn storage(pool: &AnyPool) -> &'static dyn Storage {
+    storage_for_url(pool.connect_options().database_url.as_str())
+}
+
+/// Storage of the database `conn` is connected to, for code running inside a transaction.
+pub fn storage_for_connection(conn: &AnyConnection) -> &'static dyn Storage {
+    match conn.backend_name() {
+        "PostgreSQL" => &PostgresStorage,
+        _ => &SqliteStorage,
+    }
+}
+
+pub fn storage_for_url(url: &str) -> &'static dyn Storage {
+    if url.starts_with("postgres") {
+        &PostgresStorage
+    } else {
+        &SqliteStorage
+    }
+}
+
+/// The first migration declares `games.id` as `SERIAL`, which SQLite does not auto-increment:
+/// every game ended up with a NULL id. Rebuild the table with a real rowid alias and number
+/// the existing games in insertion order.
+///
+/// The copy is made from the schema SQLite holds for `games`, so the columns added by later
+/// migrations come along, and the indexes and triggers on `games`, dropped with the old table,
+/// are created again.
+pub async fn repair_sqlite_game_ids(pool: &AnyPool) -> Result<(), sqlx::Error> {
+    let id_type: Option<String> =
+        sqlx::query_scalar("SELECT type FROM pragma_table_info('games') WHERE name = 'id'")
+            .fetch_optional(pool)
+            .await?;
+    if !id_type.is_some_and(|t| t.eq_ignore_ascii_case("SERIAL")) {
+        return Ok(());
+    }
+
+    info!("Repairing games.id on SQLite...");
+    let mut tx = pool.begin().await?;
+    let table_sql: String =
+        sqlx::query_scalar("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'games'")
+            .fetch_one(&mut *tx)
+            .await?;
+    let definition = table_sql
+        .find('(')
+        .map(|start| &table_sql[start..])
+        .filter(|definition| definition.contains("id SERIAL PRIMARY KEY"))
+        .ok_or_else(|| sqlx::Error::Protocol(format!("unexpected games schema: {}", table_sql)))?
+        .replacen(
+            "id SERIAL PRIMARY KEY",
+            "id INTEGER PRIMARY KEY AUTOINCREMENT",
+            1,
+        );
+    let dependents: Vec<String> = sqlx::query_scalar(
+        "SELECT sql FROM sqlite_master
+         WHERE tbl_name = 'games' AND type IN ('index', 'trigger') AND sql IS NOT NULL",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let columns: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM pragma_table_info('games') WHERE name <> 'id'")
+            .fetch_all(&mut *tx)
+            .await?
+            .iter()
+            .map(|name: &String| format!("\"{}\"", name))
+            .collect();
+    let columns = columns.join(", ");
+
+    for statement in [
+        format!("CREATE TABLE games_repaired {}", definition),
+        format!(
+            "INSERT INTO games_repaired ({columns}) SELECT {columns} FROM games ORDER BY rowid",
+            columns = columns
+        ),
+        "DROP TABLE games".to_string(),
+        "ALTER TABLE games_repaired RENAME TO games".to_string(),
+    ]
+    .into_iter()
+    .chain(dependents)
+    {
+        sqlx::query(&statement).execute(&mut *tx).await?;
+    }
+    tx.commit().await
+}
@@ -0,0 +1,399 @@
+//! Full database backup to a JSON archive and restore from it.
+
+use crate::storage::{
+    aggregates::recompute_aggregates,
+    audit::{record_audit, AuditEvent},
+    backend::{storage, Storage},
+    get_nullable,
+    names::rebuild_name_history,
+    version::bump_data_version,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{any::AnyRow, AnyPool};
+use tracing::error;
+use utoipa::ToSchema;
+
+pub const BACKUP_FORMAT: &str = "bullseye-backup";
+
+/// Bumped whenever a table or column is added to `BACKUP_TABLES`.
+pub const BACKUP_VERSION: u32 = 7;
+
+#[derive(Clone, Copy)]
+pub enum ColumnKind {
+    Integer,
+    Real,
+    /// 0/1 integer in the database, boolean in the archive; missing values restore as `false`
+    Bool,
+    Text,
+    /// Text in the archive, `TIMESTAMPTZ` on Postgres
+    Timestamp,
+    /// Text in the archive, `JSONB` on Postgres
+    Json,
+}
+
+pub struct BackupTable {
+    pub name: &'static str,
+    pub columns: &'static [(&'static str, ColumnKind)],
+}
+
+/// Every table of the schema, parents before children so restores satisfy foreign keys.
+pub const BACKUP_TABLES: &[BackupTable] = &[
+    BackupTable {
+        name: "games",
+        columns: &[
+            ("id", ColumnKind::Integer),
+            ("game_id", ColumnKind::Text),
+            ("map_name", ColumnKind::Text),
+            ("score", ColumnKind::Integer),
+            ("round_time", ColumnKind::Integer),
+            ("total_duration", ColumnKind::Integer),
+            ("played_at", ColumnKind::Timestamp),
+            ("data", ColumnKind::Json),
+            ("deleted_at", ColumnKind::Text),
+            ("excluded", ColumnKind::Bool),
+            ("excluded_reason", ColumnKind::Text),
+            ("notes", ColumnKind::Text),
+        ],
+    },
+    BackupTable {
+        name: "game_tags",
+        columns: &[("game_id", ColumnKind::Integer), ("tag", ColumnKind::Text)],
+    },
+    BackupTable {
+        name: "players",
+        columns: &[
+            ("id", ColumnKind::Text),
+            ("name", ColumnKind::Text),
+            ("last_seen", ColumnKind::Timestamp),
+            ("pinned_name", ColumnKind::Text),
+        ],
+    },
+    BackupTable {
+        name: "player_names",
+        columns: &[
+            ("player_id", ColumnKind::Text),
+            ("nick", ColumnKind::Text),
+            ("first_seen", ColumnKind::Text),
+            ("last_seen", ColumnKind::Text),
+            ("games", ColumnKind::Integer),
+            ("source_game_id", ColumnKind::Integer),
+        ],
+    },
+    BackupTable {
+        name: "player_aliases",
+        columns: &[
+            ("alias_id", ColumnKind::Text),
+            ("primary_id", ColumnKind::Text),
+            ("created_at", ColumnKind::Timestamp),
+        ],
+    },
+    BackupTable {
+        name: "alias_rejections",
+        columns: &[
+            ("player_a", ColumnKind::Text),
+            ("player_b", ColumnKind::Text),
+            ("rejected_at", ColumnKind::Text),
+            ("actor", ColumnKind::Text),
+        ],
+    },
+    BackupTable {
+        name: "seasons",
+        columns: &[
+            ("id", ColumnKind::Integer),
+            ("name", ColumnKind::Text),
+            ("starts_at", ColumnKind::Text),
+            ("ends_at", ColumnKind::Text),
+            ("closed_at", ColumnKind::Text),
+            ("created_at", ColumnKind::Text),
+        ],
+    },
+    BackupTable {
+        name: "season_standings",
+        columns: &[
+            ("season_id", ColumnKind::Integer),
+            ("kind", ColumnKind::Text),
+            ("standing_rank", ColumnKind::Integer),
+            ("standing_key", ColumnKind::Text),
+            ("name", ColumnKind::Text),
+            ("members", ColumnKind::Text),
+            ("games_played", ColumnKind::Integer),
+            ("total_score", ColumnKind::Integer),
+            ("average_score", ColumnKind::Real),
+            ("total_duration", ColumnKind::Integer),
+        ],
+    },
+    BackupTable {
+        name: "api_tokens",
+        columns: &[
+            ("token_hash", ColumnKind::Text),
+            ("name", ColumnKind::Text),
+            ("created_at", ColumnKind::Text),
+        ],
+    },
+    BackupTable {
+        name: "audit_log",
+        columns: &[
+            ("id", ColumnKind::Integer),
+            ("created_at", ColumnKind::Text),
+            ("actor", ColumnKind::Text),
+            ("action", ColumnKind::Text),
+            ("target_type", ColumnKind::Text),
+            ("target_id", ColumnKind::Text),
+            ("before_value", ColumnKind::Text),
+            ("after_value", ColumnKind::Text),
+            ("undone_at", ColumnKind::Text),
+        ],
+    },
+];
+
+#[derive(Serialize, Deserialize)]
+pub struct BackupArchive {
+    pub format: String,
+    pub version: u32,
+    pub created_at: String,
+    pub tables: std::collections::BTreeMap<String, Vec<BackupRecord>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RestoreReport {
+    /// Number of restored rows per table
+    pub tables: std::collections::BTreeMap<String, usize>,
+}
+
+pub enum RestoreError {
+    Invalid(String),
+    NotEmpty(&'static str),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestoreError::Invalid(reason) => write!(f, "invalid backup: {}", reason),
+            RestoreError::NotEmpty(table) => {
+                write!(f, "target database is not empty (table {})", table)
+            }
+            RestoreError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for RestoreError {
+    fn from(e: sqlx::Error) -> Self {
+        RestoreError::Database(e)
+    }
+}
+
+pub type BackupRecord = serde_json::Map<String, serde_json::Value>;
+
+impl BackupTable {
+    /// Columns to select; timestamps and JSON come back as text.
+    pub fn select_list(&self, storage: &dyn Storage) -> String {
+        self.columns
+            .iter()
+            .map(|(name, kind)| match kind {
+                ColumnKind::Timestamp => storage.timestamp_column(name),
+                ColumnKind::Json => storage.json_column(name),
+                _ => name.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn record(&self, row: &AnyRow) -> Result<BackupRecord, sqlx::Error> {
+        let mut record = serde_json::Map::new();
+        for (name, kind) in self.columns {
+            let value = match kind {
+                ColumnKind::Integer => get_nullable::<i64>(row, name)?.into(),
+                ColumnKind::Real => get_nullable::<f64>(row, name)?.into(),
+                ColumnKind::Bool => get_nullable::<i64>(row, name)?.map(|v| v != 0).into(),
+                _ => get_nullable::<String>(row, name)?.into(),
+            };
+            record.insert(name.to_string(), value);
+        }
+        Ok(record)
+    }
+
+    pub fn insert_statement(&self, storage: &dyn Storage) -> String {
+        let names = self
+            .columns
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let placeholders = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, (_, kind))| match kind {
+                ColumnKind::Timestamp => storage.timestamp_param(i + 1),
+                ColumnKind::Json => storage.json_param(i + 1),
+                _ => format!("${}", i + 1),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            self.name, names, placeholders
+        )
+    }
+
+    /// Binds the columns of `record` in order; on a type mismatch returns the column name.
+    pub fn bind_record<'q>(
+        &self,
+        mut query: sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>>,
+        record: &BackupRecord,
+    ) -> Result<sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>>, &'static str> {
+        for (name, kind) in self.columns {
+            let value = record.get(*name).unwrap_or(&serde_json::Value::Null);
+            query = match kind {
+                ColumnKind::Integer if value.is_null() => query.bind(None::<i64>),
+                ColumnKind::Integer => query.bind(value.as_i64().ok_or(*name)?),
+                ColumnKind::Real if value.is_null() => query.bind(None::<f64>),
+                ColumnKind::Real => query.bind(value.as_f64().ok_or(*name)?),
+                ColumnKind::Bool if value.is_null() => query.bind(0_i64),
+                ColumnKind::Bool => query.bind(i64::from(value.as_bool().ok_or(*name)?)),
+                _ if value.is_null() => query.bind(None::<String>),
+                _ => query.bind(value.as_str().ok_or(*name)?.to_string()),
+            };
+        }
+        Ok(query)
+    }
+}
+
+/// Reads every table into a versioned archive.
+pub async fn create_backup(pool: &AnyPool) -> Result<BackupArchive, sqlx::Error> {
+    let storage = storage(pool);
+    let mut tables = std::collections::BTreeMap::new();
+
+    for table in BACKUP_TABLES {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM {}",
+            table.select_list(storage),
+            table.name
+        ))
+        .fetch_all(pool)
+        .await?;
+
+        let records = rows
+            .iter()
+            .map(|row| table.record(row))
+            .collect::<Result<Vec<_>, _>>()?;
+        tables.insert(table.name.to_string(), records);
+    }
+
+    Ok(BackupArchive {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: Utc::now().to_rfc3339(),
+        tables,
+    })
+}
+
+/// Loads an archive into a migrated but empty database, in a single transaction.
+pub async fn restore_backup(
+    pool: &AnyPool,
+    archive: &BackupArchive,
+) -> Result<RestoreReport, RestoreError> {
+    if archive.format != BACKUP_FORMAT {
+        return Err(RestoreError::Invalid(format!(
+            "unknown format {:?}",
+            archive.format
+        )));
+    }
+    if archive.version > BACKUP_VERSION {
+        return Err(RestoreError::Invalid(format!(
+            "version {} is newer than the supported version {}",
+            archive.version, BACKUP_VERSION
+        )));
+    }
+    if let Some(unknown) = archive
+        .tables
+        .keys()
+        .find(|name| !BACKUP_TABLES.iter().any(|t| t.name == name.as_str()))
+    {
+        return Err(RestoreError::Invalid(format!("unknown table {}", unknown)));
+    }
+
+    for table in BACKUP_TABLES {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table.name))
+            .fetch_one(pool)
+            .await?;
+        if count > 0 {
+            return Err(RestoreError::NotEmpty(table.name));
+        }
+    }
+
+    let storage = storage(pool);
+    let mut report = RestoreReport {
+        tables: std::collections::BTreeMap::new(),
+    };
+    let mut tx = pool.begin().await?;
+
+    for table in BACKUP_TABLES {
+        let records = archive
+            .tables
+            .get(table.name)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let sql = table.insert_statement(storage);
+        for (index, record) in records.iter().enumerate() {
+            table
+                .bind_record(sqlx::query(&sql), record)
+                .map_err(|name| {
+                    RestoreError::Invalid(format!(
+                        "{}[{}].{} has an unexpected type",
+                        table.name, index, name
+                    ))
+                })?
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        report.tables.insert(table.name.to_string(), records.len());
+    }
+
+    for statement in storage.reset_sequences() {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+    // Archives before version 6 have no nickname history
+    if archive.version < 6 {
+        rebuild_name_history(&mut tx).await?;
+    }
+    // Derived tables are not archived
+    recompute_aggregates(&mut tx).await?;
+    bump_data_version(&mut *tx).await?;
+
+    tx.commit().await?;
+    Ok(report)
+}
+
+/// The restore itself is already committed: a failure here is only logged.
+pub async fn audit_restore(
+    pool: &AnyPool,
+    actor: &str,
+    archive: &BackupArchive,
+    report: &RestoreReport,
+) {
+    let recorded = match pool.acquire().await {
+        Ok(mut conn) => record_audit(
+            &mut conn,
+            actor,
+            AuditEvent {
+                action: "backup.restore",
+                target_type: "database",
+                target_id: archive.created_at.clone(),
+                before: None,
+                after: serde_json::to_value(&report.tables).ok(),
+            },
+        )
+        .await
+        .map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = recorded {
+        error!("Failed to record the restore in the audit log: {}", e);
+    }
+}
@@ -0,0 +1,259 @@
+//! Database access: connection, migrations and storing submitted games.
+
+pub mod aggregates;
+pub mod annotations;
+pub mod audit;
+pub mod backend;
+pub mod backup;
+pub mod names;
+pub mod players;
+pub mod trash;
+pub mod version;
+
+use crate::{
+    config::{DatabaseConfig, DisplayNamePolicy},
+    model::{payload_game_id, BullseyePayload},
+    storage::{
+        aggregates::{add_game_to_stats, ensure_aggregates},
+        audit::{record_audit, AuditEvent},
+        backend::{storage, storage_for_url},
+        names::{ensure_name_history, record_game_names},
+        version::bump_data_version,
+    },
+};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{
+    any::{AnyPoolOptions, AnyRow},
+    AnyPool, Row, TypeInfo, ValueRef,
+};
+use tracing::{debug, info};
+
+/// Connects to the database, creating the SQLite file if needed, and runs the migrations.
+pub async fn connect_database(config: &DatabaseConfig) -> Result<AnyPool, String> {
+    let database_url = config.url.as_str();
+    let storage = storage_for_url(database_url);
+
+    storage
+        .create_database(database_url)
+        .await
+        .map_err(|e| format!("failed to create {}: {}", database_url, e))?;
+
+    let pool = AnyPoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(std::time::Duration::from_secs(config.acquire_timeout_secs))
+        .idle_timeout(
+            (config.idle_timeout_secs > 0)
+                .then(|| std::time::Duration::from_secs(config.idle_timeout_secs)),
+        )
+        .connect(&storage.connect_url(database_url))
+        .await
+        .map_err(|e| format!("failed to connect: {}", e))?;
+
+    info!("Running {} migrations...", storage.name());
+    storage
+        .migrator()
+        .run(&pool)
+        .await
+        .map_err(|e| format!("failed to run migrations: {}", e))?;
+    info!("Migrations run successfully");
+
+    storage
+        .repair(&pool)
+        .await
+        .map_err(|e| format!("failed to repair the schema: {}", e))?;
+
+    ensure_aggregates(&pool)
+        .await
+        .map_err(|e| format!("failed to build the stats aggregates: {}", e))?;
+    ensure_name_history(&pool)
+        .await
+        .map_err(|e| format!("failed to build the nickname history: {}", e))?;
+
+    Ok(pool)
+}
+
+/// API tokens are only stored as SHA-256 hashes.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Generates and stores a new API token for the extension; the clear token is only returned here.
+pub async fn create_api_token(pool: &AnyPool, name: &str) -> Result<String, sqlx::Error> {
+    let token = format!("bt_{}", hex::encode(rand::random::<[u8; 32]>()));
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("INSERT INTO api_tokens (token_hash, name, created_at) VALUES ($1, $2, $3)")
+        .bind(hash_token(&token))
+        .bind(name)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+    record_audit(
+        &mut tx,
+        "cli",
+        AuditEvent {
+            action: "token.create",
+            target_type: "token",
+            target_id: name.to_string(),
+            before: None,
+            after: None,
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(token)
+}
+
+/// Step of `store_game` that failed; the whole submission is rolled back then.
+#[derive(Debug)]
+pub enum StoreError {
+    Game(sqlx::Error),
+    Players(sqlx::Error),
+    Derived(sqlx::Error),
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Game(e) => write!(f, "cannot store the game: {}", e),
+            StoreError::Players(e) => write!(f, "cannot store the players of the game: {}", e),
+            StoreError::Derived(e) => write!(f, "cannot update the stats of the game: {}", e),
+        }
+    }
+}
+
+/// Inserts a game with its summary columns, records its players and their nicks and adds it to
+/// the stats aggregates, in one transaction. Returns false, storing nothing, when a game with
+/// the same `game_id` is already stored outside the trash.
+pub async fn store_game(
+    pool: &AnyPool,
+    payload: &BullseyePayload,
+    policy: DisplayNamePolicy,
+) -> Result<bool, StoreError> {
+    let data_json = serde_json::to_string(payload).unwrap();
+
+    // Extract fields safely
+    let game_id = payload_game_id(payload);
+
+    let map_name = payload
+        .bullseye
+        .as_ref()
+        .and_then(|b| b.state.as_ref())
+        .and_then(|s| s.map_name.as_deref());
+
+    let mut score = payload
+        .bullseye
+        .as_ref()
+        .and_then(|b| b.guess.as_ref())
+        .and_then(|g| g.score.as_ref())
+        .and_then(|s| s.points)
+        .unwrap_or(0);
+
+    // If score is 0, try to calculate it from rounds in state
+    if score == 0 {
+        if let Some(rounds) = payload
+            .bullseye
+            .as_ref()
+            .and_then(|b| b.state.as_ref())
+            .and_then(|s| s.rounds.as_ref())
+        {
+            let calculated_score: i32 = rounds
+                .iter()
+                .filter_map(|r| r.score.as_ref().and_then(|s| s.points))
+                .sum();
+
+            if calculated_score > 0 {
+                debug!("Calculated score from rounds: {}", calculated_score);
+                score = calculated_score;
+            }
+        }
+    }
+
+    let round_time = payload
+        .bullseye
+        .as_ref()
+        .and_then(|b| b.state.as_ref())
+        .and_then(|s| s.options.as_ref())
+        .and_then(|o| o.round_time);
+
+    let total_duration = payload.total_duration;
+
+    let mut played_at = payload.timestamp.clone();
+
+    if played_at.is_none() {
+        if let Some(rounds) = payload
+            .bullseye
+            .as_ref()
+            .and_then(|b| b.state.as_ref())
+            .and_then(|s| s.rounds.as_ref())
+        {
+            // Try to find round 1
+            if let Some(first_round) = rounds.iter().find(|r| r.round_number == Some(1)) {
+                played_at = first_round.start_time.clone();
+            } else if let Some(first) = rounds.first() {
+                // Fallback to first available round if round 1 not found
+                played_at = first.start_time.clone();
+            }
+        }
+    }
+
+    // Insert game
+    let storage = storage(pool);
+    let mut tx = pool.begin().await.map_err(StoreError::Game)?;
+    // The unique index on live game ids settles two copies of a game sent at once
+    let stored_id: Option<i64> = sqlx::query_scalar(&format!(
+        "INSERT INTO games (game_id, map_name, score, round_time, total_duration, data, played_at) VALUES ($1, $2, $3, $4, $5, {}, COALESCE({}, CURRENT_TIMESTAMP))
+         ON CONFLICT (game_id) WHERE deleted_at IS NULL DO NOTHING
+         RETURNING id",
+        storage.json_param(6),
+        storage.timestamp_param(7)
+    ))
+    .bind(game_id)
+    .bind(map_name)
+    .bind(score)
+    .bind(round_time)
+    .bind(total_duration)
+    .bind(data_json)
+    .bind(played_at.clone())
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(StoreError::Game)?;
+    let Some(stored_id) = stored_id else {
+        return Ok(false);
+    };
+
+    if let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) {
+        record_game_names(
+            &mut tx,
+            stored_id,
+            state,
+            played_at.unwrap_or_default(),
+            policy,
+        )
+        .await
+        .map_err(StoreError::Players)?;
+    }
+
+    add_game_to_stats(&mut tx, stored_id)
+        .await
+        .map_err(StoreError::Derived)?;
+    bump_data_version(&mut *tx)
+        .await
+        .map_err(StoreError::Derived)?;
+    tx.commit().await.map_err(StoreError::Game)?;
+    Ok(true)
+}
+
+/// Decodes a nullable column: the Any driver never flags values as NULL, so `Option<T>`
+/// fails on them; the `NULL` type name of the value is checked instead.
+pub fn get_nullable<'r, T>(row: &'r AnyRow, name: &str) -> Result<Option<T>, sqlx::Error>
+where
+    T: sqlx::Decode<'r, sqlx::Any> + sqlx::Type<sqlx::Any>,
+{
+    if row.try_get_raw(name)?.type_info().name() == "NULL" {
+        return Ok(None);
+    }
+    row.try_get(name).map(Some)
+}
@@ -0,0 +1,369 @@
+//! Nickname history of the players and the display name chosen from it.
+//!
+//! Every nick a player id is seen with is kept in `player_names` with the dates of its first
+//! and last game. `players.name` holds the display name picked among them by the configured
+//! `DisplayNamePolicy`, so every reader shows the same one.
+
+use crate::{
+    config::DisplayNamePolicy,
+    model::{game_played_at, parse_payload, BullseyeState},
+    stats::parse_timestamp,
+    storage::{
+        audit::{record_audit, AuditEvent},
+        backend::storage_for_connection,
+        get_nullable,
+        version::bump_data_version,
+    },
+};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use sqlx::{AnyConnection, AnyPool, Row};
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct PlayerNames {
+    /// Primary id of the player
+    pub player_id: String,
+    pub display_name: Option<String>,
+    pub pinned_name: Option<String>,
+    /// Nicks of the player and of its aliases, the oldest first
+    pub names: Vec<NameSighting>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct NameSighting {
+    pub player_id: String,
+    pub nick: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub games: i64,
+    /// Game the nick was first seen in
+    pub source_game_id: Option<i64>,
+}
+
+/// Date of a game as stored in the history: RFC 3339 in UTC, so the dates compare as text.
+fn sighting_date(state: &BullseyeState, stored_played_at: String) -> String {
+    parse_timestamp(&game_played_at(state, stored_played_at))
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Adds the nicks of a game to the history, creating the missing players; returns their ids.
+async fn record_sightings(
+    conn: &mut AnyConnection,
+    game_id: i64,
+    state: &BullseyeState,
+    stored_played_at: String,
+) -> Result<Vec<String>, sqlx::Error> {
+    let seen_at = sighting_date(state, stored_played_at);
+    let mut recorded = HashSet::new();
+    let mut ids = Vec::new();
+
+    for player in state.players.as_deref().unwrap_or_default() {
+        let (Some(id), Some(nick)) = (&player.player_id, &player.nick) else {
+            continue;
+        };
+        if !recorded.insert((id, nick)) {
+            continue;
+        }
+
+        sqlx::query(
+            "INSERT INTO players (id, name, last_seen) VALUES ($1, $2, CURRENT_TIMESTAMP)
+             ON CONFLICT(id) DO UPDATE SET last_seen = CURRENT_TIMESTAMP",
+        )
+        .bind(id)
+        .bind(nick)
+        .execute(&mut *conn)
+        .await?;
+
+        // The right-hand sides all read the row before the update
+        sqlx::query(
+            "INSERT INTO player_names (player_id, nick, first_seen, last_seen, games, source_game_id)
+             VALUES ($1, $2, $3, $3, 1, $4)
+             ON CONFLICT (player_id, nick) DO UPDATE SET
+                 games = player_names.games + 1,
+                 first_seen = CASE WHEN excluded.first_seen < player_names.first_seen
+                     THEN excluded.first_seen ELSE player_names.first_seen END,
+                 source_game_id = CASE WHEN excluded.first_seen < player_names.first_seen
+                     THEN excluded.source_game_id ELSE player_names.source_game_id END,
+                 last_seen = CASE WHEN excluded.last_seen > player_names.last_seen
+                     THEN excluded.last_seen ELSE player_names.last_seen END",
+        )
+        .bind(id)
+        .bind(nick)
+        .bind(&seen_at)
+        .bind(game_id)
+        .execute(&mut *conn)
+        .await?;
+
+        if !ids.contains(id) {
+            ids.push(id.clone());
+        }
+    }
+    Ok(ids)
+}
+
+/// Records the nicks of a newly stored game and updates the display names of its players.
+pub async fn record_game_names(
+    conn: &mut AnyConnection,
+    game_id: i64,
+    state: &BullseyeState,
+    stored_played_at: String,
+    policy: DisplayNamePolicy,
+) -> Result<(), sqlx::Error> {
+    for id in record_sightings(conn, game_id, state, stored_played_at).await? {
+        refresh_display_name(conn, &id, policy).await?;
+    }
+    Ok(())
+}
+
+/// Rebuilds the history from every stored game, trash included; returns the number of players
+/// seen. Display names are left as they are.
+pub async fn rebuild_name_history(conn: &mut AnyConnection) -> Result<usize, sqlx::Error> {
+    sqlx::query("DELETE FROM player_names")
+        .execute(&mut *conn)
+        .await?;
+
+    let storage = storage_for_connection(conn);
+    let rows = sqlx::query(&format!(
+        "SELECT id, {} FROM games ORDER BY id",
+        storage.payload_columns()
+    ))
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut players = HashSet::new();
+    for row in rows {
+        let data = get_nullable::<String>(&row, "data")?;
+        let Some(payload) = data.as_deref().and_then(|d| parse_payload(d).ok()) else {
+            continue;
+        };
+        let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) else {
+            continue;
+        };
+        let played_at = get_nullable::<String>(&row, "played_at")?.unwrap_or_default();
+        players.extend(record_sightings(conn, row.get("id"), state, played_at).await?);
+    }
+    Ok(players.len())
+}
+
+/// Fills the history of a database migrated from a version without it.
+pub async fn ensure_name_history(pool: &AnyPool) -> Result<(), sqlx::Error> {
+    let built = sqlx::query("SELECT 1 FROM player_names LIMIT 1")
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+    let has_games = sqlx::query("SELECT 1 FROM games LIMIT 1")
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+    if built || !has_games {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    let players = rebuild_name_history(&mut tx).await?;
+    tx.commit().await?;
+    info!("Built the nickname history of {} players", players);
+    Ok(())
+}
+
+/// SQL expression of the display name of the `players` row under `policy`; NULL when the
+/// player has no nick in the history.
+fn display_name_expression(policy: DisplayNamePolicy) -> String {
+    let nick = |order: &str| {
+        format!(
+            "(SELECT nick FROM player_names WHERE player_id = players.id ORDER BY {} LIMIT 1)",
+            order
+        )
+    };
+    let latest = nick("last_seen DESC, games DESC, nick");
+    match policy {
+        DisplayNamePolicy::Latest => latest,
+        DisplayNamePolicy::MostFrequent => nick("games DESC, last_seen DESC, nick"),
+        DisplayNamePolicy::Pinned => format!("COALESCE(pinned_name, {})", latest),
+    }
+}
+
+/// Sets `players.name` of one player from its history.
+pub async fn refresh_display_name(
+    conn: &mut AnyConnection,
+    player_id: &str,
+    policy: DisplayNamePolicy,
+) -> Result<(), sqlx::Error> {
+    let name = display_name_expression(policy);
+    sqlx::query(&format!(
+        "UPDATE players SET name = {name} WHERE id = $1 AND {name} <> name",
+        name = name
+    ))
+    .bind(player_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Sets `players.name` of every player from its history; returns the number of names changed.
+pub async fn refresh_display_names(
+    conn: &mut AnyConnection,
+    policy: DisplayNamePolicy,
+) -> Result<u64, sqlx::Error> {
+    let name = display_name_expression(policy);
+    let result = sqlx::query(&format!(
+        "UPDATE players SET name = {name} WHERE {name} <> name",
+        name = name
+    ))
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Applies the configured policy at startup, in case it changed since the names were chosen.
+pub async fn apply_display_name_policy(
+    pool: &AnyPool,
+    policy: DisplayNamePolicy,
+) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let changed = refresh_display_names(&mut tx, policy).await?;
+    if changed > 0 {
+        bump_data_version(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(changed)
+}
+
+/// Display names by player id, for every `PlayerInfo` the API returns: an alias shows the name
+/// of its primary player, a primary without a name the one of an alias.
+pub async fn display_names(pool: &AnyPool) -> Result<HashMap<String, String>, sqlx::Error> {
+    let mut names: HashMap<String, String> = sqlx::query("SELECT id, name FROM players")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.get("id"), row.get("name")))
+        .collect();
+
+    for row in sqlx::query("SELECT alias_id, primary_id FROM player_aliases")
+        .fetch_all(pool)
+        .await?
+    {
+        let alias_id: String = row.get("alias_id");
+        let primary_id: String = row.get("primary_id");
+        match names.get(&primary_id).cloned() {
+            Some(name) => {
+                names.insert(alias_id, name);
+            }
+            None => {
+                if let Some(name) = names.get(&alias_id).cloned() {
+                    names.insert(primary_id, name);
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Primary id of `player_id`, itself when it is not an alias.
+async fn primary_id_of(conn: &mut AnyConnection, player_id: &str) -> Result<String, sqlx::Error> {
+    let primary: Option<String> =
+        sqlx::query_scalar("SELECT primary_id FROM player_aliases WHERE alias_id = $1")
+            .bind(player_id)
+            .fetch_optional(conn)
+            .await?;
+    Ok(primary.unwrap_or_else(|| player_id.to_string()))
+}
+
+/// Nickname timeline of a player and its aliases; `None` when the player is unknown.
+pub async fn player_names(
+    pool: &AnyPool,
+    player_id: &str,
+) -> Result<Option<PlayerNames>, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let primary_id = primary_id_of(&mut conn, player_id).await?;
+
+    let player = sqlx::query("SELECT name, pinned_name FROM players WHERE id = $1")
+        .bind(&primary_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    let rows = sqlx::query(
+        "SELECT player_id, nick, first_seen, last_seen, games, source_game_id FROM player_names
+         WHERE player_id = $1
+            OR player_id IN (SELECT alias_id FROM player_aliases WHERE primary_id = $1)
+         ORDER BY first_seen, player_id, nick",
+    )
+    .bind(&primary_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    if player.is_none() && rows.is_empty() {
+        return Ok(None);
+    }
+
+    let names = rows
+        .iter()
+        .map(|row| {
+            Ok(NameSighting {
+                player_id: row.get("player_id"),
+                nick: row.get("nick"),
+                first_seen: row.get("first_seen"),
+                last_seen: row.get("last_seen"),
+                games: row.get("games"),
+                source_game_id: get_nullable(row, "source_game_id")?,
+            })
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+    let (display_name, pinned_name) = match &player {
+        Some(row) => (row.get("name"), get_nullable(row, "pinned_name")?),
+        None => (None, None),
+    };
+
+    Ok(Some(PlayerNames {
+        player_id: primary_id,
+        display_name,
+        pinned_name,
+        names,
+    }))
+}
+
+/// Pins (or with `None` unpins) the name of a player, of its primary when it is an alias;
+/// returns the primary id, or `None` when the player is unknown.
+pub async fn pin_player_name(
+    pool: &AnyPool,
+    player_id: &str,
+    name: Option<&str>,
+    policy: DisplayNamePolicy,
+    actor: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let primary_id = primary_id_of(&mut tx, player_id).await?;
+
+    let Some(row) = sqlx::query("SELECT pinned_name FROM players WHERE id = $1")
+        .bind(&primary_id)
+        .fetch_optional(&mut *tx)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let previous: Option<String> = get_nullable(&row, "pinned_name")?;
+
+    sqlx::query("UPDATE players SET pinned_name = $1 WHERE id = $2")
+        .bind(name.map(str::to_string))
+        .bind(&primary_id)
+        .execute(&mut *tx)
+        .await?;
+    refresh_display_name(&mut tx, &primary_id, policy).await?;
+    record_audit(
+        &mut tx,
+        actor,
+        AuditEvent {
+            action: "player.pin_name",
+            target_type: "player",
+            target_id: primary_id.clone(),
+            before: Some(serde_json::json!({ "pinned_name": previous })),
+            after: Some(serde_json::json!({ "pinned_name": name })),
+        },
+    )
+    .await?;
+    bump_data_version(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(Some(primary_id))
+}
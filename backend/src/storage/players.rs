@@ -0,0 +1,288 @@
+//! The players directory, alias links between player ids and rejected alias suggestions.
+
+use crate::{
+    config::DisplayNamePolicy,
+    storage::{
+        aggregates::recompute_aggregates,
+        audit::{record_audit, AuditEvent},
+        names::{rebuild_name_history, refresh_display_names},
+        version::bump_data_version,
+    },
+};
+use chrono::Utc;
+use sqlx::{AnyPool, Row};
+use tracing::debug;
+
+/// Rebuilds the players directory and their nickname history from the stored payloads, then
+/// chooses their display names; returns the number of players found.
+pub async fn backfill_players(
+    pool: &AnyPool,
+    policy: DisplayNamePolicy,
+) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let found = rebuild_name_history(&mut tx).await?;
+    debug!("Backfill - Found {} unique players", found);
+    refresh_display_names(&mut tx, policy).await?;
+    bump_data_version(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(found)
+}
+
+pub enum LinkError {
+    SameId,
+    PrimaryIsAlias,
+    AliasHasAliases,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for LinkError {
+    fn from(e: sqlx::Error) -> Self {
+        LinkError::Database(e)
+    }
+}
+
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::SameId => write!(f, "a player cannot be its own alias"),
+            LinkError::PrimaryIsAlias => write!(f, "the primary player is itself an alias"),
+            LinkError::AliasHasAliases => write!(f, "the alias player already has aliases"),
+            LinkError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+/// Makes `alias_id` an alias of `primary_id`, keeping links one level deep.
+pub async fn link_players(
+    pool: &AnyPool,
+    alias_id: &str,
+    primary_id: &str,
+    actor: &str,
+) -> Result<(), LinkError> {
+    let mut tx = pool.begin().await?;
+    let previous = apply_link(&mut tx, alias_id, primary_id).await?;
+    record_audit(
+        &mut tx,
+        actor,
+        AuditEvent {
+            action: "player.link",
+            target_type: "player",
+            target_id: alias_id.to_string(),
+            before: Some(serde_json::json!({ "primary_id": previous })),
+            after: Some(serde_json::json!({ "primary_id": primary_id })),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Primary player `alias_id` is currently linked to.
+pub async fn current_primary(
+    conn: &mut sqlx::AnyConnection,
+    alias_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT primary_id FROM player_aliases WHERE alias_id = $1")
+        .bind(alias_id)
+        .fetch_optional(conn)
+        .await
+}
+
+/// Checks the link rules, upserts the link and rebuilds the stats aggregates; returns the
+/// previous primary of `alias_id`.
+pub async fn apply_link(
+    conn: &mut sqlx::AnyConnection,
+    alias_id: &str,
+    primary_id: &str,
+) -> Result<Option<String>, LinkError> {
+    if alias_id == primary_id {
+        return Err(LinkError::SameId);
+    }
+
+    // Prevent circular dependency: Check if primary_id is already an alias of alias_id (or anyone else)
+    // For simplicity, just ensure primary_id is NOT an alias itself.
+    // A more robust check would traverse the tree, but 1-level depth is easier to manage.
+    // Rule: A Primary ID cannot be an Alias. An Alias cannot have Aliases.
+
+    // Check if primary is already an alias
+    if current_primary(conn, primary_id).await?.is_some() {
+        return Err(LinkError::PrimaryIsAlias);
+    }
+
+    // Check if alias already has aliases (cannot make a parent a child)
+    let alias_has_children = sqlx::query("SELECT 1 FROM player_aliases WHERE primary_id = $1")
+        .bind(alias_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .is_some();
+
+    if alias_has_children {
+        return Err(LinkError::AliasHasAliases);
+    }
+
+    let previous = current_primary(conn, alias_id).await?;
+
+    // Upsert the link
+    sqlx::query(
+        "INSERT INTO player_aliases (alias_id, primary_id) VALUES ($1, $2)
+         ON CONFLICT(alias_id) DO UPDATE SET primary_id = excluded.primary_id",
+    )
+    .bind(alias_id)
+    .bind(primary_id)
+    .execute(&mut *conn)
+    .await?;
+    recompute_aggregates(conn).await?;
+    bump_data_version(&mut *conn).await?;
+
+    Ok(previous)
+}
+
+/// Removes the alias link of `alias_id`; returns whether a link existed.
+pub async fn unlink_players(
+    pool: &AnyPool,
+    alias_id: &str,
+    actor: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let previous = apply_unlink(&mut tx, alias_id).await?;
+    if let Some(previous) = &previous {
+        record_audit(
+            &mut tx,
+            actor,
+            AuditEvent {
+                action: "player.unlink",
+                target_type: "player",
+                target_id: alias_id.to_string(),
+                before: Some(serde_json::json!({ "primary_id": previous })),
+                after: None,
+            },
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(previous.is_some())
+}
+
+/// Deletes the link of `alias_id` and rebuilds the stats aggregates; returns the primary it
+/// pointed to.
+pub async fn apply_unlink(
+    conn: &mut sqlx::AnyConnection,
+    alias_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let previous = current_primary(conn, alias_id).await?;
+    sqlx::query("DELETE FROM player_aliases WHERE alias_id = $1")
+        .bind(alias_id)
+        .execute(&mut *conn)
+        .await?;
+    if previous.is_some() {
+        recompute_aggregates(conn).await?;
+        bump_data_version(&mut *conn).await?;
+    }
+    Ok(previous)
+}
+
+/// Ids of a pair of players, the smaller first, as kept in `alias_rejections`.
+pub fn ordered_pair<'a>(a: &'a str, b: &'a str) -> (&'a str, &'a str) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// Links a suggested pair: `alias_id` becomes an alias of `primary_id` and an earlier
+/// rejection of the pair is forgotten.
+pub async fn accept_alias_pair(
+    pool: &AnyPool,
+    alias_id: &str,
+    primary_id: &str,
+    actor: &str,
+) -> Result<(), LinkError> {
+    let mut tx = pool.begin().await?;
+    let previous = apply_link(&mut tx, alias_id, primary_id).await?;
+    apply_unreject(&mut tx, alias_id, primary_id).await?;
+    record_audit(
+        &mut tx,
+        actor,
+        AuditEvent {
+            action: "player.link",
+            target_type: "player",
+            target_id: alias_id.to_string(),
+            before: Some(serde_json::json!({ "primary_id": previous })),
+            after: Some(serde_json::json!({ "primary_id": primary_id, "suggested": true })),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Keeps a suggested pair from being suggested again; returns whether it was not rejected yet.
+pub async fn reject_alias_pair(
+    pool: &AnyPool,
+    alias_id: &str,
+    primary_id: &str,
+    actor: &str,
+) -> Result<bool, sqlx::Error> {
+    let (player_a, player_b) = ordered_pair(alias_id, primary_id);
+    let mut tx = pool.begin().await?;
+    let inserted = sqlx::query(
+        "INSERT INTO alias_rejections (player_a, player_b, rejected_at, actor)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (player_a, player_b) DO NOTHING",
+    )
+    .bind(player_a)
+    .bind(player_b)
+    .bind(Utc::now().to_rfc3339())
+    .bind(actor)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if inserted {
+        record_audit(
+            &mut tx,
+            actor,
+            AuditEvent {
+                action: "alias.reject",
+                target_type: "player",
+                target_id: alias_id.to_string(),
+                before: None,
+                after: Some(serde_json::json!({ "rejected_pair": [player_a, player_b] })),
+            },
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(inserted)
+}
+
+/// Forgets the rejection of a pair; returns whether there was one.
+pub async fn apply_unreject(
+    conn: &mut sqlx::AnyConnection,
+    player_a: &str,
+    player_b: &str,
+) -> Result<bool, sqlx::Error> {
+    let (player_a, player_b) = ordered_pair(player_a, player_b);
+    let deleted = sqlx::query("DELETE FROM alias_rejections WHERE player_a = $1 AND player_b = $2")
+        .bind(player_a)
+        .bind(player_b)
+        .execute(conn)
+        .await?
+        .rows_affected();
+    Ok(deleted > 0)
+}
+
+/// Rejected pairs, as `ordered_pair` gives them.
+pub async fn rejected_pairs(
+    pool: &AnyPool,
+) -> Result<std::collections::HashSet<(String, String)>, sqlx::Error> {
+    Ok(
+        sqlx::query("SELECT player_a, player_b FROM alias_rejections")
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.get("player_a"), row.get("player_b")))
+            .collect(),
+    )
+}
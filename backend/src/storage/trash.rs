@@ -0,0 +1,198 @@
+//! Soft deletion of games into the trash, restore and purge.
+
+use crate::storage::{
+    aggregates::{add_game_to_stats, remove_game_from_stats},
+    audit::{record_audit, AuditEvent},
+    get_nullable,
+    version::bump_data_version,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{AnyPool, Row};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+/// How often the server looks for trashed games past the retention period.
+pub const TRASH_PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+#[derive(Serialize, ToSchema)]
+pub struct TrashedGame {
+    pub id: i64,
+    pub game_id: Option<String>,
+    pub map_name: Option<String>,
+    pub score: Option<i64>,
+    pub played_at: String,
+    pub deleted_at: String,
+    /// When the automatic purge will remove the game for good (absent when disabled)
+    pub purge_after: Option<String>,
+}
+
+/// Moves a game to the trash; returns false if it does not exist or is already trashed.
+pub async fn trash_game(pool: &AnyPool, id: i64, actor: &str) -> Result<bool, sqlx::Error> {
+    let deleted_at = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+
+    let result =
+        sqlx::query("UPDATE games SET deleted_at = $1 WHERE id = $2 AND deleted_at IS NULL")
+            .bind(&deleted_at)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    remove_game_from_stats(&mut tx, id).await?;
+    bump_data_version(&mut *tx).await?;
+    record_audit(
+        &mut tx,
+        actor,
+        AuditEvent {
+            action: "game.delete",
+            target_type: "game",
+            target_id: id.to_string(),
+            before: None,
+            after: Some(serde_json::json!({ "deleted_at": deleted_at })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Outcome of taking a game out of the trash.
+#[derive(Debug, PartialEq)]
+pub enum Untrash {
+    Restored,
+    NotInTrash,
+    /// The game was submitted again since it went to the trash: only one copy can be live
+    StoredAgain,
+}
+
+/// Clears `deleted_at` inside an open transaction and counts the game again.
+pub async fn untrash_game(conn: &mut sqlx::AnyConnection, id: i64) -> Result<Untrash, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE games SET deleted_at = NULL
+         WHERE id = $1 AND deleted_at IS NOT NULL
+           AND NOT EXISTS (SELECT 1 FROM games live
+                           WHERE live.game_id = games.game_id AND live.deleted_at IS NULL)",
+    )
+    .bind(id)
+    .execute(&mut *conn)
+    .await?;
+    if result.rows_affected() == 0 {
+        let trashed = sqlx::query("SELECT 1 FROM games WHERE id = $1 AND deleted_at IS NOT NULL")
+            .bind(id)
+            .fetch_optional(&mut *conn)
+            .await?;
+        return Ok(if trashed.is_some() {
+            Untrash::StoredAgain
+        } else {
+            Untrash::NotInTrash
+        });
+    }
+    add_game_to_stats(conn, id).await?;
+    bump_data_version(&mut *conn).await?;
+    Ok(Untrash::Restored)
+}
+
+pub async fn restore_trashed_game(
+    pool: &AnyPool,
+    id: i64,
+    actor: &str,
+) -> Result<Untrash, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let outcome = untrash_game(&mut tx, id).await?;
+    if outcome != Untrash::Restored {
+        return Ok(outcome);
+    }
+    record_audit(
+        &mut tx,
+        actor,
+        AuditEvent {
+            action: "game.restore",
+            target_type: "game",
+            target_id: id.to_string(),
+            before: None,
+            after: None,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(Untrash::Restored)
+}
+
+/// Permanently deletes a trashed game. The audit entry only keeps its identifiers: the raw
+/// payload is gone for good.
+pub async fn purge_game(pool: &AnyPool, id: i64, actor: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let Some(row) = sqlx::query(
+        "SELECT game_id, deleted_at FROM games WHERE id = $1 AND deleted_at IS NOT NULL",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(false);
+    };
+    let game_id = get_nullable::<String>(&row, "game_id")?;
+    let deleted_at: String = row.try_get("deleted_at")?;
+
+    sqlx::query("DELETE FROM games WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    record_audit(
+        &mut tx,
+        actor,
+        AuditEvent {
+            action: "game.purge",
+            target_type: "game",
+            target_id: id.to_string(),
+            before: Some(serde_json::json!({ "game_id": game_id, "deleted_at": deleted_at })),
+            after: None,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Purges the games trashed more than `retention_days` ago; returns how many were removed.
+pub async fn purge_expired_games(
+    pool: &AnyPool,
+    retention_days: u32,
+) -> Result<usize, sqlx::Error> {
+    let cutoff = Utc::now() - chrono::Duration::days(i64::from(retention_days));
+    let rows = sqlx::query("SELECT id, deleted_at FROM games WHERE deleted_at IS NOT NULL")
+        .fetch_all(pool)
+        .await?;
+
+    let mut purged = 0;
+    for row in rows {
+        let deleted_at: String = row.try_get("deleted_at")?;
+        let expired = DateTime::parse_from_rfc3339(&deleted_at)
+            .map(|d| d < cutoff)
+            .unwrap_or(false);
+        if expired && purge_game(pool, row.try_get("id")?, "system").await? {
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+/// Background task of `serve`: empties the expired part of the trash every hour.
+pub async fn run_trash_purge(pool: AnyPool, retention_days: u32) {
+    let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match purge_expired_games(&pool, retention_days).await {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} games from the trash", purged),
+            Err(e) => error!("Failed to purge the trash: {}", e),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! Version of the stored data, bumped by every write the read endpoints can see.
+//!
+//! It lives in the database so the replicas and the command line share it; `cache` derives the
+//! ETag and Last-Modified of the read endpoints from it.
+
+use chrono::{DateTime, Utc};
+use sqlx::{Any, AnyPool, Executor, Row};
+
+pub struct DataVersion {
+    pub version: i64,
+    pub modified_at: DateTime<Utc>,
+}
+
+pub async fn data_version(pool: &AnyPool) -> Result<DataVersion, sqlx::Error> {
+    let row = sqlx::query("SELECT version, modified_at FROM data_version WHERE id = 1")
+        .fetch_one(pool)
+        .await?;
+    Ok(DataVersion {
+        version: row.get("version"),
+        modified_at: DateTime::from_timestamp(row.get("modified_at"), 0).unwrap_or_default(),
+    })
+}
+
+/// Marks the data as changed; called in the transaction of the write when there is one.
+pub async fn bump_data_version<'c, E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Executor<'c, Database = Any>,
+{
+    sqlx::query("UPDATE data_version SET version = version + 1, modified_at = $1 WHERE id = 1")
+        .bind(Utc::now().timestamp())
+        .execute(executor)
+        .await?;
+    Ok(())
+}
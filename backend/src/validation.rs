@@ -0,0 +1,212 @@
+//! Size limits and sanity checks applied to submitted payloads.
+
+use crate::{
+    config::LimitsConfig,
+    model::{payload_game_id, BullseyePayload, Score},
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Checks the sizes of the embedded arrays against the configured limits.
+pub fn check_payload_limits(
+    payload: &BullseyePayload,
+    limits: &LimitsConfig,
+) -> Result<(), String> {
+    let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) else {
+        return Ok(());
+    };
+
+    let rounds = state.rounds.as_ref().map_or(0, Vec::len);
+    if rounds > limits.max_rounds {
+        return Err(format!(
+            "{} rounds, at most {} are accepted",
+            rounds, limits.max_rounds
+        ));
+    }
+
+    let players = state.players.as_deref().unwrap_or_default();
+    if players.len() > limits.max_players {
+        return Err(format!(
+            "{} players, at most {} are accepted",
+            players.len(),
+            limits.max_players
+        ));
+    }
+
+    if let Some(guesses) = players
+        .iter()
+        .map(|p| p.guesses.as_ref().map_or(0, Vec::len))
+        .find(|&g| g > limits.max_guesses_per_player)
+    {
+        return Err(format!(
+            "{} guesses for one player, at most {} are accepted",
+            guesses, limits.max_guesses_per_player
+        ));
+    }
+
+    Ok(())
+}
+
+/// Highest score a single round (or guess) can earn.
+pub const MAX_ROUND_POINTS: i32 = 5000;
+
+/// One problem found in a submitted payload.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct ValidationProblem {
+    /// Location in the payload, e.g. `bullseye.state.rounds[2].score.points`
+    pub path: String,
+    /// `required`, `empty`, `out_of_range`, `sequence` or `mismatch`
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Body of a 422 response.
+#[derive(Serialize, ToSchema)]
+pub struct ValidationErrors {
+    pub problems: Vec<ValidationProblem>,
+}
+
+#[derive(Default)]
+pub struct Problems(Vec<ValidationProblem>);
+
+impl Problems {
+    pub fn push(
+        &mut self,
+        path: impl Into<String>,
+        code: &'static str,
+        message: impl Into<String>,
+    ) {
+        self.0.push(ValidationProblem {
+            path: path.into(),
+            code,
+            message: message.into(),
+        });
+    }
+
+    pub fn check_lat_lng(&mut self, path: &str, lat: Option<f64>, lng: Option<f64>) {
+        if let Some(lat) = lat.filter(|l| !(-90.0..=90.0).contains(l)) {
+            self.push(
+                format!("{}.lat", path),
+                "out_of_range",
+                format!("latitude {} is outside [-90, 90]", lat),
+            );
+        }
+        if let Some(lng) = lng.filter(|l| !(-180.0..=180.0).contains(l)) {
+            self.push(
+                format!("{}.lng", path),
+                "out_of_range",
+                format!("longitude {} is outside [-180, 180]", lng),
+            );
+        }
+    }
+
+    pub fn check_points(&mut self, path: &str, score: Option<&Score>) {
+        if let Some(points) = score
+            .and_then(|s| s.points)
+            .filter(|p| !(0..=MAX_ROUND_POINTS).contains(p))
+        {
+            self.push(
+                format!("{}.points", path),
+                "out_of_range",
+                format!("{} points is outside [0, {}]", points, MAX_ROUND_POINTS),
+            );
+        }
+    }
+}
+
+/// Checks a submitted game beyond what deserialization guarantees.
+pub fn validate_payload(payload: &BullseyePayload) -> Vec<ValidationProblem> {
+    let mut problems = Problems::default();
+
+    if payload_game_id(payload).is_none() {
+        problems.push("gameId", "required", "gameId is required");
+    }
+
+    let Some(state) = payload.bullseye.as_ref().and_then(|b| b.state.as_ref()) else {
+        problems.push("bullseye.state", "required", "the game state is required");
+        return problems.0;
+    };
+
+    let rounds = state.rounds.as_deref().unwrap_or_default();
+    if rounds.is_empty() {
+        problems.push(
+            "bullseye.state.rounds",
+            "empty",
+            "at least one round is required",
+        );
+    }
+
+    let round_count = state.options.as_ref().and_then(|o| o.round_count);
+    if let Some(count) = round_count {
+        if count < 1 {
+            problems.push(
+                "bullseye.state.options.roundCount",
+                "out_of_range",
+                format!("roundCount {} must be at least 1", count),
+            );
+        } else if rounds.len() > count as usize {
+            problems.push(
+                "bullseye.state.rounds",
+                "mismatch",
+                format!("{} rounds for a roundCount of {}", rounds.len(), count),
+            );
+        }
+    }
+
+    for (i, round) in rounds.iter().enumerate() {
+        let path = format!("bullseye.state.rounds[{}]", i);
+        match round.round_number {
+            None => problems.push(
+                format!("{}.roundNumber", path),
+                "required",
+                "roundNumber is required",
+            ),
+            Some(number) if number != i as i32 + 1 => problems.push(
+                format!("{}.roundNumber", path),
+                "sequence",
+                format!("expected round {}, got {}", i + 1, number),
+            ),
+            Some(_) => {}
+        }
+        if let Some(panorama) = &round.panorama {
+            problems.check_lat_lng(&format!("{}.panorama", path), panorama.lat, panorama.lng);
+        }
+        problems.check_points(&format!("{}.score", path), round.score.as_ref());
+    }
+
+    let players = state.players.as_deref().unwrap_or_default();
+    if players.is_empty() {
+        problems.push(
+            "bullseye.state.players",
+            "empty",
+            "at least one player is required",
+        );
+    }
+
+    for (i, player) in players.iter().enumerate() {
+        let path = format!("bullseye.state.players[{}]", i);
+        if player.player_id.as_deref().is_none_or(str::is_empty) {
+            problems.push(
+                format!("{}.playerId", path),
+                "required",
+                "playerId is required",
+            );
+        }
+        for (j, guess) in player.guesses.iter().flatten().enumerate() {
+            let guess_path = format!("{}.guesses[{}]", path, j);
+            if let Some(number) = guess.round_number {
+                if number < 1 || number as usize > rounds.len() {
+                    problems.push(
+                        format!("{}.roundNumber", guess_path),
+                        "mismatch",
+                        format!("guess for round {} which is not in the game", number),
+                    );
+                }
+            }
+            problems.check_lat_lng(&guess_path, guess.lat, guess.lng);
+            problems.check_points(&format!("{}.score", guess_path), guess.score.as_ref());
+        }
+    }
+
+    problems.0
+}
@@ -0,0 +1,1780 @@
+//! In-process tests: the router runs against a temporary SQLite database, or the Postgres
+//! database of `DATABASE_URL` when it points to one, and replays the recorded payloads of
+//! `extension/data_debug`.
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::Router;
+use bullseye_tracker_backend::config::{Config, DatabaseConfig, DisplayNamePolicy};
+use bullseye_tracker_backend::routes::{build_router, AppState};
+use bullseye_tracker_backend::stats::{
+    compute_team_leaderboard, jsonb, overall_stats, scan_overall_stats, scan_team_leaderboard,
+    SeasonWindow, StatsQuery,
+};
+use bullseye_tracker_backend::storage::{
+    aggregates::AGGREGATE_TABLES,
+    backend::{storage, storage_for_url},
+    backup::BACKUP_TABLES,
+    connect_database,
+};
+use serde_json::{json, Value};
+use sqlx::{migrate::Migrator, AnyPool};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+/// Game of every `extension/data_debug` capture.
+const FIXTURE_GAME_ID: &str = "b67d1e69-e2f6-4dce-ad87-b852e9106cbd";
+const HOST: &str = "5d5afe4db4ec171bd432ac55";
+const GUEST: &str = "692e0515735fa457d1fef2c5";
+
+/// Tests share the Postgres database, so they run one at a time there.
+static POSTGRES_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+struct TestApp {
+    router: Router,
+    pool: AnyPool,
+    _dir: Option<tempfile::TempDir>,
+    _lock: Option<tokio::sync::MutexGuard<'static, ()>>,
+}
+
+impl TestApp {
+    async fn new() -> TestApp {
+        TestApp::with_config(Config::default()).await
+    }
+
+    /// App with `config`, its database URL replaced by the test database.
+    async fn with_config(mut config: Config) -> TestApp {
+        sqlx::any::install_default_drivers();
+
+        let (dir, lock) = match std::env::var("DATABASE_URL") {
+            Ok(url) if url.starts_with("postgres") => {
+                config.database.url = url;
+                (None, Some(POSTGRES_LOCK.lock().await))
+            }
+            _ => {
+                let dir = tempfile::tempdir().expect("temporary directory");
+                config.database.url = format!(
+                    "sqlite://{}?mode=rwc",
+                    dir.path().join("bullseye.db").display()
+                );
+                (Some(dir), None)
+            }
+        };
+
+        let pool = connect_database(&config.database)
+            .await
+            .expect("test database");
+        if lock.is_some() {
+            let tables = BACKUP_TABLES
+                .iter()
+                .map(|t| t.name)
+                .chain(AGGREGATE_TABLES)
+                .collect::<Vec<_>>()
+                .join(", ");
+            sqlx::query(&format!("TRUNCATE {} RESTART IDENTITY CASCADE", tables))
+                .execute(&pool)
+                .await
+                .expect("empty the test database");
+        }
+
+        let router = build_router(AppState {
+            pool: pool.clone(),
+            config: Arc::new(config),
+        });
+        TestApp {
+            router,
+            pool,
+            _dir: dir,
+            _lock: lock,
+        }
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<String>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.map(Body::from).unwrap_or_else(Body::empty))
+            .unwrap();
+        let response = self.router.clone().oneshot(request).await.unwrap();
+
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (status, value)
+    }
+
+    async fn get(&self, uri: &str) -> Value {
+        let (status, value) = self.request(Method::GET, uri, None).await;
+        assert_eq!(status, StatusCode::OK, "GET {}", uri);
+        value
+    }
+
+    /// GET of a body that is not JSON; returns the status, the content type and the text.
+    async fn get_text(&self, uri: &str) -> (StatusCode, String, String) {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            content_type,
+            String::from_utf8(bytes.to_vec()).unwrap(),
+        )
+    }
+
+    /// GET with `If-None-Match`; returns the status and the ETag of the response.
+    async fn get_if_none_match(&self, uri: &str, etag: &str) -> (StatusCode, String) {
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::IF_NONE_MATCH, etag)
+            .body(Body::empty())
+            .unwrap();
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        (response.status(), etag)
+    }
+
+    async fn submit(&self, payload: &str) -> (StatusCode, Value) {
+        self.request(Method::POST, "/api/submit-game", Some(payload.to_string()))
+            .await
+    }
+
+    /// Submits the last round of the recorded game under another game id.
+    async fn submit_finished_game(&self, game_id: &str) -> i64 {
+        let (status, _) = self
+            .submit(&with_game_id(
+                &fixture("ws_data_round_ended_last.json"),
+                game_id,
+            ))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        sqlx::query_scalar("SELECT id FROM games WHERE game_id = $1 AND deleted_at IS NULL")
+            .bind(game_id)
+            .fetch_one(&self.pool)
+            .await
+            .unwrap()
+    }
+}
+
+/// Reads a recorded payload, dropping the `//` comment lines the captures are annotated with.
+fn fixture(name: &str) -> String {
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let path = if name == "payload.json" {
+        root.join(name)
+    } else {
+        root.join("../extension/data_debug").join(name)
+    };
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn with_game_id(payload: &str, game_id: &str) -> String {
+    payload.replace(FIXTURE_GAME_ID, game_id)
+}
+
+/// Wraps a game state returned by the Geoguessr API the way the extension submits it.
+fn state_payload(name: &str) -> String {
+    let state: Value = serde_json::from_str(&fixture(name)).unwrap();
+    json!({
+        "code": "BullseyeRoundEnded",
+        "gameId": state["gameId"],
+        "timestamp": "2025-12-01T21:39:32Z",
+        "bullseye": { "state": state },
+    })
+    .to_string()
+}
+
+#[tokio::test]
+async fn replays_every_recorded_payload() {
+    // Captures that carry a game state are stored; the others are rejected as incomplete
+    let cases = [
+        ("payload.json", StatusCode::UNPROCESSABLE_ENTITY),
+        ("get_game_api.json", StatusCode::UNPROCESSABLE_ENTITY),
+        ("get_lobby.json", StatusCode::UNPROCESSABLE_ENTITY),
+        (
+            "ws_data_round_abborted.json",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        ("ws_data_guess.json", StatusCode::OK),
+        ("ws_data_round_started.json", StatusCode::OK),
+        ("ws_data_round_ended.json", StatusCode::OK),
+        ("ws_data_round_ended_last.json", StatusCode::OK),
+    ];
+
+    for (name, expected) in cases {
+        let app = TestApp::new().await;
+        let (status, body) = app.submit(&fixture(name)).await;
+        assert_eq!(status, expected, "{}: {}", name, body);
+        if expected == StatusCode::UNPROCESSABLE_ENTITY {
+            assert!(!body["problems"].as_array().unwrap().is_empty(), "{}", name);
+        }
+    }
+}
+
+#[tokio::test]
+async fn api_game_states_are_accepted() {
+    for name in [
+        "get_game_api.json",
+        "get_game_durring_a_game.json",
+        "get_game_api_ended_party.json.json",
+    ] {
+        let app = TestApp::new().await;
+        let (status, body) = app.submit(&state_payload(name)).await;
+        assert_eq!(status, StatusCode::OK, "{}: {}", name, body);
+    }
+}
+
+#[tokio::test]
+async fn finished_game_is_summarized() {
+    let app = TestApp::new().await;
+    let (status, _) = app.submit(&fixture("ws_data_round_ended_last.json")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let games = app.get("/api/games").await;
+    assert_eq!(games.as_array().unwrap().len(), 1);
+    let game = &games[0];
+    assert_eq!(game["game_id"], FIXTURE_GAME_ID);
+    assert_eq!(game["map_name"], "500 000 lieux en France métropolitaine !");
+    assert_eq!(game["score"], 2500);
+    assert_eq!(game["round_count"], 5);
+    assert_eq!(game["max_score"], 25000);
+    assert_eq!(game["is_finished"], true);
+    assert_eq!(game["game_mode"], "Moving");
+    assert_eq!(game["played_at"], "2025-12-01T21:19:36.6217492+00:00");
+    assert_eq!(game["country_codes"], json!(["fr", "fr", "fr", "fr", "fr"]));
+    let players: Vec<&str> = game["players"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(players, [HOST, GUEST]);
+}
+
+#[tokio::test]
+async fn lists_are_compressed_on_request() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    app.submit_finished_game("game-2").await;
+
+    for (uri, encoding) in [
+        ("/api/games".to_string(), "gzip"),
+        (format!("/api/players/{}/stats", HOST), "gzip"),
+        (format!("/api/players/{}/stats", HOST), "br"),
+    ] {
+        let request = Request::builder()
+            .uri(&uri)
+            .header(header::ACCEPT_ENCODING, encoding)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], encoding);
+
+        if encoding == "gzip" {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let mut json = String::new();
+            std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&bytes[..]), &mut json)
+                .unwrap();
+            let value: Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(value, app.get(&uri).await);
+        }
+    }
+    assert_eq!(app.get("/api/games").await.as_array().unwrap().len(), 2);
+    let host = app.get(&format!("/api/players/{}/stats", HOST)).await;
+    assert_eq!(host["games"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn later_payloads_of_a_stored_game_are_skipped() {
+    let app = TestApp::new().await;
+    for name in ["ws_data_round_ended.json", "ws_data_round_ended_last.json"] {
+        let (status, _) = app.submit(&fixture(name)).await;
+        assert_eq!(status, StatusCode::OK, "{}", name);
+    }
+
+    let games = app.get("/api/games").await;
+    assert_eq!(games.as_array().unwrap().len(), 1);
+    assert_eq!(games[0]["round_count"], 1);
+}
+
+#[tokio::test]
+async fn stats_add_up_the_games() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    app.submit_finished_game("game-2").await;
+
+    let stats = app.get("/api/stats").await;
+    assert_eq!(stats["total_games"], 2);
+    assert_eq!(stats["average_score"], 2500.0);
+    assert_eq!(
+        stats["best_country_guesses"],
+        json!([{ "country_code": "fr", "total_score": 5000, "count": 10, "average": 500.0 }])
+    );
+}
+
+#[tokio::test]
+async fn abandoned_games_can_be_left_out() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("finished").await;
+    let (status, _) = app
+        .submit(&with_game_id(
+            &fixture("ws_data_round_ended.json"),
+            "ongoing",
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert_eq!(app.get("/api/stats").await["total_games"], 2);
+    assert_eq!(
+        app.get("/api/stats?exclude_abandons=true").await["total_games"],
+        1
+    );
+}
+
+#[tokio::test]
+async fn team_leaderboard_groups_the_players() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    app.submit_finished_game("game-2").await;
+
+    let teams = app.get("/api/leaderboard/teams").await;
+    assert_eq!(teams.as_array().unwrap().len(), 1);
+    let team = &teams[0];
+    assert_eq!(team["games_played"], 2);
+    assert_eq!(team["total_score"], 5000);
+    assert_eq!(team["average_score"], 2500.0);
+    let members: Vec<&str> = team["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(members, [HOST, GUEST]);
+}
+
+/// On Postgres the stats are computed in SQL; they must agree with the scan of the payloads.
+#[tokio::test]
+async fn stats_match_the_payload_scan() {
+    let app = TestApp::new().await;
+    if !storage(&app.pool).supports_jsonb() {
+        return;
+    }
+    app.submit_finished_game("game-1").await;
+    app.submit_finished_game("game-2").await;
+    let (status, _) = app
+        .submit(&with_game_id(
+            &fixture("ws_data_round_ended.json"),
+            "ongoing",
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Games are dated by the start of their first round, 2025-12-01T21:19:36Z
+    let window = |start: &str, end: &str| SeasonWindow {
+        start: start.parse().unwrap(),
+        end: end.parse().unwrap(),
+    };
+    for (exclude_abandons, map, window) in [
+        (None, None, None),
+        (Some(true), None, None),
+        (None, Some("nothing like it"), None),
+        (None, Some("FRANCE"), None),
+        (
+            None,
+            None,
+            Some(window("2025-12-01T00:00:00Z", "2025-12-02T00:00:00Z")),
+        ),
+        (
+            Some(true),
+            None,
+            Some(window("2025-12-01T21:20:00Z", "2025-12-02T00:00:00Z")),
+        ),
+    ] {
+        let params = StatsQuery {
+            exclude_abandons,
+            map: map.map(str::to_string),
+            score_type: None,
+            season: None,
+            include_excluded: None,
+        };
+        let stats = jsonb::overall_stats(&app.pool, &params, window.as_ref())
+            .await
+            .unwrap();
+        let scanned = scan_overall_stats(&app.pool, &params, window).await;
+        assert_eq!(json!(stats), json!(scanned));
+
+        let teams = jsonb::team_leaderboard(&app.pool, &params, window.as_ref())
+            .await
+            .unwrap();
+        let scanned = scan_team_leaderboard(&app.pool, &params, window).await;
+        assert_eq!(json!(teams), json!(scanned));
+    }
+}
+
+#[tokio::test]
+async fn player_stats_use_personal_scores() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+
+    let host = app.get(&format!("/api/players/{}/stats", HOST)).await;
+    assert_eq!(host["player_id"], HOST);
+    assert_eq!(host["total_games"], 1);
+    assert_eq!(host["average_score"], 1500.0);
+    assert_eq!(host["games"][0]["score"], 1500);
+    assert_eq!(host["score_history"][0]["score"], 1500);
+
+    let guest = app.get(&format!("/api/players/{}/stats", GUEST)).await;
+    assert_eq!(guest["total_games"], 1);
+    assert_eq!(guest["average_score"], 1000.0);
+}
+
+#[tokio::test]
+async fn team_stats_use_game_scores() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+
+    let team = app
+        .get(&format!("/api/teams/{},{}/stats", GUEST, HOST))
+        .await;
+    assert_eq!(team["total_games"], 1);
+    assert_eq!(team["average_score"], 2500.0);
+    assert_eq!(team["games"][0]["score"], 2500);
+}
+
+#[tokio::test]
+async fn excluded_games_only_count_on_request() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    let id = app.submit_finished_game("game-2").await;
+
+    let (status, annotations) = app
+        .request(
+            Method::PATCH,
+            &format!("/api/games/{}", id),
+            Some(r#"{"excluded": true, "excluded_reason": "test game", "tags": ["Test"]}"#.into()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(annotations["tags"], json!(["test"]));
+
+    assert_eq!(app.get("/api/stats").await["total_games"], 1);
+    assert_eq!(
+        app.get("/api/stats?include_excluded=true").await["total_games"],
+        2
+    );
+    assert_eq!(
+        app.get("/api/leaderboard/teams").await[0]["games_played"],
+        1
+    );
+    let host = format!("/api/players/{}/stats", HOST);
+    assert_eq!(app.get(&host).await["total_games"], 1);
+
+    // Excluded games stay listed, and can be found by tag
+    assert_eq!(app.get("/api/games").await.as_array().unwrap().len(), 2);
+    let tagged = app.get("/api/games?tags=test").await;
+    assert_eq!(tagged.as_array().unwrap().len(), 1);
+    assert_eq!(tagged[0]["id"], id);
+}
+
+#[tokio::test]
+async fn annotations_can_be_cleared_and_set_again() {
+    let app = TestApp::new().await;
+    let id = app.submit_finished_game("game-1").await;
+    let uri = format!("/api/games/{}", id);
+
+    // Postgres used to reject a value bound where a NULL had been bound before
+    for (notes, expected) in [
+        ("", json!(null)),
+        ("first", json!("first")),
+        ("", json!(null)),
+        ("second", json!("second")),
+    ] {
+        let body = json!({ "notes": notes, "excluded_reason": notes }).to_string();
+        let (status, annotations) = app.request(Method::PATCH, &uri, Some(body)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(annotations["notes"], expected);
+    }
+}
+
+#[tokio::test]
+async fn backups_hold_rfc3339_timestamps_and_payloads() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+
+    let backup = app.get("/api/admin/backup").await;
+    let game = &backup["tables"]["games"][0];
+    let played_at = game["played_at"].as_str().unwrap();
+    assert!(
+        chrono::DateTime::parse_from_rfc3339(played_at).is_ok(),
+        "{}",
+        played_at
+    );
+    let data: Value = serde_json::from_str(game["data"].as_str().unwrap()).unwrap();
+    assert_eq!(data["bullseye"]["state"]["gameId"], "game-1");
+}
+
+#[tokio::test]
+async fn trashed_games_are_hidden_until_restored() {
+    let app = TestApp::new().await;
+    let id = app.submit_finished_game("game-1").await;
+
+    let (status, _) = app
+        .request(Method::DELETE, &format!("/api/games/{}", id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.get("/api/games").await, json!([]));
+    assert_eq!(app.get("/api/stats").await["total_games"], 0);
+    assert_eq!(app.get("/api/admin/trash").await[0]["id"], id);
+
+    let (status, _) = app
+        .request(
+            Method::POST,
+            &format!("/api/admin/trash/{}/restore", id),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.get("/api/stats").await["total_games"], 1);
+}
+
+#[tokio::test]
+async fn read_endpoints_answer_conditional_requests() {
+    let app = TestApp::new().await;
+    let id = app.submit_finished_game("game-1").await;
+
+    let (status, etag) = app.get_if_none_match("/api/stats", "\"none\"").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, same) = app.get_if_none_match("/api/stats", &etag).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(same, etag);
+    // Served from the cache this time
+    assert_eq!(app.get("/api/stats").await["total_games"], 1);
+
+    // Writes change the ETag and drop the cached responses
+    app.submit_finished_game("game-2").await;
+    let (status, after_submit) = app.get_if_none_match("/api/stats", &etag).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(after_submit, etag);
+    assert_eq!(app.get("/api/stats").await["total_games"], 2);
+
+    let (status, _) = app
+        .request(Method::DELETE, &format!("/api/games/{}", id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get_if_none_match("/api/stats", &after_submit).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.get("/api/stats").await["total_games"], 1);
+}
+
+/// Stats without a season come from the aggregate tables; they must match a scan of the games.
+async fn assert_aggregates_match_scan(app: &TestApp) {
+    for (exclude_abandons, include_excluded, map) in [
+        (None, None, None),
+        (Some(true), None, None),
+        (None, Some(true), None),
+        (None, None, Some("FRANCE")),
+        (None, None, Some("nothing like it")),
+    ] {
+        let params = StatsQuery {
+            exclude_abandons,
+            map: map.map(str::to_string),
+            score_type: None,
+            season: None,
+            include_excluded,
+        };
+        let stats = overall_stats(&app.pool, &params).await.unwrap();
+        let scanned = scan_overall_stats(&app.pool, &params, None).await;
+        assert_eq!(json!(stats), json!(scanned));
+
+        let teams = compute_team_leaderboard(&app.pool, &params, None)
+            .await
+            .unwrap();
+        let scanned = scan_team_leaderboard(&app.pool, &params, None).await;
+        assert_eq!(json!(teams), json!(scanned));
+    }
+}
+
+#[tokio::test]
+async fn aggregates_follow_the_games() {
+    let app = TestApp::new().await;
+    let first = app.submit_finished_game("game-1").await;
+    let second = app.submit_finished_game("game-2").await;
+    let (status, _) = app
+        .submit(&with_game_id(
+            &fixture("ws_data_round_ended.json"),
+            "ongoing",
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_aggregates_match_scan(&app).await;
+
+    let (status, _) = app
+        .request(
+            Method::PATCH,
+            &format!("/api/games/{}", second),
+            Some(r#"{"excluded": true}"#.into()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_aggregates_match_scan(&app).await;
+
+    let (status, _) = app
+        .request(Method::DELETE, &format!("/api/games/{}", first), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_aggregates_match_scan(&app).await;
+    let (status, _) = app
+        .request(
+            Method::POST,
+            &format!("/api/admin/trash/{}/restore", first),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_aggregates_match_scan(&app).await;
+
+    // The recorded players have no nicks, so the directory is filled by hand
+    for (id, name) in [(HOST, "Host"), (GUEST, "Guest")] {
+        sqlx::query("INSERT INTO players (id, name) VALUES ($1, $2)")
+            .bind(id)
+            .bind(name)
+            .execute(&app.pool)
+            .await
+            .unwrap();
+    }
+
+    // Linking the guest to the host makes a solo team of the host
+    let link = json!({ "alias_id": GUEST, "primary_id": HOST }).to_string();
+    let (status, _) = app
+        .request(Method::POST, "/api/admin/link", Some(link))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_aggregates_match_scan(&app).await;
+    let teams = app.get("/api/leaderboard/teams").await;
+    assert_eq!(
+        teams[0]["members"],
+        json!([{ "id": HOST, "name": teams[0]["members"][0]["name"] }])
+    );
+    let host = app.get(&format!("/api/players/{}/stats", HOST)).await;
+    assert_eq!(host["total_games"], 2);
+
+    let unlink = json!({ "alias_id": GUEST }).to_string();
+    let (status, _) = app
+        .request(Method::POST, "/api/admin/unlink", Some(unlink))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_aggregates_match_scan(&app).await;
+
+    let (status, report) = app
+        .request(Method::POST, "/api/admin/stats/recompute", None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["games"], 3);
+    assert_aggregates_match_scan(&app).await;
+}
+
+#[tokio::test]
+async fn invalid_scores_are_rejected() {
+    let app = TestApp::new().await;
+    let payload = fixture("ws_data_round_ended_last.json").replacen(
+        r#""points": 1000"#,
+        r#""points": 6000"#,
+        1,
+    );
+    assert_ne!(payload, fixture("ws_data_round_ended_last.json"));
+
+    let (status, body) = app.submit(&payload).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["problems"][0]["code"], "out_of_range");
+    assert_eq!(app.get("/api/games").await, json!([]));
+}
+
+#[tokio::test]
+async fn submissions_are_stored_atomically() {
+    let app = TestApp::new().await;
+    let pool = app.pool.clone();
+    let count = |table: &'static str| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {}", table))
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        }
+    };
+
+    let (status, _) = app
+        .submit(&with_nicks("game-1", "Host", "2025-12-01"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(count("players").await, 2);
+
+    // The players cannot be written: the game and its totals are rolled back with them
+    sqlx::query("ALTER TABLE players RENAME TO players_away")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    let (status, _) = app
+        .submit(&with_nicks("game-2", "Host", "2025-12-01"))
+        .await;
+    sqlx::query("ALTER TABLE players_away RENAME TO players")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(count("games").await, 1);
+    assert_eq!(app.get("/api/stats").await["total_games"], 1);
+
+    let (status, _) = app
+        .submit(&with_nicks("game-2", "Host", "2025-12-01"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(count("games").await, 2);
+}
+
+/// The recorded finished game, played on `day` (`2025-12-01` as recorded) with the host under
+/// `host_nick` and the guest as "Guest": the recorded players have no nicks.
+fn with_nicks(game_id: &str, host_nick: &str, day: &str) -> String {
+    let payload = with_game_id(&fixture("ws_data_round_ended_last.json"), game_id)
+        .replace("\"2025-12-01T", &format!("\"{}T", day));
+    let mut payload: Value = serde_json::from_str(&payload).unwrap();
+    for player in payload["bullseye"]["state"]["players"]
+        .as_array_mut()
+        .unwrap()
+    {
+        player["nick"] = json!(if player["playerId"] == HOST {
+            host_nick
+        } else {
+            "Guest"
+        });
+    }
+    payload.to_string()
+}
+
+#[tokio::test]
+async fn nicknames_are_kept_with_their_dates() {
+    let app = TestApp::new().await;
+    let mut ids = Vec::new();
+    // Submitted out of order: the dates of the games count, not the order they arrive in
+    for (game_id, nick, day) in [
+        ("game-1", "Alpha", "2025-12-01"),
+        ("game-2", "Bravo", "2025-12-03"),
+        ("game-3", "Alpha", "2025-12-02"),
+    ] {
+        let (status, _) = app.submit(&with_nicks(game_id, nick, day)).await;
+        assert_eq!(status, StatusCode::OK);
+        ids.push(
+            sqlx::query_scalar::<_, i64>("SELECT id FROM games WHERE game_id = $1")
+                .bind(game_id)
+                .fetch_one(&app.pool)
+                .await
+                .unwrap(),
+        );
+    }
+
+    let host = app.get(&format!("/api/players/{}/names", HOST)).await;
+    assert_eq!(host["player_id"], HOST);
+    assert_eq!(host["display_name"], "Bravo");
+    assert_eq!(host["pinned_name"], Value::Null);
+    let names = host["names"].as_array().unwrap();
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[0]["nick"], "Alpha");
+    assert_eq!(names[0]["games"], 2);
+    assert_eq!(names[0]["source_game_id"], ids[0]);
+    assert!(names[0]["first_seen"]
+        .as_str()
+        .unwrap()
+        .starts_with("2025-12-01T"));
+    assert!(names[0]["last_seen"]
+        .as_str()
+        .unwrap()
+        .starts_with("2025-12-02T"));
+    assert_eq!(names[1]["nick"], "Bravo");
+    assert_eq!(names[1]["source_game_id"], ids[1]);
+
+    // Every game shows the display name, not the nick of that game
+    for game in app.get("/api/games").await.as_array().unwrap() {
+        assert_eq!(game["players"][0], json!({ "id": HOST, "name": "Bravo" }));
+    }
+    let stats = app.get(&format!("/api/players/{}/stats", HOST)).await;
+    assert_eq!(stats["player_name"], "Bravo");
+    let teams = app.get("/api/leaderboard/teams").await;
+    assert_eq!(teams[0]["team_name"], "Bravo, Guest");
+
+    // An alias shows the timeline and the name of its primary
+    let link = json!({ "alias_id": GUEST, "primary_id": HOST }).to_string();
+    let (status, _) = app
+        .request(Method::POST, "/api/admin/link", Some(link))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let guest = app.get(&format!("/api/players/{}/names", GUEST)).await;
+    assert_eq!(guest["player_id"], HOST);
+    assert_eq!(guest["names"].as_array().unwrap().len(), 3);
+    let games = app.get("/api/games").await;
+    assert_eq!(
+        games[0]["players"][1],
+        json!({ "id": GUEST, "name": "Bravo" })
+    );
+
+    let (status, _) = app
+        .request(Method::GET, "/api/players/nobody/names", None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn display_names_follow_the_policy() {
+    let mut config = Config::default();
+    config.players.display_name = DisplayNamePolicy::MostFrequent;
+    let app = TestApp::with_config(config).await;
+    for (game_id, nick, day) in [
+        ("game-1", "Alpha", "2025-12-01"),
+        ("game-2", "Alpha", "2025-12-02"),
+        ("game-3", "Bravo", "2025-12-03"),
+    ] {
+        let (status, _) = app.submit(&with_nicks(game_id, nick, day)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let host = app.get(&format!("/api/players/{}/names", HOST)).await;
+    assert_eq!(host["display_name"], "Alpha");
+    // Releases the Postgres test lock for the next app
+    drop(app);
+
+    let mut config = Config::default();
+    config.players.display_name = DisplayNamePolicy::Pinned;
+    let app = TestApp::with_config(config).await;
+    let (status, _) = app
+        .submit(&with_nicks("game-1", "Alpha", "2025-12-01"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let uri = format!("/api/admin/players/{}/name", HOST);
+    let pin = |name: Value| {
+        app.request(
+            Method::POST,
+            &uri,
+            Some(json!({ "name": name }).to_string()),
+        )
+    };
+
+    let (status, host) = pin(json!("Captain")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(host["pinned_name"], "Captain");
+    assert_eq!(host["display_name"], "Captain");
+    let games = app.get("/api/games").await;
+    assert_eq!(games[0]["players"][0]["name"], "Captain");
+    // Later nicks do not replace a pinned name
+    let (status, _) = app
+        .submit(&with_nicks("game-2", "Bravo", "2025-12-02"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let stats = app.get(&format!("/api/players/{}/stats", HOST)).await;
+    assert_eq!(stats["player_name"], "Captain");
+
+    let (status, host) = pin(Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(host["display_name"], "Bravo");
+    let (status, _) = pin(json!("  ")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let audit = app.get("/api/admin/audit?action=player.pin_name").await;
+    assert_eq!(audit.as_array().unwrap().len(), 2);
+
+    let (status, _) = app
+        .request(
+            Method::POST,
+            "/api/admin/players/nobody/name",
+            Some(json!({ "name": "Nobody" }).to_string()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn alias_suggestions_can_be_rejected_or_accepted() {
+    let app = TestApp::new().await;
+    // A second account of the host, with the same teammate but never in a game with the host
+    let alt = "a1a1a1a1a1a1a1a1a1a1a1a1";
+    for payload in [
+        with_nicks("game-1", "Pierre MAHOT", "2025-12-01"),
+        with_nicks("game-2", "Pierre MAHOT 2", "2025-12-02").replace(HOST, alt),
+    ] {
+        let (status, _) = app.submit(&payload).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let suggestions = app.get("/api/admin/aliases/suggestions").await;
+    let suggestions = suggestions.as_array().unwrap();
+    // The guest played with both, so it is never suggested
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0]["primary"]["id"], HOST);
+    assert_eq!(suggestions[0]["alias"]["id"], alt);
+    assert_eq!(suggestions[0]["shared_teammates"], 1.0);
+    assert_eq!(suggestions[0]["skill_similarity"], 1.0);
+    assert!(suggestions[0]["nick_similarity"].as_f64().unwrap() >= 0.9);
+    assert!(suggestions[0]["confidence"].as_f64().unwrap() > 0.9);
+    let none = app
+        .get("/api/admin/aliases/suggestions?min_confidence=0.99")
+        .await;
+    assert_eq!(none, json!([]));
+    for bad in ["1.5", "-0.1", "NaN"] {
+        let uri = format!("/api/admin/aliases/suggestions?min_confidence={}", bad);
+        let (status, _) = app.request(Method::GET, &uri, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", bad);
+    }
+
+    let pair = json!({ "alias_id": alt, "primary_id": HOST }).to_string();
+    let (status, _) = app
+        .request(
+            Method::POST,
+            "/api/admin/aliases/reject",
+            Some(pair.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.get("/api/admin/aliases/suggestions").await, json!([]));
+
+    // Undoing the rejection suggests the pair again
+    let audit = app.get("/api/admin/audit?action=alias.reject").await;
+    let (status, _) = app
+        .request(
+            Method::POST,
+            &format!("/api/admin/audit/{}/undo", audit[0]["id"]),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let suggestions = app.get("/api/admin/aliases/suggestions").await;
+    assert_eq!(suggestions.as_array().unwrap().len(), 1);
+
+    let (status, _) = app
+        .request(Method::POST, "/api/admin/aliases/accept", Some(pair))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.get("/api/admin/aliases/suggestions").await, json!([]));
+    let stats = app.get(&format!("/api/players/{}/stats", alt)).await;
+    assert_eq!(stats["total_games"], 2);
+
+    let same = json!({ "alias_id": HOST, "primary_id": HOST }).to_string();
+    let (status, _) = app
+        .request(Method::POST, "/api/admin/aliases/reject", Some(same))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn spoofed_forwarded_for_entries_share_a_bucket() {
+    let mut config = Config::default();
+    config.limits.trust_forwarded_for = true;
+    config.limits.submissions_per_minute_per_ip = 1;
+    config.limits.burst = 1;
+    let app = TestApp::with_config(config).await;
+
+    let submit = |forwarded_for: &'static str| {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/submit-game")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::from(fixture("ws_data_round_ended_last.json")))
+            .unwrap();
+        let router = app.router.clone();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+
+    assert_eq!(submit("198.51.100.1, 203.0.113.7").await, StatusCode::OK);
+    // Only the entry of the proxy counts, whatever the client puts before it
+    assert_eq!(
+        submit("198.51.100.2, 203.0.113.7").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(submit("203.0.113.7").await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(submit("198.51.100.1, 203.0.113.8").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sqlite_games_keep_their_indexes_after_the_id_repair() {
+    let app = TestApp::new().await;
+    if storage(&app.pool).name() != "SQLite" {
+        return;
+    }
+
+    let id_type: String =
+        sqlx::query_scalar("SELECT type FROM pragma_table_info('games') WHERE name = 'id'")
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(id_type, "INTEGER");
+    let indexes: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = 'games' AND sql IS NOT NULL",
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+    assert!(
+        indexes.contains(&"idx_games_deleted_at".to_string()),
+        "{:?}",
+        indexes
+    );
+
+    let first = app.submit_finished_game("game-1").await;
+    let second = app.submit_finished_game("game-2").await;
+    assert_eq!(second, first + 1);
+}
+
+#[tokio::test]
+async fn failed_game_lists_are_never_complete() {
+    let app = TestApp::new().await;
+    if storage(&app.pool).name() != "SQLite" {
+        return;
+    }
+    app.submit_finished_game("game-1").await;
+    let list = || {
+        let request = Request::builder()
+            .uri("/api/games")
+            .body(Body::empty())
+            .unwrap();
+        app.router.clone().oneshot(request)
+    };
+
+    // The games fail once the list started: the body breaks off instead of closing the array
+    sqlx::query("ALTER TABLE games RENAME TO games_gone")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    let response = list().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .is_err());
+
+    // Before anything is sent, it is a plain 500
+    sqlx::query("DROP TABLE game_tags")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    let response = list().await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn proxy_user_header_needs_the_proxy_secret() {
+    let mut config = Config::default();
+    config.auth.proxy_secret = Some("shared-secret".to_string());
+    let app = TestApp::with_config(config).await;
+    let (status, _) = app
+        .submit(&with_nicks("game-1", "Alpha", "2025-12-01"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let pin = |name: &str, secret: Option<&str>| {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("/api/admin/players/{}/name", HOST))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("x-webauth-user", "mallory");
+        if let Some(secret) = secret {
+            request = request.header("x-bullseye-proxy-secret", secret);
+        }
+        let body = Body::from(json!({ "name": name }).to_string());
+        app.router.clone().oneshot(request.body(body).unwrap())
+    };
+
+    for secret in [None, Some("guess")] {
+        let response = pin("Spoofed", secret).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = pin("Trusted", Some("shared-secret")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let audit = app.get("/api/admin/audit?action=player.pin_name").await;
+    let mut actors: Vec<&str> = audit
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["actor"].as_str().unwrap())
+        .collect();
+    actors.sort();
+    assert_eq!(actors, ["admin:mallory", "anonymous", "anonymous"]);
+}
+
+#[tokio::test]
+async fn concurrent_admin_actions_get_their_own_audit_ids() {
+    let app = TestApp::new().await;
+    // SQLite takes one writer at a time
+    if storage(&app.pool).name() != "PostgreSQL" {
+        return;
+    }
+    let (status, _) = app
+        .submit(&with_nicks("game-1", "Alpha", "2025-12-01"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let uri = format!("/api/admin/players/{}/name", HOST);
+
+    let results = futures_util::future::join_all((0..8).map(|i| {
+        app.request(
+            Method::POST,
+            &uri,
+            Some(json!({ "name": format!("Captain {}", i) }).to_string()),
+        )
+    }))
+    .await;
+    assert!(results.iter().all(|(status, _)| *status == StatusCode::OK));
+
+    let audit = app.get("/api/admin/audit?action=player.pin_name").await;
+    let ids: std::collections::HashSet<i64> = audit
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(ids.len(), 8);
+}
+
+#[tokio::test]
+async fn concurrent_seasons_get_their_own_ids() {
+    let app = TestApp::new().await;
+    // SQLite takes one writer at a time
+    if storage(&app.pool).name() != "PostgreSQL" {
+        return;
+    }
+
+    let results = futures_util::future::join_all((0..4).map(|i| {
+        let season = json!({
+            "name": format!("Season {}", i),
+            "starts_at": "2025-12-01T00:00:00Z",
+            "ends_at": "2026-01-01T00:00:00Z",
+        });
+        app.request(Method::POST, "/api/admin/seasons", Some(season.to_string()))
+    }))
+    .await;
+    let ids: std::collections::HashSet<i64> = results
+        .iter()
+        .map(|(status, season)| {
+            assert_eq!(*status, StatusCode::CREATED);
+            season["id"].as_i64().unwrap()
+        })
+        .collect();
+    assert_eq!(ids.len(), 4);
+}
+
+#[tokio::test]
+async fn games_can_be_submitted_again_once_trashed() {
+    let app = TestApp::new().await;
+    let first = app.submit_finished_game("game-1").await;
+    // A copy sent again is ignored while the first one is live
+    assert_eq!(app.submit_finished_game("game-1").await, first);
+    assert_eq!(app.get("/api/stats").await["total_games"], 1);
+
+    let (status, _) = app
+        .request(Method::DELETE, &format!("/api/games/{}", first), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let second = app.submit_finished_game("game-1").await;
+    assert_ne!(second, first);
+    assert_eq!(app.get("/api/stats").await["total_games"], 1);
+
+    // Only one copy can be out of the trash
+    let restore = format!("/api/admin/trash/{}/restore", first);
+    let (status, _) = app.request(Method::POST, &restore, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let audit = app.get("/api/admin/audit?action=game.delete").await;
+    let (status, _) = app
+        .request(
+            Method::POST,
+            &format!("/api/admin/audit/{}/undo", audit[0]["id"]),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = app
+        .request(Method::DELETE, &format!("/api/games/{}", second), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.request(Method::POST, &restore, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.submit_finished_game("game-1").await, first);
+}
+
+#[tokio::test]
+async fn conditional_requests_only_spare_successful_responses() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    let (_, etag) = app.get_if_none_match("/api/stats", "\"none\"").await;
+    let get = |uri: &str, name: header::HeaderName, value: &str| {
+        let request = Request::builder()
+            .uri(uri)
+            .header(name, value)
+            .body(Body::empty())
+            .unwrap();
+        app.router.clone().oneshot(request)
+    };
+
+    // The ETag of the data does not make a missing season exist
+    let response = get("/api/seasons/42/standings", header::IF_NONE_MATCH, &etag)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = get("/api/seasons/42/standings", header::IF_NONE_MATCH, "*")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Dates are too coarse to tell writes of the same second apart: only the ETag is used
+    let response = get(
+        "/api/stats",
+        header::IF_MODIFIED_SINCE,
+        "Fri, 01 Jan 2100 00:00:00 GMT",
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn seasons_are_closed_once() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    let create = || {
+        let season = json!({
+            "name": "Forever",
+            "starts_at": "2000-01-01T00:00:00Z",
+            "ends_at": "2100-01-01T00:00:00Z",
+        });
+        app.request(Method::POST, "/api/admin/seasons", Some(season.to_string()))
+    };
+    let app = &app;
+    let close = |id: &Value| {
+        let uri = format!("/api/admin/seasons/{}/close", id);
+        async move { app.request(Method::POST, &uri, None).await }
+    };
+
+    let (_, season) = create().await;
+    let (status, standings) = close(&season["id"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!standings["players"].as_array().unwrap().is_empty());
+    let (status, _) = close(&season["id"]).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // SQLite takes one writer at a time
+    if storage(&app.pool).name() == "PostgreSQL" {
+        let (_, season) = create().await;
+        let (first, second) = tokio::join!(close(&season["id"]), close(&season["id"]));
+        let mut statuses = [first.0, second.0];
+        statuses.sort();
+        assert_eq!(statuses, [StatusCode::OK, StatusCode::CONFLICT]);
+    }
+}
+
+#[tokio::test]
+async fn malformed_points_are_read_alike_by_sql_and_scan() {
+    let app = TestApp::new().await;
+    if !storage(&app.pool).supports_jsonb() {
+        return;
+    }
+    app.submit_finished_game("game-1").await;
+    app.submit_finished_game("game-2").await;
+    // As an older extension or a hand edit could have stored them
+    sqlx::query(
+        "UPDATE games SET data = jsonb_set(jsonb_set(data,
+             '{bullseye,state,rounds,0,score,points}', '\"lots\"'),
+             '{bullseye,state,rounds,2,score,points}', '999.6')
+         WHERE game_id = 'game-1'",
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    for exclude_abandons in [None, Some(true)] {
+        let params = StatsQuery {
+            exclude_abandons,
+            map: None,
+            score_type: None,
+            season: None,
+            include_excluded: None,
+        };
+        let stats = jsonb::overall_stats(&app.pool, &params, None)
+            .await
+            .unwrap();
+        let scanned = scan_overall_stats(&app.pool, &params, None).await;
+        assert_eq!(json!(stats), json!(scanned));
+        // The string counts as no points, the float is rounded to 1000
+        assert_eq!(stats.best_country_guesses[0].total_score, 1500 + 2500);
+
+        let teams = jsonb::team_leaderboard(&app.pool, &params, None)
+            .await
+            .unwrap();
+        let scanned = scan_team_leaderboard(&app.pool, &params, None).await;
+        assert_eq!(json!(teams), json!(scanned));
+    }
+}
+
+#[tokio::test]
+async fn baseline_sqlite_databases_with_duplicates_are_upgraded() {
+    if std::env::var("DATABASE_URL").is_ok_and(|url| url.starts_with("postgres")) {
+        return;
+    }
+    sqlx::any::install_default_drivers();
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!(
+        "sqlite://{}?mode=rwc",
+        dir.path().join("bullseye.db").display()
+    );
+
+    // The three migrations of the baseline, and a game it stored twice with NULL ids
+    let migrator = storage_for_url(&url).migrator();
+    let baseline = Migrator {
+        migrations: std::borrow::Cow::Owned(migrator.migrations[..3].to_vec()),
+        ..Migrator::DEFAULT
+    };
+    {
+        let pool = AnyPool::connect(&url).await.unwrap();
+        baseline.run(&pool).await.unwrap();
+        let payload = with_game_id(&fixture("ws_data_round_ended_last.json"), "game-1");
+        for _ in 0..2 {
+            sqlx::query("INSERT INTO games (game_id, map_name, score, data) VALUES ($1, 'A Diverse World', 0, $2)")
+                .bind("game-1")
+                .bind(&payload)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool.close().await;
+    }
+
+    let pool = connect_database(&DatabaseConfig {
+        url,
+        ..DatabaseConfig::default()
+    })
+    .await
+    .expect("upgraded database");
+    let copies: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT id, CASE WHEN deleted_at IS NULL THEN 0 ELSE 1 END FROM games WHERE game_id = 'game-1' ORDER BY id",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    // The later copy went to the trash, then both got their ids
+    assert_eq!(copies, [(1, 0), (2, 1)]);
+}
+
+/// Game ids of the lines of a CSV export, or of an NDJSON one, sorted.
+fn exported_game_ids(text: &str) -> Vec<String> {
+    let mut ids: Vec<String> = if text.starts_with('{') {
+        text.lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["game_id"].to_string())
+            .map(|id| id.trim_matches('"').to_string())
+            .collect()
+    } else {
+        text.lines()
+            .skip(1)
+            .map(|line| line.split(',').nth(1).unwrap().to_string())
+            .collect()
+    };
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn exports_list_the_filtered_games_and_rounds() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    let (status, _) = app
+        .submit(&with_game_id(
+            &fixture("ws_data_round_ended.json"),
+            "ongoing",
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, content_type, csv) = app.get_text("/api/export/games").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "text/csv; charset=utf-8");
+    assert!(csv.starts_with("id,game_id,played_at,map_name,"), "{}", csv);
+    assert_eq!(exported_game_ids(&csv), ["game-1", "ongoing"]);
+
+    let (_, content_type, ndjson) = app
+        .get_text("/api/export/games?format=ndjson&exclude_abandons=true")
+        .await;
+    assert_eq!(content_type, "application/x-ndjson");
+    let game: Value = serde_json::from_str(ndjson.trim_end()).unwrap();
+    assert_eq!(game["game_id"], "game-1");
+    assert_eq!(game["is_finished"], true);
+    assert_eq!(game["score"], 2500);
+    assert_eq!(game["round_count"], 5);
+    assert_eq!(game["player_ids"], format!("{};{}", HOST, GUEST));
+
+    // One line per guess: both players guessed in each round
+    let (_, _, csv) = app.get_text("/api/export/rounds").await;
+    assert_eq!(csv.lines().count(), 1 + 2 + 5 * 2);
+    let (_, _, ndjson) = app
+        .get_text("/api/export/rounds?format=ndjson&exclude_abandons=true")
+        .await;
+    let guesses: Vec<Value> = ndjson
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(guesses.len(), 5 * 2);
+    assert!(guesses.iter().all(|g| g["game_id"] == "game-1"));
+    let host_points: i64 = guesses
+        .iter()
+        .filter(|g| g["player_id"] == HOST)
+        .map(|g| g["guess_points"].as_i64().unwrap())
+        .sum();
+    assert_eq!(host_points, 1500);
+
+    let (_, _, csv) = app.get_text("/api/export/games?map=france").await;
+    assert_eq!(exported_game_ids(&csv), ["game-1", "ongoing"]);
+    let (_, _, csv) = app.get_text("/api/export/rounds?map=elsewhere").await;
+    assert_eq!(csv, "");
+
+    let ongoing: i64 = sqlx::query_scalar("SELECT id FROM games WHERE game_id = 'ongoing'")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    let (status, _) = app
+        .request(
+            Method::PATCH,
+            &format!("/api/games/{}", ongoing),
+            Some(r#"{"excluded": true}"#.into()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, _, ndjson) = app.get_text("/api/export/games?format=ndjson").await;
+    assert_eq!(exported_game_ids(&ndjson), ["game-1"]);
+    let (_, _, ndjson) = app
+        .get_text("/api/export/games?format=ndjson&include_excluded=true")
+        .await;
+    assert_eq!(exported_game_ids(&ndjson), ["game-1", "ongoing"]);
+
+    let (status, _, _) = app.get_text("/api/export/games?season=999").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn failed_exports_are_never_complete() {
+    let app = TestApp::new().await;
+    if storage(&app.pool).name() != "SQLite" {
+        return;
+    }
+    app.submit_finished_game("game-1").await;
+    sqlx::query("ALTER TABLE games RENAME TO games_gone")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    // The export only starts once the headers are sent: the body breaks off instead of ending
+    for uri in [
+        "/api/export/games",
+        "/api/export/games?format=ndjson",
+        "/api/export/rounds",
+        "/api/export/rounds?format=ndjson",
+    ] {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .is_err(),
+            "{}",
+            uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn annotations_can_be_patched_across_origins() {
+    let app = TestApp::new().await;
+    let request = Request::builder()
+        .method(Method::OPTIONS)
+        .uri("/api/games/1")
+        .header(header::ORIGIN, "https://stats.example")
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PATCH")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let methods = response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS]
+        .to_str()
+        .unwrap();
+    assert!(methods.contains("PATCH"), "{}", methods);
+}
+
+#[tokio::test]
+async fn closed_season_leaderboards_cannot_be_filtered() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    let season = json!({
+        "name": "Forever",
+        "starts_at": "2000-01-01T00:00:00Z",
+        "ends_at": "2100-01-01T00:00:00Z",
+    });
+    let (_, season) = app
+        .request(Method::POST, "/api/admin/seasons", Some(season.to_string()))
+        .await;
+    let uri = format!("/api/leaderboard/teams?season={}", season["id"]);
+
+    // Open seasons are computed, filters included
+    let teams = app.get(&format!("{}&include_excluded=true", uri)).await;
+    assert_eq!(teams[0]["games_played"], 1);
+
+    let close = format!("/api/admin/seasons/{}/close", season["id"]);
+    let (status, _) = app.request(Method::POST, &close, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.get(&uri).await[0]["games_played"], 1);
+    assert_eq!(
+        app.get(&format!("{}&exclude_abandons=false", uri)).await[0]["games_played"],
+        1
+    );
+    for filter in [
+        "map=elsewhere",
+        "exclude_abandons=true",
+        "include_excluded=true",
+    ] {
+        let (status, _) = app
+            .request(Method::GET, &format!("{}&{}", uri, filter), None)
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", filter);
+    }
+}
+
+#[tokio::test]
+async fn geojson_export_draws_guesses_to_their_answers() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    let features = |uri: String| {
+        let app = &app;
+        async move {
+            let (status, content_type, text) = app.get_text(&uri).await;
+            assert_eq!(status, StatusCode::OK, "{}", uri);
+            assert_eq!(content_type, "application/geo+json");
+            let collection: Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(collection["type"], "FeatureCollection");
+            collection["features"].as_array().unwrap().clone()
+        }
+    };
+    let kinds = |features: &[Value]| {
+        ["panorama", "guess", "guess_line"].map(|kind| {
+            features
+                .iter()
+                .filter(|f| f["properties"]["kind"] == kind)
+                .count()
+        })
+    };
+
+    // Five rounds, each with a guess and its line for both players
+    let all = features("/api/export/geojson".into()).await;
+    assert_eq!(kinds(&all), [5, 10, 10]);
+
+    // Coordinates are [lng, lat], and lines go from the guess to the panorama
+    let panorama = &all[0];
+    assert_eq!(panorama["geometry"]["type"], "Point");
+    assert_eq!(panorama["properties"]["round_number"], 1);
+    let answer = panorama["geometry"]["coordinates"].clone();
+    assert_eq!(answer, json!([5.386637210845947, 43.32366180419922]));
+    let guess = all
+        .iter()
+        .find(|f| f["properties"]["kind"] == "guess" && f["properties"]["round_number"] == 1)
+        .unwrap();
+    let line = all
+        .iter()
+        .find(|f| {
+            f["properties"]["kind"] == "guess_line"
+                && f["properties"]["player_id"] == guess["properties"]["player_id"]
+                && f["properties"]["round_number"] == 1
+        })
+        .unwrap();
+    assert_eq!(line["geometry"]["type"], "LineString");
+    assert_eq!(
+        line["geometry"]["coordinates"],
+        json!([guess["geometry"]["coordinates"], answer])
+    );
+
+    let host = features(format!("/api/export/geojson?player={}", HOST)).await;
+    assert_eq!(kinds(&host), [5, 5, 5]);
+    assert!(host
+        .iter()
+        .filter(|f| f["properties"]["kind"] != "panorama")
+        .all(|f| f["properties"]["primary_player_id"] == HOST));
+
+    assert!(features("/api/export/geojson?map=elsewhere".into())
+        .await
+        .is_empty());
+    assert_eq!(
+        features("/api/export/geojson?map=france&to=2026-01-01T00:00:00Z".into())
+            .await
+            .len(),
+        25
+    );
+    assert!(
+        features("/api/export/geojson?from=2026-01-01T00:00:00Z".into())
+            .await
+            .is_empty()
+    );
+    let (status, _) = app
+        .request(Method::GET, "/api/export/geojson?from=yesterday", None)
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn imports_report_every_item() {
+    let app = TestApp::new().await;
+    app.submit_finished_game("game-1").await;
+    let payload = |game_id: &str| -> Value {
+        serde_json::from_str(&with_game_id(
+            &fixture("ws_data_round_ended_last.json"),
+            game_id,
+        ))
+        .unwrap()
+    };
+    // As the extension stores them: the bullseye state itself
+    let stored = payload("game-2")["bullseye"]["state"].clone();
+    let items = json!([payload("game-1"), stored, stored, 42, { "status": "finished" }]);
+
+    let (status, report) = app
+        .request(Method::POST, "/api/import", Some(items.to_string()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        [&report["imported"], &report["skipped"], &report["failed"]],
+        [1, 2, 2]
+    );
+    let statuses: Vec<&str> = report["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        statuses,
+        ["skipped", "imported", "skipped", "failed", "failed"]
+    );
+    assert_eq!(report["items"][1]["game_id"], "game-2");
+    assert_eq!(report["items"][2]["reason"], "game already exists");
+    assert_eq!(report["items"][3]["reason"], "entry is not a JSON object");
+    assert_eq!(report["items"][4]["reason"], "missing gameId");
+    assert_eq!(app.get("/api/games").await.as_array().unwrap().len(), 2);
+
+    // A whole chrome.storage.local dump is accepted too, anything else is not
+    let (status, report) = app
+        .request(Method::POST, "/api/import", Some(r#"{"games": []}"#.into()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["items"], json!([]));
+    let (status, _) = app
+        .request(Method::POST, "/api/import", Some(r#"{"game": []}"#.into()))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn bodies_are_only_read_once_authorized() {
+    let mut config = Config::default();
+    config.auth.api_key = Some("secret".to_string());
+    let app = TestApp::with_config(config).await;
+    let post = |uri: &'static str, body: Vec<u8>, token: Option<&'static str>| {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = request.body(Body::from(body)).unwrap();
+        let router = app.router.clone();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+
+    let garbage = || b"not json".to_vec();
+    let huge = || vec![b' '; 64 * 1024 * 1024 + 1];
+    for uri in ["/api/import", "/api/submit-game"] {
+        assert_eq!(
+            post(uri, garbage(), None).await,
+            StatusCode::UNAUTHORIZED,
+            "{}",
+            uri
+        );
+        assert_eq!(
+            post(uri, garbage(), Some("secret")).await,
+            StatusCode::BAD_REQUEST,
+            "{}",
+            uri
+        );
+    }
+    // Past the body limit of the route, which submissions keep lower
+    for uri in ["/api/import", "/api/submit-game"] {
+        assert_eq!(
+            post(uri, huge(), None).await,
+            StatusCode::UNAUTHORIZED,
+            "{}",
+            uri
+        );
+        assert_eq!(
+            post(uri, huge(), Some("secret")).await,
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "{}",
+            uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn readiness_follows_the_database() {
+    let app = TestApp::new().await;
+    let health = app.get("/healthz").await;
+    assert_eq!(health["status"], "ok");
+
+    let ready = app.get("/readyz").await;
+    assert_eq!(ready["status"], "ready");
+    assert_eq!(ready["database"]["ok"], true);
+    assert_eq!(ready["migrations"]["pending"], json!([]));
+    assert_eq!(
+        ready["migrations"]["schema_version"],
+        ready["migrations"]["expected_version"]
+    );
+
+    // The Postgres database is shared: its migrations stay as they are
+    if storage(&app.pool).name() == "SQLite" {
+        let latest = ready["migrations"]["expected_version"].as_i64().unwrap();
+        sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+            .bind(latest)
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        let (status, ready) = app.request(Method::GET, "/readyz", None).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(ready["migrations"]["pending"], json!([latest]));
+    }
+
+    app.pool.close().await;
+    let (status, ready) = app.request(Method::GET, "/readyz", None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(ready["status"], "not_ready");
+    assert_eq!(ready["database"]["ok"], false);
+    assert_eq!(app.get("/healthz").await["status"], "ok");
+}